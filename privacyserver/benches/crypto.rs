@@ -0,0 +1,57 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use num_bigint::BigUint;
+use num_prime::nt_funcs::next_prime;
+use num_traits::One;
+
+use privacyserver::paillier::{
+    decrypt,
+    decrypt_crt,
+    encrypt,
+    homomorphic_addition,
+    PaillierKey,
+};
+
+const KEY_BITS: [usize; 3] = [1024, 2048, 3072];
+
+/// The same key on every run: the first primes above two fixed
+/// `bits/2`-bit starting points. Easy to reproduce, so only for benchmarks.
+fn fixed_key(bits: usize) -> PaillierKey {
+    let half = bits / 2;
+    // two top bits set, so p·q has exactly `bits` bits
+    let base = BigUint::from(3u32) << (half - 2);
+    let p = next_prime(&base, None).expect("a prime exists above the base");
+    let q = next_prime(&(base + (BigUint::one() << (half - 3))), None)
+        .expect("a prime exists above the base");
+    PaillierKey::from_primes(p, q).expect("fixed primes form a valid key")
+}
+
+fn bench_crypto(c: &mut Criterion) {
+    for bits in KEY_BITS {
+        let key = fixed_key(bits);
+        let m   = BigUint::from(123_456_789u64);
+        let ct  = encrypt(&key, &m);
+        let ct2 = encrypt(&key, &BigUint::from(42u32));
+
+        let mut group = c.benchmark_group(format!("paillier_{bits}"));
+        // one operation per iteration, so criterion reports ops/sec
+        group.throughput(Throughput::Elements(1));
+        group.sample_size(20);
+
+        group.bench_function(BenchmarkId::new("encrypt", bits), |b| {
+            b.iter(|| encrypt(&key, black_box(&m)))
+        });
+        group.bench_function(BenchmarkId::new("decrypt", bits), |b| {
+            b.iter(|| decrypt(&key, black_box(&ct)))
+        });
+        group.bench_function(BenchmarkId::new("decrypt_crt", bits), |b| {
+            b.iter(|| decrypt_crt(&key, black_box(&ct)))
+        });
+        group.bench_function(BenchmarkId::new("homomorphic_addition", bits), |b| {
+            b.iter(|| homomorphic_addition(black_box(&ct), black_box(&ct2)).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_crypto);
+criterion_main!(benches);
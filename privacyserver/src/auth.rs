@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument};
+
+use crate::error::ApiError;
+use crate::wallet::normalize_wallet;
+use crate::{is_admin, tenant};
+
+/// Per-wallet API keys, stored as SHA-256 hex digests and persisted as a
+/// JSON object (wallet → digest) so registrations survive restarts
+pub struct ApiKeys {
+    path:   PathBuf,
+    hashes: RwLock<HashMap<String, String>>,
+}
+
+impl ApiKeys {
+    /// Load the registered keys from `path`, if it exists
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let hashes = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(path)?)?
+        } else {
+            HashMap::new()
+        };
+        Ok(ApiKeys { path: path.to_path_buf(), hashes: RwLock::new(hashes) })
+    }
+}
+
+/// The current tenant's registered API keys; the default tenant's are
+/// loaded from `config().api_keys_path`
+fn api_keys() -> &'static ApiKeys {
+    &tenant::current().api_keys
+}
+
+/// Helper: the token in `req`'s `Authorization: Bearer <token>` header
+pub fn bearer(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Hex SHA-256 of an API key or admin token, the form it's stored in
+pub fn digest(api_key: &str) -> String {
+    Sha256::digest(api_key.as_bytes()).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Helper: `Err(InvalidApiKey)` unless `req` may move `wallet`'s funds:
+/// it carries the wallet's API key or the admin token. Wallets that
+/// never registered a key are open to any caller, as before `/register`.
+pub fn authorize(req: &HttpRequest, wallet: &str) -> Result<(), ApiError> {
+    let wallet = normalize_wallet(wallet)?;
+    permitted(req, &api_keys().hashes.read().unwrap(), &wallet)
+}
+
+/// Helper: like `authorize`, but a wallet that never registered a key is
+/// closed rather than open: only the admin token reads it. For endpoints
+/// that reveal something about a balance, like `/check-threshold`.
+pub fn authorize_owner(req: &HttpRequest, wallet: &str) -> Result<(), ApiError> {
+    let wallet = normalize_wallet(wallet)?;
+    let owner = match api_keys().hashes.read().unwrap().get(&wallet) {
+        Some(expected) => holds_key(req, expected),
+        None           => false,
+    };
+    if owner || is_admin(req) { Ok(()) } else { Err(ApiError::InvalidApiKey) }
+}
+
+/// Helper: do `given` and `expected` match? Compares their SHA-256
+/// digests in constant time, so neither the position of the first
+/// differing byte nor the secret's length leaks through timing.
+pub fn secret_eq(given: &str, expected: &str) -> bool {
+    constant_time_eq(&Sha256::digest(given.as_bytes()), &Sha256::digest(expected.as_bytes()))
+}
+
+/// Helper: `a == b` in time that depends only on the lengths
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    a.len() == b.len() && std::hint::black_box(diff) == 0
+}
+
+/// `authorize` against an already-locked key table
+fn permitted(req: &HttpRequest, hashes: &HashMap<String, String>, wallet: &str) -> Result<(), ApiError> {
+    let Some(expected) = hashes.get(wallet) else {
+        return Ok(());
+    };
+    if holds_key(req, expected) || is_admin(req) { Ok(()) } else { Err(ApiError::InvalidApiKey) }
+}
+
+/// Helper: does `req` carry the API key whose digest is `expected`?
+fn holds_key(req: &HttpRequest, expected: &str) -> bool {
+    bearer(req).is_some_and(|api_key| secret_eq(&digest(api_key), expected))
+}
+
+#[derive(Deserialize)]
+pub struct RegisterRequest {
+    wallet: String,
+}
+
+#[derive(Serialize)]
+struct RegisterResponse {
+    wallet:  String,
+    /// shown only this once; the server keeps just its hash
+    api_key: String,
+}
+
+/// POST /register
+/// { "wallet": "..." }
+/// Mints an API key for `wallet`. From then on `/credit`, `/debit`,
+/// `/transfer` (for the `from` wallet) and the hold endpoints require
+/// `Authorization: Bearer <api_key>` (or the admin token) for it, and
+/// answer 401 otherwise. Registering an already-registered wallet
+/// replaces its key, so that needs the current key or the admin token.
+/// So does the first registration of a wallet that already has ledger
+/// entries: otherwise anyone could claim a funded wallet.
+#[instrument(skip_all, fields(operation = "register", wallet = %body.wallet))]
+pub async fn register(
+    req:  HttpRequest,
+    body: web::Json<RegisterRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let wallet = normalize_wallet(&body.wallet)?;
+    let keys = api_keys();
+
+    // hold the write lock through the file update, so two registrations
+    // for the same wallet can't both succeed
+    let mut hashes = keys.hashes.write().unwrap();
+    permitted(&req, &hashes, &wallet)?;
+    if !hashes.contains_key(&wallet) && tenant::current().ledger.has_entries(&wallet) && !is_admin(&req) {
+        return Err(ApiError::Unauthorized);
+    }
+
+    let api_key = mint_token();
+    let previous = hashes.insert(wallet.clone(), digest(&api_key));
+    if let Err(e) = save(&keys.path, &hashes) {
+        match previous {
+            Some(old) => hashes.insert(wallet, old),
+            None      => hashes.remove(&wallet),
+        };
+        return Err(e.into());
+    }
+    info!(rotated = previous.is_some(), "API key registered");
+
+    Ok(HttpResponse::Ok().json(RegisterResponse { wallet, api_key }))
+}
+
+/// Helper: a fresh random secret, 32 bytes in hex
+pub fn mint_token() -> String {
+    let mut bytes = [0u8; 32];
+    thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Write `hashes` to `path` atomically (temp file, then rename)
+fn save(path: &Path, hashes: &HashMap<String, String>) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut out = fs::File::create(&tmp)?;
+    out.write_all(serde_json::to_string(hashes)?.as_bytes())?;
+    out.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_eq_matches_only_identical_secrets() {
+        assert!(secret_eq("s3cret", "s3cret"));
+        assert!(!secret_eq("s3cret", "s3creT"));
+        assert!(!secret_eq("s3cret", "s3cret-and-more"));
+        assert!(!secret_eq("", "s3cret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"ab", b"a"));
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use num_bigint::BigUint;
+
+struct Entry {
+    m:    BigUint,
+    /// when the entry was last used; its key in `Lru::order`
+    tick: u64,
+}
+
+struct Lru {
+    entries: HashMap<BigUint, Entry>,
+    /// least recently used first
+    order:   BTreeMap<u64, BigUint>,
+    tick:    u64,
+}
+
+/// Plaintexts of recently decrypted ciphertexts, keyed by `c` and
+/// evicting the least recently used past `capacity`.
+///
+/// Every stored entry is re-randomized, so a wallet's new balance always
+/// has a new `c` and never hits a stale plaintext. The cache is only
+/// valid for one key; clear it whenever the key changes.
+pub struct DecryptCache {
+    capacity: usize,
+    lru:      Mutex<Lru>,
+}
+
+impl DecryptCache {
+    /// A cache of at most `capacity` plaintexts; 0 disables it
+    pub fn new(capacity: usize) -> Self {
+        let lru = Lru { entries: HashMap::new(), order: BTreeMap::new(), tick: 0 };
+        DecryptCache { capacity, lru: Mutex::new(lru) }
+    }
+
+    /// The cached plaintext of `c`, marking it most recently used
+    pub fn get(&self, c: &BigUint) -> Option<BigUint> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut lru = self.lru.lock().unwrap();
+        let Lru { entries, order, tick } = &mut *lru;
+        let entry = entries.get_mut(c)?;
+        *tick += 1;
+        let c = order.remove(&entry.tick).expect("every entry is in the order");
+        order.insert(*tick, c);
+        entry.tick = *tick;
+        Some(entry.m.clone())
+    }
+
+    /// Remember that `c` decrypts to `m`, evicting the least recently
+    /// used entry if the cache is full
+    pub fn insert(&self, c: BigUint, m: BigUint) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap();
+        let Lru { entries, order, tick } = &mut *lru;
+        *tick += 1;
+        if let Some(old) = entries.insert(c.clone(), Entry { m, tick: *tick }) {
+            order.remove(&old.tick);
+        } else if entries.len() > self.capacity {
+            if let Some((_, evicted)) = order.pop_first() {
+                entries.remove(&evicted);
+            }
+        }
+        order.insert(*tick, c);
+    }
+
+    /// Drop every entry, e.g. after rotating the key
+    pub fn clear(&self) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.entries.clear();
+        lru.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let cache = DecryptCache::new(2);
+        let (a, b, c) = (BigUint::from(1u32), BigUint::from(2u32), BigUint::from(3u32));
+        cache.insert(a.clone(), BigUint::from(10u32));
+        cache.insert(b.clone(), BigUint::from(20u32));
+        // touching `a` leaves `b` the least recently used
+        assert_eq!(cache.get(&a), Some(BigUint::from(10u32)));
+        cache.insert(c.clone(), BigUint::from(30u32));
+        assert_eq!(cache.get(&b), None);
+        assert_eq!(cache.get(&a), Some(BigUint::from(10u32)));
+        assert_eq!(cache.get(&c), Some(BigUint::from(30u32)));
+
+        cache.clear();
+        assert_eq!(cache.get(&a), None);
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let cache = DecryptCache::new(0);
+        cache.insert(BigUint::from(1u32), BigUint::from(10u32));
+        assert_eq!(cache.get(&BigUint::from(1u32)), None);
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs;
+
+use num_bigint::{BigUint, RandBigInt};
+use rand::thread_rng;
+use serde::Deserialize;
+
+use privacyserver::paillier::{
+    decode_signed,
+    decrypt,
+    decrypt_crt,
+    encrypt,
+    encrypt_with_randomness,
+    homomorphic_addition,
+    homomorphic_scalar_mul,
+    PaillierCiphertext,
+    PaillierKey,
+};
+
+use crate::config::arg_value;
+
+/// `privacyserver decrypt --key key.json --ciphertext <decimal>`
+/// Prints the signed plaintext of a saved ciphertext, without starting
+/// the server. Returns the exit status: 2 for bad arguments, 1 if the
+/// key can't be loaded or the ciphertext doesn't decrypt.
+pub fn decrypt_command(args: &[String]) -> i32 {
+    let (Some(key_path), Some(c)) = (arg_value(args, "--key"), arg_value(args, "--ciphertext")) else {
+        eprintln!("usage: privacyserver decrypt --key <key.json> --ciphertext <decimal>");
+        return 2;
+    };
+    let Ok(c) = c.parse::<BigUint>() else {
+        eprintln!("ciphertext must be a decimal integer");
+        return 2;
+    };
+
+    let key = match fs::read_to_string(&key_path) {
+        Ok(json) => PaillierKey::from_json(&json),
+        Err(e) => {
+            eprintln!("failed to read {key_path}: {e}");
+            return 1;
+        }
+    };
+    let key = match key {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("failed to load {key_path}: {e}");
+            return 1;
+        }
+    };
+
+    match decrypt(&key, &PaillierCiphertext::new(c, key.modulus.clone())) {
+        Ok(m) => {
+            println!("{}", decode_signed(&m, &key.n_s));
+            0
+        }
+        Err(e) => {
+            eprintln!("failed to decrypt: {e}");
+            1
+        }
+    }
+}
+
+/// Known-answer vectors computed by an independent textbook Paillier
+/// implementation (`g = n + 1`, `λ = (p-1)(q-1)`, `μ = λ⁻¹ mod n`): keys
+/// from tiny to 1024-bit, each with plaintexts from 0 up to `n - 1`
+const VECTORS: &str = include_str!("../vectors/paillier.json");
+
+/// One key in `VECTORS`, in the `PaillierKey::to_json` form
+#[derive(Deserialize)]
+struct VectorSet {
+    key:     serde_json::Value,
+    vectors: Vec<Vector>,
+}
+
+/// `m` encrypted with randomness `r` is `c`; all decimal strings
+#[derive(Deserialize)]
+struct Vector {
+    m: String,
+    r: String,
+    c: String,
+}
+
+/// `privacyserver selftest [--rounds N]`
+/// Checks that `encrypt_with_randomness` reproduces every known-answer
+/// vector's `c` exactly and that decryption recovers its `m`, so our
+/// ciphertexts stay interchangeable with other implementations'. Then
+/// generates a small key and checks, on `N` (default 100) random inputs
+/// each, that `dec(enc(m)) == m`, `dec(enc(a)·enc(b)) == a + b`, and
+/// `dec(enc(a)^k) == a·k` (mod n), to catch a broken bignum backend in a
+/// new environment. Returns the exit status: 0 if every check passed.
+pub fn selftest_command(args: &[String]) -> i32 {
+    let rounds = match arg_value(args, "--rounds").map(|v| v.parse::<usize>()) {
+        None         => 100,
+        Some(Ok(n))  => n,
+        Some(Err(_)) => {
+            eprintln!("usage: privacyserver selftest [--rounds <count>]");
+            return 2;
+        }
+    };
+    let key = match PaillierKey::new(512) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("failed to generate a key: {e}");
+            return 1;
+        }
+    };
+
+    let n = &key.n;
+    let mut rng = thread_rng();
+    let mut failures = 0;
+    let mut check = |name: &str, passed: usize, total: usize| {
+        println!("{name}: {passed}/{total} {}", if passed == total { "ok" } else { "FAILED" });
+        failures += total - passed;
+    };
+
+    let (passed, total) = known_answers();
+    check("known-answer vectors", passed, total);
+
+    let passed = (0..rounds).filter(|_| {
+        let m = rng.gen_biguint_below(n);
+        decrypt(&key, &encrypt(&key, &m)).is_ok_and(|d| d == m)
+            && decrypt_crt(&key, &encrypt(&key, &m)).is_ok_and(|d| d == m)
+    }).count();
+    check("dec(enc(m)) == m", passed, rounds);
+
+    let passed = (0..rounds).filter(|_| {
+        let (a, b) = (rng.gen_biguint_below(n), rng.gen_biguint_below(n));
+        homomorphic_addition(&encrypt(&key, &a), &encrypt(&key, &b))
+            .is_ok_and(|sum| decrypt(&key, &sum).is_ok_and(|d| d == (&a + &b) % n))
+    }).count();
+    check("dec(enc(a) + enc(b)) == a + b", passed, rounds);
+
+    let passed = (0..rounds).filter(|_| {
+        let (a, k) = (rng.gen_biguint_below(n), rng.gen_biguint_below(n));
+        let product = homomorphic_scalar_mul(&encrypt(&key, &a), &k, &key.n_squared);
+        decrypt(&key, &product).is_ok_and(|d| d == &a * &k % n)
+    }).count();
+    check("dec(enc(a) * k) == a * k", passed, rounds);
+
+    if failures == 0 { 0 } else { 1 }
+}
+
+/// Helper: how many of `VECTORS` pass, out of how many. A key that
+/// doesn't load fails all of its vectors.
+fn known_answers() -> (usize, usize) {
+    let sets: Vec<VectorSet> = serde_json::from_str(VECTORS).expect("the vectors file is valid");
+    let parse = |v: &str| v.parse::<BigUint>().expect("vector values are decimal integers");
+    let (mut passed, mut total) = (0, 0);
+    for set in sets {
+        total += set.vectors.len();
+        let key = match PaillierKey::from_json(&set.key.to_string()) {
+            Ok(key) => key,
+            Err(e) => {
+                eprintln!("failed to load a vector key: {e}");
+                continue;
+            }
+        };
+        passed += set.vectors.iter().filter(|v| {
+            let (m, c) = (parse(&v.m), parse(&v.c));
+            let ct = encrypt_with_randomness(&key, &m, &parse(&v.r));
+            ct.c == c
+                && decrypt(&key, &ct).is_ok_and(|d| d == m)
+                && decrypt_crt(&key, &ct).is_ok_and(|d| d == m)
+        }).count();
+    }
+    (passed, total)
+}
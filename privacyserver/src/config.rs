@@ -0,0 +1,259 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use privacyserver::ledger::LedgerFormat;
+use privacyserver::paillier::OverflowPolicy;
+
+use crate::wallet::WalletFormat;
+
+/// Runtime configuration, from CLI flags with environment fallbacks
+pub struct Config {
+    /// File the ledger is persisted to (`--ledger-path`)
+    pub ledger_path:     PathBuf,
+    /// How that file stores records (`--ledger-format` / `LEDGER_FORMAT`:
+    /// `jsonl`, the default, or `binary`)
+    pub ledger_format:   LedgerFormat,
+    /// What a credit past the largest balance does (`--overflow-policy` /
+    /// `OVERFLOW_POLICY`: `reject`, the default, `saturate`, or `wrap`)
+    pub overflow_policy: OverflowPolicy,
+    /// Minor units per whole unit, a power of 10 (`--scale` / `AMOUNT_SCALE`,
+    /// default 1): with 100, amounts are cents and `/decrypt` reports
+    /// `1234` as `"12.34"`. Ciphertexts hold the integer minor units, so
+    /// every server sharing a ledger must use the same scale.
+    pub scale:           u64,
+    /// JSON file the keypair is persisted to (`--key-path`)
+    pub key_path:        PathBuf,
+    /// Two decimal lines, the primes `p` and `q` to build the keypair from
+    /// instead of generating one (`--primes-file`)
+    pub primes_file:     Option<PathBuf>,
+    /// JSON file of hashed per-wallet API keys (`--api-keys-path`)
+    pub api_keys_path:   PathBuf,
+    /// Paillier modulus size (`--key-bits` / `PAILLIER_KEY_BITS`)
+    pub key_bits:        usize,
+    /// Address to listen on (`--bind` / `BIND_ADDR`)
+    pub bind:            String,
+    /// Trade key-generation rigor for startup speed (`--dev`)
+    pub dev:             bool,
+    /// Mount the raw `/oracle/*` crypto endpoints, for interoperability
+    /// testing only (`--oracle-mode`)
+    pub oracle_mode:     bool,
+    /// Gzip- or deflate-compress responses for clients that send
+    /// `Accept-Encoding` (`--compression`)
+    pub compression:     bool,
+    /// Re-randomize and persist a wallet's ciphertext when `/net/{wallet}`
+    /// reads it, at most once per this interval (`--refresh-on-read`, with
+    /// `--refresh-interval` / `REFRESH_INTERVAL_SECS` in seconds, default 60)
+    pub refresh_on_read: Option<Duration>,
+    /// Mount `GET /net/{wallet}/verified`, which reveals balances to any
+    /// caller (`--verified-net`)
+    pub verified_net:    bool,
+    /// Write ciphertexts into logs and error bodies whole, rather than
+    /// as their first and last 8 digits (`--full-ciphertext-logs`)
+    pub full_ct_logs:    bool,
+    /// How long `Idempotency-Key` responses are replayed
+    /// (`--idempotency-ttl` / `IDEMPOTENCY_TTL_SECS`, in seconds)
+    pub idempotency_ttl: Duration,
+    /// Accepted wallet identifiers (`--wallet-format` / `WALLET_FORMAT`:
+    /// `any`, the default, or `eth`)
+    pub wallet_format:   WalletFormat,
+    /// Most distinct wallets the ledger may hold, in any currency;
+    /// requests that would add another get 429 (`--max-wallets` /
+    /// `MAX_WALLETS`; unlimited if unset)
+    pub max_wallets:     Option<usize>,
+    /// Serve each tenant created with `POST /admin/tenants` under
+    /// `/{tenant}/...`, with its own ledger, keypair and admin token kept
+    /// in a subdirectory of this one (`--tenants-dir` / `TENANTS_DIR`;
+    /// off if unset)
+    pub tenants_dir:     Option<PathBuf>,
+    /// Most plaintexts kept by the decryption cache; 0 disables it
+    /// (`--decrypt-cache-size` / `DECRYPT_CACHE_SIZE`, default 1024)
+    pub decrypt_cache:   usize,
+    /// Encryption randomness factors each tenant precomputes in the
+    /// background; 0 disables the pool
+    /// (`--randomness-pool-size` / `RANDOMNESS_POOL_SIZE`, default 256)
+    pub randomness_pool: usize,
+    /// Largest accepted JSON body (`--max-body-bytes` / `MAX_BODY_BYTES`)
+    pub max_body_bytes:  usize,
+    /// Most amounts one `/credit/batch` may carry
+    /// (`--max-batch-len` / `MAX_BATCH_LEN`)
+    pub max_batch_len:   usize,
+}
+
+impl Config {
+    /// Parse the process arguments, rejecting invalid values.
+    pub fn from_env() -> Result<Self, String> {
+        Config::from_args(std::env::args().skip(1).collect())
+    }
+
+    /// Like `from_env`, for `args` in place of the process arguments
+    pub fn from_args(args: Vec<String>) -> Result<Self, String> {
+        let key_bits = match setting(&args, "--key-bits", "PAILLIER_KEY_BITS") {
+            Some(v) => v.parse::<usize>()
+                        .map_err(|_| format!("key bits must be an integer, got `{v}`"))?,
+            None    => 2048,
+        };
+        if key_bits < 512 || key_bits % 2 != 0 {
+            return Err(format!("key bits must be even and at least 512, got {key_bits}"));
+        }
+
+        let ttl_secs = match setting(&args, "--idempotency-ttl", "IDEMPOTENCY_TTL_SECS") {
+            Some(v) => v.parse::<u64>()
+                        .map_err(|_| format!("idempotency TTL must be a whole number of seconds, got `{v}`"))?,
+            None    => 24 * 60 * 60,
+        };
+
+        let refresh_secs = match setting(&args, "--refresh-interval", "REFRESH_INTERVAL_SECS") {
+            Some(v) => v.parse::<u64>()
+                        .map_err(|_| format!("refresh interval must be a whole number of seconds, got `{v}`"))?,
+            None    => 60,
+        };
+
+        let wallet_format = match setting(&args, "--wallet-format", "WALLET_FORMAT").as_deref() {
+            None | Some("any") => WalletFormat::Any,
+            Some("eth")        => WalletFormat::Eth,
+            Some(v)            => return Err(format!("wallet format must be `any` or `eth`, got `{v}`")),
+        };
+
+        let ledger_format = match setting(&args, "--ledger-format", "LEDGER_FORMAT").as_deref() {
+            None | Some("jsonl") => LedgerFormat::Jsonl,
+            Some("binary")       => LedgerFormat::Binary,
+            Some(v)              => return Err(format!("ledger format must be `jsonl` or `binary`, got `{v}`")),
+        };
+
+        let overflow_policy = match setting(&args, "--overflow-policy", "OVERFLOW_POLICY").as_deref() {
+            None | Some("reject") => OverflowPolicy::Reject,
+            Some("saturate")      => OverflowPolicy::Saturate,
+            Some("wrap")          => OverflowPolicy::Wrap,
+            Some(v)               => {
+                return Err(format!("overflow policy must be `reject`, `saturate`, or `wrap`, got `{v}`"));
+            }
+        };
+
+        let scale = match setting(&args, "--scale", "AMOUNT_SCALE") {
+            Some(v) => v.parse::<u64>()
+                        .map_err(|_| format!("scale must be an integer, got `{v}`"))?,
+            None    => 1,
+        };
+        if scale == 0 || 10u64.pow(scale.ilog10()) != scale {
+            return Err(format!("scale must be a power of 10, got {scale}"));
+        }
+
+        let max_wallets = setting(&args, "--max-wallets", "MAX_WALLETS")
+            .map(|v| v.parse::<usize>().map_err(|_| format!("max wallets must be an integer, got `{v}`")))
+            .transpose()?;
+
+        let decrypt_cache = match setting(&args, "--decrypt-cache-size", "DECRYPT_CACHE_SIZE") {
+            Some(v) => v.parse::<usize>()
+                        .map_err(|_| format!("decrypt cache size must be an integer, got `{v}`"))?,
+            None    => 1024,
+        };
+        let randomness_pool = match setting(&args, "--randomness-pool-size", "RANDOMNESS_POOL_SIZE") {
+            Some(v) => v.parse::<usize>()
+                        .map_err(|_| format!("randomness pool size must be an integer, got `{v}`"))?,
+            None    => 256,
+        };
+
+        let max_body_bytes = match setting(&args, "--max-body-bytes", "MAX_BODY_BYTES") {
+            Some(v) => v.parse::<usize>()
+                        .map_err(|_| format!("max body bytes must be an integer, got `{v}`"))?,
+            None    => 64 * 1024,
+        };
+        let max_batch_len = match setting(&args, "--max-batch-len", "MAX_BATCH_LEN") {
+            Some(v) => v.parse::<usize>()
+                        .map_err(|_| format!("max batch length must be an integer, got `{v}`"))?,
+            None    => 1000,
+        };
+
+        Ok(Config {
+            ledger_path:     arg_value(&args, "--ledger-path")
+                .unwrap_or_else(|| "./ledger.jsonl".into())
+                .into(),
+            ledger_format,
+            overflow_policy,
+            scale,
+            key_path:        arg_value(&args, "--key-path")
+                .unwrap_or_else(|| "./key.json".into())
+                .into(),
+            primes_file:     arg_value(&args, "--primes-file").map(Into::into),
+            api_keys_path:   arg_value(&args, "--api-keys-path")
+                .unwrap_or_else(|| "./api_keys.json".into())
+                .into(),
+            key_bits,
+            bind:            setting(&args, "--bind", "BIND_ADDR")
+                .unwrap_or_else(|| "127.0.0.1:8085".into()),
+            dev:             args.iter().any(|a| a == "--dev"),
+            oracle_mode:     args.iter().any(|a| a == "--oracle-mode"),
+            compression:     args.iter().any(|a| a == "--compression"),
+            refresh_on_read: args.iter().any(|a| a == "--refresh-on-read")
+                                 .then(|| Duration::from_secs(refresh_secs)),
+            verified_net:    args.iter().any(|a| a == "--verified-net"),
+            full_ct_logs:    args.iter().any(|a| a == "--full-ciphertext-logs"),
+            idempotency_ttl: Duration::from_secs(ttl_secs),
+            wallet_format,
+            max_wallets,
+            tenants_dir:     setting(&args, "--tenants-dir", "TENANTS_DIR").map(Into::into),
+            decrypt_cache,
+            randomness_pool,
+            max_body_bytes,
+            max_batch_len,
+        })
+    }
+}
+
+/// Helper: `flag` from `args` if given, else the `env` variable
+fn setting(args: &[String], flag: &str, env: &str) -> Option<String> {
+    arg_value(args, flag).or_else(|| std::env::var(env).ok())
+}
+
+/// Helper: value of `--name value` or `--name=value` in `args`
+pub fn arg_value(args: &[String], name: &str) -> Option<String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == name {
+            return iter.next().cloned();
+        }
+        if let Some(v) = arg.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(v.to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::from_args(args.iter().map(|a| a.to_string()).collect())
+    }
+
+    #[test]
+    fn key_bits_and_bind_come_from_flags() {
+        let config = parse(&["--key-bits", "3072", "--bind=0.0.0.0:9000"]).unwrap();
+        assert_eq!((config.key_bits, config.bind.as_str()), (3072, "0.0.0.0:9000"));
+    }
+
+    #[test]
+    fn key_bits_must_be_even_and_at_least_512() {
+        for bits in ["511", "1025", "256", "big"] {
+            assert!(parse(&["--key-bits", bits]).is_err(), "{bits}");
+        }
+    }
+
+    #[test]
+    fn overflow_policy_comes_from_the_flag() {
+        assert_eq!(parse(&[]).unwrap().overflow_policy, OverflowPolicy::Reject);
+        assert_eq!(parse(&["--overflow-policy", "saturate"]).unwrap().overflow_policy, OverflowPolicy::Saturate);
+        assert_eq!(parse(&["--overflow-policy", "wrap"]).unwrap().overflow_policy, OverflowPolicy::Wrap);
+        assert!(parse(&["--overflow-policy", "clamp"]).is_err());
+    }
+
+    #[test]
+    fn scale_must_be_a_power_of_ten() {
+        assert_eq!(parse(&[]).unwrap().scale, 1);
+        assert_eq!(parse(&["--scale", "100"]).unwrap().scale, 100);
+        for scale in ["0", "50", "-10", "ten"] {
+            assert!(parse(&["--scale", scale]).is_err(), "{scale}");
+        }
+    }
+}
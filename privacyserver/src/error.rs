@@ -0,0 +1,314 @@
+use std::fmt;
+use std::io;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use num_bigint::BigUint;
+use serde::Serialize;
+
+use actix_web::error::BlockingError;
+
+use privacyserver::ledger::{IntegrityError, WalletLimitError};
+use privacyserver::paillier::{Amount, Balance, DecryptError, KeyGenError, MismatchError, RangeError};
+use privacyserver::store::StoreError;
+
+use crate::mask;
+use crate::wallet::WalletError;
+
+/// Body of every error response
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    /// stable, machine-readable identifier, e.g. `WALLET_NOT_FOUND`
+    pub code:    String,
+    /// human-readable explanation; not meant to be parsed
+    pub message: String,
+    /// extra fields for errors that carry data, e.g. an overdraft's amounts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Why a request failed
+#[derive(Debug)]
+pub enum ApiError {
+    /// the wallet (in that currency) has no records
+    WalletNotFound,
+    /// no unexpired response is stored under that `Idempotency-Key`
+    IdempotencyKeyNotFound,
+    /// an `Idempotency-Key` came back with a different request than the
+    /// `operation` it was first used for
+    IdempotencyKeyReused { operation: &'static str },
+    /// missing or wrong admin bearer token
+    Unauthorized,
+    /// the wallet has an API key and the request didn't carry it
+    InvalidApiKey,
+    /// the body, path, or query couldn't be parsed
+    InvalidRequest(String),
+    /// a wallet identifier doesn't match `--wallet-format`
+    InvalidWallet(WalletError),
+    /// a `/{tenant}` path segment isn't a usable tenant name
+    InvalidTenant(String),
+    /// no tenant by that name was created
+    TenantNotFound(String),
+    /// `POST /admin/tenants` for a name that's taken
+    TenantExists(String),
+    /// `/credit/batch` with no amounts
+    EmptyBatch,
+    /// `/credit/batch` with more than `max` amounts
+    BatchTooLarge { max: usize },
+    /// the request body is over `limit` bytes
+    PayloadTooLarge { limit: usize },
+    /// `/transfer` or `/spend` to the sending wallet
+    SameWallet,
+    /// a ciphertext isn't valid under the server key; `c` is the value,
+    /// if the client sent it
+    InvalidCiphertext { why: DecryptError, c: Option<BigUint> },
+    /// a submitted ciphertext's proof of well-formedness didn't verify
+    InvalidProof,
+    /// the client exceeded its rate limit
+    RateLimited,
+    /// a new wallet would pass `--max-wallets`
+    TooManyWallets { max: usize },
+    /// a credit would push the balance past `max`; at most `headroom`
+    /// more fits
+    Overflow { max: BigUint, headroom: Amount },
+    /// a debit would leave the balance negative
+    InsufficientFunds { attempted: Amount, available: Balance },
+    /// an exact `/spend` whose amount isn't the whole balance
+    InexactSpend { attempted: Amount, available: Balance },
+    /// a capture or release exceeds what the wallet has on hold
+    InsufficientHeld { attempted: Amount, held: Balance },
+    /// the ledger file couldn't be written
+    Storage(io::Error),
+    /// a new keypair couldn't be generated
+    KeyGen(KeyGenError),
+    /// the key hasn't passed its self-test
+    NotReady,
+    /// a tenant's ledger failed `Ledger::check`; `tenant` is `None` for
+    /// the default tenant
+    LedgerUnhealthy { tenant: Option<String>, error: IntegrityError },
+    /// a blocking task was cancelled before it finished, or ciphertexts
+    /// that should share the server key didn't
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::WalletNotFound           => "WALLET_NOT_FOUND",
+            ApiError::IdempotencyKeyNotFound   => "IDEMPOTENCY_KEY_NOT_FOUND",
+            ApiError::IdempotencyKeyReused { .. } => "IDEMPOTENCY_KEY_REUSED",
+            ApiError::Unauthorized             => "UNAUTHORIZED",
+            ApiError::InvalidApiKey            => "INVALID_API_KEY",
+            ApiError::InvalidRequest(_)        => "INVALID_REQUEST",
+            ApiError::InvalidWallet(_)         => "INVALID_WALLET",
+            ApiError::InvalidTenant(_)         => "INVALID_TENANT",
+            ApiError::TenantNotFound(_)        => "TENANT_NOT_FOUND",
+            ApiError::TenantExists(_)          => "TENANT_EXISTS",
+            ApiError::EmptyBatch               => "EMPTY_BATCH",
+            ApiError::BatchTooLarge { .. }     => "BATCH_TOO_LARGE",
+            ApiError::PayloadTooLarge { .. }   => "PAYLOAD_TOO_LARGE",
+            ApiError::SameWallet               => "SAME_WALLET",
+            ApiError::InvalidCiphertext { .. } => "INVALID_CIPHERTEXT",
+            ApiError::InvalidProof             => "INVALID_PROOF",
+            ApiError::RateLimited              => "RATE_LIMITED",
+            ApiError::TooManyWallets { .. }    => "TOO_MANY_WALLETS",
+            ApiError::Overflow { .. }          => "PLAINTEXT_OVERFLOW",
+            ApiError::InsufficientFunds { .. } => "INSUFFICIENT_FUNDS",
+            ApiError::InexactSpend { .. }      => "INEXACT_SPEND",
+            ApiError::InsufficientHeld { .. }  => "INSUFFICIENT_HELD",
+            ApiError::Storage(_)               => "STORAGE_ERROR",
+            ApiError::KeyGen(_)                => "KEYGEN_FAILED",
+            ApiError::NotReady                 => "NOT_READY",
+            ApiError::LedgerUnhealthy { .. }   => "LEDGER_UNHEALTHY",
+            ApiError::Internal(_)              => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::WalletNotFound       => write!(f, "No records for that wallet"),
+            ApiError::IdempotencyKeyNotFound => write!(f, "No stored response for that idempotency key"),
+            ApiError::IdempotencyKeyReused { .. } => {
+                write!(f, "That idempotency key was already used for a different request")
+            }
+            ApiError::Unauthorized         => write!(f, "Missing or invalid admin token"),
+            ApiError::InvalidApiKey        => write!(f, "Missing or invalid API key for this wallet"),
+            ApiError::InvalidRequest(why)  => write!(f, "{why}"),
+            ApiError::InvalidWallet(e)     => write!(f, "{e}"),
+            ApiError::InvalidTenant(name)  => {
+                write!(f, "`{name}` is not a tenant name: use 1-64 letters, digits, `-` or `_`, \
+                           other than a root path segment")
+            }
+            ApiError::TenantNotFound(name) => write!(f, "No tenant named `{name}`"),
+            ApiError::TenantExists(name)   => write!(f, "Tenant `{name}` already exists"),
+            ApiError::EmptyBatch           => write!(f, "`amounts` must not be empty"),
+            ApiError::BatchTooLarge { max } => write!(f, "`amounts` may hold at most {max} entries"),
+            ApiError::PayloadTooLarge { limit } => write!(f, "Request body exceeds {limit} bytes"),
+            ApiError::SameWallet           => write!(f, "Cannot transfer to the same wallet"),
+            ApiError::InvalidCiphertext { why, c: Some(c) } => {
+                write!(f, "Invalid ciphertext {}: {why}", mask::ciphertext(c))
+            }
+            ApiError::InvalidCiphertext { why, c: None } => write!(f, "Invalid ciphertext: {why}"),
+            ApiError::InvalidProof         => write!(f, "Ciphertext proof does not verify"),
+            ApiError::RateLimited          => write!(f, "Too many requests; try again later"),
+            ApiError::TooManyWallets { max } => write!(f, "The server holds its maximum of {max} wallets"),
+            ApiError::Overflow { max, .. } => {
+                write!(f, "Credit would overflow the plaintext range (max balance {max})")
+            }
+            ApiError::InsufficientFunds { .. } => write!(f, "Insufficient funds"),
+            ApiError::InexactSpend { .. }      => write!(f, "An exact spend must use the whole balance"),
+            ApiError::InsufficientHeld { .. }  => write!(f, "Amount exceeds the funds on hold"),
+            ApiError::Storage(e)           => write!(f, "Failed to write the ledger: {e}"),
+            ApiError::KeyGen(e)            => write!(f, "Key generation failed: {e}"),
+            ApiError::NotReady             => write!(f, "Key self-test has not passed"),
+            ApiError::LedgerUnhealthy { tenant: Some(name), error } => {
+                write!(f, "Tenant `{name}`'s ledger failed its integrity check: {error}")
+            }
+            ApiError::LedgerUnhealthy { tenant: None, error } => {
+                write!(f, "The ledger failed its integrity check: {error}")
+            }
+            ApiError::Internal(why)        => write!(f, "Internal error: {why}"),
+        }
+    }
+}
+
+impl From<io::Error> for ApiError {
+    fn from(e: io::Error) -> Self {
+        ApiError::Storage(e)
+    }
+}
+
+impl From<WalletError> for ApiError {
+    fn from(e: WalletError) -> Self {
+        ApiError::InvalidWallet(e)
+    }
+}
+
+impl From<DecryptError> for ApiError {
+    fn from(e: DecryptError) -> Self {
+        match e {
+            DecryptError::Erased | DecryptError::UnsupportedKey => ApiError::Internal(e.to_string()),
+            _                                                   => ApiError::InvalidCiphertext { why: e, c: None },
+        }
+    }
+}
+
+impl From<KeyGenError> for ApiError {
+    fn from(e: KeyGenError) -> Self {
+        ApiError::KeyGen(e)
+    }
+}
+
+impl From<RangeError> for ApiError {
+    fn from(e: RangeError) -> Self {
+        match e {
+            RangeError::Decrypt(e)                 => e.into(),
+            RangeError::Overflow { max, headroom } => ApiError::Overflow { max, headroom: headroom.into() },
+        }
+    }
+}
+
+impl From<WalletLimitError> for ApiError {
+    fn from(e: WalletLimitError) -> Self {
+        ApiError::TooManyWallets { max: e.max }
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::Io(e)             => e.into(),
+            StoreError::TooManyWallets(e) => e.into(),
+        }
+    }
+}
+
+impl From<MismatchError> for ApiError {
+    fn from(e: MismatchError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(e: BlockingError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::WalletNotFound
+            | ApiError::IdempotencyKeyNotFound
+            | ApiError::TenantNotFound(_)      => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized
+            | ApiError::InvalidApiKey          => StatusCode::UNAUTHORIZED,
+            ApiError::InvalidRequest(_)
+            | ApiError::InvalidWallet(_)
+            | ApiError::InvalidTenant(_)
+            | ApiError::EmptyBatch
+            | ApiError::BatchTooLarge { .. }
+            | ApiError::SameWallet
+            | ApiError::InvalidCiphertext { .. }
+            | ApiError::InvalidProof
+            | ApiError::Overflow { .. }        => StatusCode::BAD_REQUEST,
+            ApiError::InsufficientFunds { .. }
+            | ApiError::TenantExists(_)
+            | ApiError::InexactSpend { .. }
+            | ApiError::InsufficientHeld { .. } => StatusCode::CONFLICT,
+            ApiError::RateLimited
+            | ApiError::TooManyWallets { .. }  => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::PayloadTooLarge { .. }   => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::IdempotencyKeyReused { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Storage(_)
+            | ApiError::KeyGen(_)
+            | ApiError::Internal(_)            => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::NotReady
+            | ApiError::LedgerUnhealthy { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let details = match self {
+            ApiError::InsufficientFunds { attempted, available }
+            | ApiError::InexactSpend { attempted, available } => Some(serde_json::json!({
+                "attempted": attempted,
+                "available": available,
+            })),
+            ApiError::InsufficientHeld { attempted, held } => Some(serde_json::json!({
+                "attempted": attempted,
+                "held":      held,
+            })),
+            ApiError::Overflow { headroom, .. } => Some(serde_json::json!({
+                "headroom": headroom,
+            })),
+            ApiError::IdempotencyKeyReused { operation } => Some(serde_json::json!({
+                "operation": operation,
+            })),
+            ApiError::InvalidCiphertext { c: Some(c), .. } => Some(serde_json::json!({
+                "c": mask::ciphertext(c),
+            })),
+            ApiError::LedgerUnhealthy { tenant, error: IntegrityError::Mismatch { expected, actual } } => {
+                Some(serde_json::json!({
+                    "tenant":   tenant,
+                    "expected": expected,
+                    "actual":   actual,
+                }))
+            }
+            ApiError::LedgerUnhealthy { tenant, error: IntegrityError::Unwritable(e) } => {
+                Some(serde_json::json!({
+                    "tenant": tenant,
+                    "error":  e.to_string(),
+                }))
+            }
+            _ => None,
+        };
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            code:    self.code().to_string(),
+            message: self.to_string(),
+            details,
+        })
+    }
+}
@@ -0,0 +1,206 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use num_bigint::BigInt;
+use serde::Serialize;
+use tracing::{instrument, warn};
+
+use privacyserver::paillier::{
+    decode_signed,
+    homomorphic_addition,
+    homomorphic_subtraction,
+    rerandomize,
+    PaillierCiphertext,
+    PaillierKey,
+};
+
+use crate::error::ApiError;
+use crate::wallet::normalize_wallet;
+use crate::{
+    audit, auth, idempotency_key, key, metrics, replay, respond, rotation_gate, subscribe, timed_decrypt,
+    timed_encrypt, Radix, RadixQuery, Store, TxRequest,
+};
+
+/// A wallet's balance and held sub-balance after a hold operation
+#[derive(Serialize)]
+struct HoldResponse {
+    wallet:   String,
+    currency: String,
+    /// the available balance, as in `/net`
+    c:        String,
+    /// the held sub-balance
+    held:     String,
+}
+
+impl HoldResponse {
+    fn new(wallet: &str, currency: &str, balance: Option<&PaillierCiphertext>, held: &PaillierCiphertext, radix: Radix) -> Self {
+        HoldResponse {
+            wallet:   wallet.to_string(),
+            currency: currency.to_string(),
+            c:        balance.map(|ct| radix.format(&ct.c)).unwrap_or_default(),
+            held:     radix.format(&held.c),
+        }
+    }
+}
+
+/// Which way a hold operation moves funds
+#[derive(Clone, Copy)]
+enum Move {
+    /// balance → held
+    Hold,
+    /// held → gone
+    Capture,
+    /// held → balance
+    Release,
+}
+
+impl Move {
+    /// The endpoint's name, which scopes its idempotency keys
+    fn operation(self) -> &'static str {
+        match self {
+            Move::Hold    => "hold",
+            Move::Capture => "capture",
+            Move::Release => "release",
+        }
+    }
+}
+
+/// POST /hold
+/// { "wallet": "...", "amount": 40 }
+/// Reserves `amount` by moving it from the balance into the wallet's held
+/// sub-balance, so `/net` (and any debit) sees only what's left. Rejected
+/// with 409 if the balance can't cover it. Takes an `Idempotency-Key`
+/// like `/credit`; retry with the same key rather than holding twice.
+#[instrument(skip_all, fields(operation = "hold", wallet = %body.wallet))]
+pub async fn hold(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<TxRequest>,
+) -> Result<HttpResponse, ApiError> {
+    move_funds(&req, &store, &body, query.radix, Move::Hold)
+}
+
+/// POST /capture
+/// { "wallet": "...", "amount": 40 }
+/// Finalizes a hold: `amount` leaves the held sub-balance for good.
+/// Rejected with 409 if less than that is held.
+#[instrument(skip_all, fields(operation = "capture", wallet = %body.wallet))]
+pub async fn capture(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<TxRequest>,
+) -> Result<HttpResponse, ApiError> {
+    move_funds(&req, &store, &body, query.radix, Move::Capture)
+}
+
+/// POST /release
+/// { "wallet": "...", "amount": 40 }
+/// Cancels a hold: `amount` goes from the held sub-balance back to the
+/// balance. Rejected with 409 if less than that is held.
+#[instrument(skip_all, fields(operation = "release", wallet = %body.wallet))]
+pub async fn release(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<TxRequest>,
+) -> Result<HttpResponse, ApiError> {
+    move_funds(&req, &store, &body, query.radix, Move::Release)
+}
+
+/// Helper: apply `step` for `body.amount` to the wallet's balance and
+/// held sub-balance in `store`, appending both changes in a single write
+fn move_funds(
+    req:   &HttpRequest,
+    store: &Store,
+    body:  &TxRequest,
+    radix: Radix,
+    step:  Move,
+) -> Result<HttpResponse, ApiError> {
+    auth::authorize(req, &body.wallet)?;
+    let idem = idempotency_key(req, step.operation(), body);
+    let wallet = normalize_wallet(&body.wallet)?;
+    let currency = &body.currency;
+    let _gate = rotation_gate();
+    let key = key();
+    let ct_m = timed_encrypt(body.amount.get());
+    let amount = BigInt::from(body.amount.get().clone());
+
+    // 1) the store holds the wallet from the funds check through the append
+    let mut early = None;
+    let mut response = None;
+    store.update_held(
+        &wallet,
+        currency,
+        &mut |balance, held| {
+            let next = match replay(&wallet, currency, idem.as_ref()) {
+                Ok(Some(original)) => Err(Ok(original)),
+                Ok(None)           => next_entries(step, body, &key, &ct_m, &amount, balance, held).map_err(Err),
+                Err(e)             => Err(Err(e)),
+            };
+            next.map_err(|outcome| early = Some(outcome)).ok()
+        },
+        &mut |balance, held| {
+            // 4) a capture is the debit; holds and releases only move funds
+            if let Move::Capture = step {
+                audit::record(currency, -amount.clone());
+                metrics::DEBITS.inc();
+            }
+            if let (Some(ct), Move::Hold | Move::Release) = (balance, step) {
+                subscribe::publish(&wallet, currency, ct);
+            }
+            let body = HoldResponse::new(&wallet, currency, balance, held, radix);
+            response = Some(respond(&wallet, currency, idem.as_ref(), body));
+        },
+    )?;
+    match (early, response) {
+        (Some(outcome), _)     => outcome,
+        (None, Some(response)) => Ok(response),
+        (None, None)           => Err(ApiError::Internal("the ledger store skipped an update".into())),
+    }
+}
+
+/// Helper: the balance entry (if it changes) and held entry moving
+/// `amount` per `step` would append, given the latest of each
+fn next_entries(
+    step:    Move,
+    body:    &TxRequest,
+    key:     &PaillierKey,
+    ct_m:    &PaillierCiphertext,
+    amount:  &BigInt,
+    balance: Option<&PaillierCiphertext>,
+    held:    Option<&PaillierCiphertext>,
+) -> Result<(Option<PaillierCiphertext>, PaillierCiphertext), ApiError> {
+    let zero = || timed_encrypt(&0u32.into());
+    let prev_ct   = balance.cloned().unwrap_or_else(zero);
+    let prev_held = held.cloned().unwrap_or_else(zero);
+
+    // 2) the side funds come out of must cover the amount
+    let (source, source_ct) = match step {
+        Move::Hold                    => ("balance", &prev_ct),
+        Move::Capture | Move::Release => ("held", &prev_held),
+    };
+    let available = decode_signed(&timed_decrypt(source_ct)?, &key.n_s);
+    if available < *amount {
+        metrics::OVERDRAFTS_REJECTED.inc();
+        warn!(currency = %body.currency, source, "hold operation exceeds available funds");
+        return Err(match step {
+            Move::Hold => ApiError::InsufficientFunds {
+                attempted: body.amount.clone(),
+                available: available.into(),
+            },
+            _ => ApiError::InsufficientHeld {
+                attempted: body.amount.clone(),
+                held:      available.into(),
+            },
+        });
+    }
+
+    // 3) move the amount homomorphically, re-randomizing what's stored
+    let minus = |ct: &PaillierCiphertext| homomorphic_subtraction(ct, ct_m, key).map(|diff| rerandomize(&diff, key));
+    let plus  = |ct: &PaillierCiphertext| homomorphic_addition(ct, ct_m).map(|sum| rerandomize(&sum, key));
+    Ok(match step {
+        Move::Hold    => (Some(minus(&prev_ct)?), plus(&prev_held)?),
+        Move::Capture => (None, minus(&prev_held)?),
+        Move::Release => (Some(plus(&prev_ct)?), minus(&prev_held)?),
+    })
+}
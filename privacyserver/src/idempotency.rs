@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// `(wallet, currency, Idempotency-Key)`: the same key on another
+/// wallet is a different request
+type Scope = (String, String, String);
+
+/// What a key was sent with: the operation, e.g. `credit`, and a SHA-256
+/// digest of the request body. Only a retry matching both is replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    operation: &'static str,
+    digest:    [u8; 32],
+}
+
+impl Request {
+    pub fn new(operation: &'static str, body: &impl Serialize) -> Self {
+        let body = serde_json::to_vec(body).expect("request serialization cannot fail");
+        Request { operation, digest: Sha256::digest(body).into() }
+    }
+}
+
+/// A key came back with a different request than it was first used for;
+/// `operation` is the original's
+#[derive(Debug)]
+pub struct KeyReused {
+    pub operation: &'static str,
+}
+
+/// A stored response's key, as `IdempotencyStore::active` lists it
+pub struct ActiveKey {
+    pub wallet:    String,
+    pub currency:  String,
+    pub key:       String,
+    pub operation: &'static str,
+    /// how much longer the response will be replayed
+    pub remaining: Duration,
+}
+
+struct Entry {
+    stored_at: Instant,
+    request:   Request,
+    /// JSON body of the original response
+    body:      String,
+}
+
+/// Responses of requests that carried an `Idempotency-Key`, kept for
+/// `ttl` so a retry gets the original response instead of re-running.
+///
+/// Callers must look up and record while holding the wallet lock, so two
+/// concurrent retries can't both miss.
+pub struct IdempotencyStore {
+    ttl:     Duration,
+    entries: Mutex<HashMap<Scope, Entry>>,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> Self {
+        IdempotencyStore { ttl, entries: Mutex::new(HashMap::new()) }
+    }
+
+    /// The stored response for `key` on this wallet, if it hasn't
+    /// expired. `Err` if the key was stored for a different `request`.
+    pub fn get(&self, wallet: &str, currency: &str, key: &str, request: &Request) -> Result<Option<String>, KeyReused> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(&(wallet.to_string(), currency.to_string(), key.to_string()))
+                           .filter(|e| e.stored_at.elapsed() < self.ttl);
+        match entry {
+            Some(e) if e.request != *request => Err(KeyReused { operation: e.request.operation }),
+            Some(e)                          => Ok(Some(e.body.clone())),
+            None                             => Ok(None),
+        }
+    }
+
+    /// Every unexpired key, sorted by wallet, currency, then key
+    pub fn active(&self) -> Vec<ActiveKey> {
+        let entries = self.entries.lock().unwrap();
+        let mut active: Vec<_> = entries.iter()
+            .filter_map(|((wallet, currency, key), e)| {
+                let remaining = self.ttl.checked_sub(e.stored_at.elapsed()).filter(|r| !r.is_zero())?;
+                Some(ActiveKey {
+                    wallet:    wallet.clone(),
+                    currency:  currency.clone(),
+                    key:       key.clone(),
+                    operation: e.request.operation,
+                    remaining,
+                })
+            })
+            .collect();
+        active.sort_by(|a, b| (&a.wallet, &a.currency, &a.key).cmp(&(&b.wallet, &b.currency, &b.key)));
+        active
+    }
+
+    /// Forget `key` on every wallet, so a request carrying it runs again.
+    /// Returns how many unexpired responses were dropped.
+    pub fn revoke(&self, key: &str) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, e| e.stored_at.elapsed() < self.ttl);
+        let before = entries.len();
+        entries.retain(|(_, _, k), _| k != key);
+        before - entries.len()
+    }
+
+    /// Record the response to `request` for `key` on this wallet, dropping
+    /// expired entries
+    pub fn insert(&self, wallet: &str, currency: &str, key: &str, request: Request, body: String) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, e| e.stored_at.elapsed() < self.ttl);
+        entries.insert(
+            (wallet.to_string(), currency.to_string(), key.to_string()),
+            Entry { stored_at: Instant::now(), request, body },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn replays_only_the_same_request() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let credit = Request::new("credit", &json!({ "wallet": "alice", "amount": 100 }));
+        store.insert("alice", "USD", "k1", credit.clone(), "{}".into());
+
+        assert_eq!(store.get("alice", "USD", "k1", &credit).unwrap().as_deref(), Some("{}"));
+        // the same body to another endpoint, or another body to the same one
+        let debit = Request::new("debit", &json!({ "wallet": "alice", "amount": 100 }));
+        assert_eq!(store.get("alice", "USD", "k1", &debit).unwrap_err().operation, "credit");
+        let more = Request::new("credit", &json!({ "wallet": "alice", "amount": 200 }));
+        assert!(store.get("alice", "USD", "k1", &more).is_err());
+        // other wallets and unknown keys are unaffected
+        assert_eq!(store.get("bob", "USD", "k1", &debit).unwrap(), None);
+        assert_eq!(store.get("alice", "USD", "k2", &debit).unwrap(), None);
+    }
+
+    #[test]
+    fn expired_keys_run_again() {
+        let store = IdempotencyStore::new(Duration::ZERO);
+        let credit = Request::new("credit", &json!({ "amount": 1 }));
+        store.insert("alice", "USD", "k1", credit.clone(), "{}".into());
+        assert_eq!(store.get("alice", "USD", "k1", &credit).unwrap(), None);
+        assert!(store.active().is_empty());
+    }
+}
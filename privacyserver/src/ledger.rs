@@ -0,0 +1,853 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use crate::paillier::{read_prefixed, write_prefixed, PaillierCiphertext};
+
+/// Currency used when a request or an older ledger line names none
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// Serde default for `currency` fields
+pub fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_string()
+}
+
+/// Single ledger entry, storing the raw ciphertext
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub wallet:   String,
+    pub currency: String,
+    pub ct:       PaillierCiphertext,
+    /// the entry is for the wallet's held sub-balance, not its balance
+    pub held:     bool,
+}
+
+impl Record {
+    /// Compact binary form: `wallet`, `currency`, a held flag byte, and
+    /// `c` as little-endian bytes, the variable-length fields each behind
+    /// a u32 little-endian length. Like the JSONL form it leaves out `n²`,
+    /// which comes from the key.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_prefixed(&mut out, self.wallet.as_bytes());
+        write_prefixed(&mut out, self.currency.as_bytes());
+        out.push(self.held as u8);
+        write_prefixed(&mut out, &self.ct.c.to_bytes_le());
+        out
+    }
+
+    /// Parse the output of `to_bytes`, taking `n²` from the key
+    pub fn from_bytes(mut bytes: &[u8], n_squared: &BigUint) -> Result<Record, String> {
+        let mut field = |name: &str| {
+            read_prefixed(&mut bytes).ok_or_else(|| format!("truncated `{name}`"))
+        };
+        let text = |name: &str, raw: &[u8]| {
+            String::from_utf8(raw.to_vec()).map_err(|_| format!("`{name}` is not UTF-8"))
+        };
+        let wallet   = text("wallet", field("wallet")?)?;
+        let currency = text("currency", field("currency")?)?;
+        let held = match bytes.split_first() {
+            Some((&flag @ (0 | 1), rest)) => {
+                bytes = rest;
+                flag == 1
+            }
+            Some(_) => return Err("`held` flag is not 0 or 1".to_string()),
+            None    => return Err("truncated `held`".to_string()),
+        };
+        let c = BigUint::from_bytes_le(
+            read_prefixed(&mut bytes).ok_or("truncated `c`")?,
+        );
+        if !bytes.is_empty() {
+            return Err("trailing bytes after `c`".to_string());
+        }
+        Ok(Record {
+            wallet,
+            currency,
+            ct: PaillierCiphertext::new(c, n_squared.clone()),
+            held,
+        })
+    }
+}
+
+/// How the ledger file stores records (`--ledger-format`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LedgerFormat {
+    /// one JSON object per line, ciphertexts as decimal strings
+    #[default]
+    Jsonl,
+    /// `Record::to_bytes` frames, each behind a u32 little-endian length;
+    /// well under half the size of JSONL for large keys
+    Binary,
+}
+
+/// On-disk form of a `Record`: one JSON object per line
+#[derive(Serialize, Deserialize)]
+struct RecordLine {
+    wallet:   String,
+    #[serde(default = "default_currency")]
+    currency: String,
+    /// ciphertext as a decimal string; `n²` comes from the key
+    c:        String,
+    /// set on held sub-balance entries; absent on older lines
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    held:     bool,
+}
+
+/// One wallet's append‐only history of net-balance ciphertexts
+/// in a single currency, plus its held sub-balance: funds reserved by
+/// `/hold` and not yet captured or released
+#[derive(Debug)]
+pub struct Wallet {
+    name:     String,
+    currency: String,
+    history:  Vec<PaillierCiphertext>,
+    held:     Vec<PaillierCiphertext>,
+}
+
+impl Wallet {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// All entries, oldest first
+    pub fn history(&self) -> &[PaillierCiphertext] {
+        &self.history
+    }
+
+    /// Latest net-balance ciphertext, if any
+    pub fn latest(&self) -> Option<&PaillierCiphertext> {
+        self.history.last()
+    }
+
+    /// Latest held sub-balance ciphertext, if anything was ever held
+    pub fn latest_held(&self) -> Option<&PaillierCiphertext> {
+        self.held.last()
+    }
+}
+
+/// Shared handle to a wallet; lock it for the whole read‐modify‐append
+pub type WalletHandle = Arc<Mutex<Wallet>>;
+
+/// Wallets are keyed by `(wallet, currency)`; each currency keeps its
+/// own running balance
+type WalletKey = (String, String);
+
+/// Append‐only ledger, optionally mirrored to a file (JSONL, or binary
+/// per `LedgerFormat`).
+///
+/// Each wallet has its own lock, so operations on different wallets run
+/// concurrently; the outer `RwLock` is only written when a wallet is
+/// first created. Code locking several wallets must lock them in
+/// ascending `(name, currency)` order (see `with_wallet_pair`) to avoid
+/// deadlocks. The file lock is always taken after any wallet locks.
+#[derive(Default)]
+pub struct Ledger {
+    wallets:     RwLock<HashMap<WalletKey, WalletHandle>>,
+    path:        Option<PathBuf>,
+    format:      LedgerFormat,
+    file:        Mutex<Option<File>>,
+    /// length of the file just past the last record written; only
+    /// changed while holding the file lock
+    offset:      AtomicU64,
+    /// most distinct wallet names the ledger will add
+    max_wallets: Option<usize>,
+    /// held while adding a wallet; see `with_wallet`
+    creating:    Mutex<()>,
+}
+
+/// The ledger refused to add a wallet: `max` already exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletLimitError {
+    pub max: usize,
+}
+
+impl fmt::Display for WalletLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the ledger already holds the maximum of {} wallets", self.max)
+    }
+}
+
+impl std::error::Error for WalletLimitError {}
+
+/// What `Ledger::check` found wrong with the file
+#[derive(Debug)]
+pub enum IntegrityError {
+    /// the file, or the directory it's in, can't be written to or read,
+    /// e.g. because the disk is full
+    Unwritable(io::Error),
+    /// the file's length isn't where the last write left it: a write
+    /// failed partway, or something else changed the file
+    Mismatch { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::Unwritable(e) => write!(f, "the ledger file is not writable: {e}"),
+            IntegrityError::Mismatch { expected, actual } => write!(
+                f,
+                "the ledger file is {actual} bytes, but its last record ends at byte {expected}",
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+impl Ledger {
+    /// Open (or create) the JSONL file at `path`, replaying any existing
+    /// lines into memory. Every later append is written through to it.
+    pub fn open(path: &Path, n_squared: &BigUint) -> io::Result<Self> {
+        Ledger::open_with_format(path, n_squared, LedgerFormat::Jsonl)
+    }
+
+    /// Like `open`, for a file in `format`. The format isn't detected, so
+    /// switching an existing ledger's format means converting its file.
+    pub fn open_with_format(path: &Path, n_squared: &BigUint, format: LedgerFormat) -> io::Result<Self> {
+        let mut wallets: HashMap<WalletKey, Wallet> = HashMap::new();
+        if path.exists() {
+            for (i, entry) in read_entries(path, format)?.iter().enumerate() {
+                let rec = decode(entry, format, n_squared).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: record {}: {e}", path.display(), i + 1),
+                    )
+                })?;
+                let wallet = wallets.entry((rec.wallet.clone(), rec.currency.clone()))
+                                    .or_insert_with(|| Wallet {
+                                        name:     rec.wallet,
+                                        currency: rec.currency,
+                                        history:  Vec::new(),
+                                        held:     Vec::new(),
+                                    });
+                if rec.held {
+                    wallet.held.push(rec.ct);
+                } else {
+                    wallet.history.push(rec.ct);
+                }
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let offset = file.metadata()?.len();
+        Ok(Ledger {
+            wallets: RwLock::new(
+                wallets.into_iter()
+                       .map(|(k, w)| (k, Arc::new(Mutex::new(w))))
+                       .collect(),
+            ),
+            path:        Some(path.to_path_buf()),
+            format,
+            file:        Mutex::new(Some(file)),
+            offset:      AtomicU64::new(offset),
+            max_wallets: None,
+            creating:    Mutex::new(()),
+        })
+    }
+
+    /// Handle to `wallet`'s `currency` balance, if it has ever been created
+    pub fn get(&self, wallet: &str, currency: &str) -> Option<WalletHandle> {
+        self.wallets.read().unwrap()
+            .get(&(wallet.to_string(), currency.to_string()))
+            .cloned()
+    }
+
+    /// Handle to `wallet`'s `currency` balance, creating it empty if
+    /// needed. Creating one counts against the `with_max_wallets` cap as
+    /// soon as it's made, so `with_wallet`, which only adds a wallet once
+    /// something is written to it, suits anything that may fail.
+    pub fn wallet(&self, wallet: &str, currency: &str) -> Result<WalletHandle, WalletLimitError> {
+        if let Some(handle) = self.get(wallet, currency) {
+            return Ok(handle);
+        }
+        let _creating = self.creating.lock().unwrap();
+        let (handle, created) = self.get_or_make(wallet, currency);
+        if created {
+            self.check_cap(&[wallet])?;
+            self.insert(handle.clone());
+        }
+        Ok(handle)
+    }
+
+    /// Cap how many distinct wallet names, across all currencies, the
+    /// ledger adds, bounding memory. Wallets already in the file still
+    /// load, even past the cap.
+    pub fn with_max_wallets(mut self, max: usize) -> Self {
+        self.max_wallets = Some(max);
+        self
+    }
+
+    /// Run `f` on `wallet`'s `currency` balance, locked. A wallet that
+    /// doesn't exist yet is only added to the ledger if `f` leaves
+    /// entries in it, so an operation that fails creates nothing; past
+    /// the `with_max_wallets` cap, a new wallet name is refused before
+    /// `f` runs.
+    pub fn with_wallet<T, E: From<WalletLimitError>>(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        f:        impl FnOnce(&mut Wallet) -> Result<T, E>,
+    ) -> Result<T, E> {
+        if let Some(handle) = self.get(wallet, currency) {
+            return f(&mut handle.lock().unwrap());
+        }
+        // creations take turns, so none can slip in between seeing the
+        // wallet missing and adding it
+        let _creating = self.creating.lock().unwrap();
+        let (handle, created) = self.get_or_make(wallet, currency);
+        if created {
+            self.check_cap(&[wallet])?;
+        }
+        let out = f(&mut handle.lock().unwrap());
+        if created {
+            self.insert_if_written(handle);
+        }
+        out
+    }
+
+    /// `with_wallet` for two distinct wallets in `currency`, locked in
+    /// ascending name order so concurrent pairs can't deadlock
+    pub fn with_wallet_pair<T, E: From<WalletLimitError>>(
+        &self,
+        a:        &str,
+        b:        &str,
+        currency: &str,
+        f:        impl FnOnce(&mut Wallet, &mut Wallet) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let locked = |handle_a: &WalletHandle, handle_b: &WalletHandle| {
+            let (first, second) = if a <= b { (handle_a, handle_b) } else { (handle_b, handle_a) };
+            let (mut first, mut second) = (first.lock().unwrap(), second.lock().unwrap());
+            if a <= b { f(&mut first, &mut second) } else { f(&mut second, &mut first) }
+        };
+        if let (Some(handle_a), Some(handle_b)) = (self.get(a, currency), self.get(b, currency)) {
+            return locked(&handle_a, &handle_b);
+        }
+
+        let _creating = self.creating.lock().unwrap();
+        let (handle_a, created_a) = self.get_or_make(a, currency);
+        let (handle_b, created_b) = self.get_or_make(b, currency);
+        let new: Vec<&str> = [(a, created_a), (b, created_b)].into_iter()
+            .filter_map(|(name, created)| created.then_some(name))
+            .collect();
+        self.check_cap(&new)?;
+        let out = locked(&handle_a, &handle_b);
+        for (handle, created) in [(handle_a, created_a), (handle_b, created_b)] {
+            if created {
+                self.insert_if_written(handle);
+            }
+        }
+        out
+    }
+
+    /// The wallet's handle, or one for a new wallet not yet in the ledger
+    /// (flagged `true`). Call it holding `creating`.
+    fn get_or_make(&self, wallet: &str, currency: &str) -> (WalletHandle, bool) {
+        match self.get(wallet, currency) {
+            Some(handle) => (handle, false),
+            None         => (new_wallet(wallet, currency), true),
+        }
+    }
+
+    /// Refuse to add the wallets `names` if that would take the ledger
+    /// past `with_max_wallets` distinct names. Call it holding `creating`.
+    fn check_cap(&self, names: &[&str]) -> Result<(), WalletLimitError> {
+        let Some(max) = self.max_wallets else {
+            return Ok(());
+        };
+        let wallets = self.wallets.read().unwrap();
+        let mut known: HashSet<&str> = wallets.keys().map(|(name, _)| name.as_str()).collect();
+        let before = known.len();
+        known.extend(names);
+        if known.len() > before && known.len() > max {
+            return Err(WalletLimitError { max });
+        }
+        Ok(())
+    }
+
+    /// Add a wallet `with_wallet` made, if anything was written to it
+    fn insert_if_written(&self, handle: WalletHandle) {
+        let written = {
+            let wallet = handle.lock().unwrap();
+            !wallet.history.is_empty() || !wallet.held.is_empty()
+        };
+        if written {
+            self.insert(handle);
+        }
+    }
+
+    fn insert(&self, handle: WalletHandle) {
+        let key = {
+            let wallet = handle.lock().unwrap();
+            (wallet.name.clone(), wallet.currency.clone())
+        };
+        self.wallets.write().unwrap().insert(key, handle);
+    }
+
+    /// Does `wallet` have entries in any currency? Locks each of its
+    /// wallets in turn, so call it holding none of them.
+    pub fn has_entries(&self, wallet: &str) -> bool {
+        let handles: Vec<_> = self.wallets.read().unwrap().iter()
+            .filter(|((name, _), _)| name == wallet)
+            .map(|(_, handle)| handle.clone())
+            .collect();
+        handles.iter().any(|handle| {
+            let wallet = handle.lock().unwrap();
+            !wallet.history.is_empty() || !wallet.held.is_empty()
+        })
+    }
+
+    /// Snapshot of every wallet handle, in lock order (ascending
+    /// `(name, currency)`), so they can all be locked in turn
+    pub fn wallets(&self) -> Vec<WalletHandle> {
+        let wallets = self.wallets.read().unwrap();
+        let mut entries: Vec<_> = wallets.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter().map(|(_, handle)| handle.clone()).collect()
+    }
+
+    /// Append `ct` to a locked wallet, persisting it before it becomes
+    /// visible in memory.
+    pub fn append(&self, wallet: &mut Wallet, ct: PaillierCiphertext) -> io::Result<()> {
+        self.append_all(vec![(wallet, ct)])
+    }
+
+    /// Append to several locked wallets as one unit: the records are
+    /// written to disk in a single write, and none become visible in
+    /// memory if that fails.
+    pub fn append_all(&self, updates: Vec<(&mut Wallet, PaillierCiphertext)>) -> io::Result<()> {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let mut buf = Vec::new();
+            for (wallet, ct) in &updates {
+                buf.extend(self.encode(wallet, ct, false)?);
+            }
+            // a single write keeps each batch of records intact on disk
+            write_records(file, self.offset.load(Ordering::Acquire), &buf)?;
+            self.offset.fetch_add(buf.len() as u64, Ordering::Release);
+        }
+        for (wallet, ct) in updates {
+            wallet.history.push(ct);
+        }
+        Ok(())
+    }
+
+    /// Append `held` to a locked wallet's held sub-balance and, if given,
+    /// `balance` to its balance, in a single write as in `append_all`.
+    pub fn append_held(
+        &self,
+        wallet:  &mut Wallet,
+        balance: Option<PaillierCiphertext>,
+        held:    PaillierCiphertext,
+    ) -> io::Result<()> {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let mut buf = Vec::new();
+            if let Some(ct) = &balance {
+                buf.extend(self.encode(wallet, ct, false)?);
+            }
+            buf.extend(self.encode(wallet, &held, true)?);
+            write_records(file, self.offset.load(Ordering::Acquire), &buf)?;
+            self.offset.fetch_add(buf.len() as u64, Ordering::Release);
+        }
+        wallet.history.extend(balance);
+        wallet.held.push(held);
+        Ok(())
+    }
+
+    /// Flush the file to disk, e.g. before shutting down
+    pub fn sync(&self) -> io::Result<()> {
+        match self.file.lock().unwrap().as_ref() {
+            Some(file) => file.sync_all(),
+            None       => Ok(()),
+        }
+    }
+
+    /// Check that the file can still be written to, and that it ends
+    /// where the last record written to it did, so memory and disk agree.
+    /// Syncs the file and writes (then removes) a scratch file next to
+    /// it, so a full disk fails here before it fails a request. A ledger
+    /// with no file always passes.
+    pub fn check(&self) -> Result<(), IntegrityError> {
+        let file = self.file.lock().unwrap();
+        let (Some(path), Some(file)) = (&self.path, file.as_ref()) else {
+            return Ok(());
+        };
+        file.sync_all().map_err(IntegrityError::Unwritable)?;
+
+        let mut probe = path.as_os_str().to_owned();
+        probe.push(".probe");
+        let written = File::create(&probe).and_then(|mut out| {
+            out.write_all(&[0; 4096])?;
+            out.sync_all()
+        });
+        let removed = fs::remove_file(&probe);
+        written.and(removed).map_err(IntegrityError::Unwritable)?;
+
+        let expected = self.offset.load(Ordering::Acquire);
+        let actual   = fs::metadata(path).map_err(IntegrityError::Unwritable)?.len();
+        if actual != expected {
+            return Err(IntegrityError::Mismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Replace a locked wallet's whole history with the single entry `ct`,
+    /// returning how many entries were dropped. The held sub-balance
+    /// likewise keeps only its latest entry.
+    ///
+    /// The file is rewritten without the wallet's old records and swapped
+    /// in with a rename, so a crash leaves either the old or the new
+    /// ledger on disk, never a mix.
+    pub fn compact(&self, wallet: &mut Wallet, ct: PaillierCiphertext) -> io::Result<usize> {
+        let mut file = self.file.lock().unwrap();
+        if let (Some(path), Some(_)) = (&self.path, file.as_ref()) {
+            let mut buf = Vec::new();
+            for entry in read_entries(path, self.format)? {
+                // records that don't parse are kept as they are
+                let keep = match decode(&entry, self.format, &ct.n_squared) {
+                    Ok(rec) => rec.wallet != wallet.name || rec.currency != wallet.currency,
+                    Err(_)  => true,
+                };
+                if keep {
+                    buf.extend(frame(&entry, self.format));
+                }
+            }
+            buf.extend(self.encode(wallet, &ct, false)?);
+            if let Some(held) = wallet.latest_held() {
+                buf.extend(self.encode(wallet, held, true)?);
+            }
+            *file = Some(replace_file(path, &buf)?);
+            self.offset.store(buf.len() as u64, Ordering::Release);
+        }
+        let removed = wallet.history.len().saturating_sub(1);
+        wallet.history = vec![ct];
+        wallet.held.drain(..wallet.held.len().saturating_sub(1));
+        Ok(removed)
+    }
+
+    /// Replace the whole ledger: each locked wallet's history becomes its
+    /// single new balance entry (and its held sub-balance the given held
+    /// entry, if any), and the file is rewritten to hold exactly those
+    /// records (swapped in with a rename, as in `compact`). Used when
+    /// every ciphertext changes at once, e.g. on key rotation.
+    pub fn replace_all(
+        &self,
+        updates: Vec<(&mut Wallet, PaillierCiphertext, Option<PaillierCiphertext>)>,
+    ) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        if let (Some(path), Some(_)) = (&self.path, file.as_ref()) {
+            let mut buf = Vec::new();
+            for (wallet, ct, held) in &updates {
+                buf.extend(self.encode(wallet, ct, false)?);
+                if let Some(held) = held {
+                    buf.extend(self.encode(wallet, held, true)?);
+                }
+            }
+            *file = Some(replace_file(path, &buf)?);
+            self.offset.store(buf.len() as u64, Ordering::Release);
+        }
+        for (wallet, ct, held) in updates {
+            wallet.history = vec![ct];
+            wallet.held = held.into_iter().collect();
+        }
+        Ok(())
+    }
+
+    /// One record in the file's format, ready to append: recording `ct`
+    /// for `wallet`'s balance, or its held sub-balance if `held`
+    fn encode(&self, wallet: &Wallet, ct: &PaillierCiphertext, held: bool) -> io::Result<Vec<u8>> {
+        match self.format {
+            LedgerFormat::Jsonl  => Ok(record_line(wallet, ct, held)?.into_bytes()),
+            LedgerFormat::Binary => {
+                let rec = Record {
+                    wallet:   wallet.name.clone(),
+                    currency: wallet.currency.clone(),
+                    ct:       ct.clone(),
+                    held,
+                };
+                Ok(frame(&rec.to_bytes(), LedgerFormat::Binary))
+            }
+        }
+    }
+}
+
+/// Handle to a new, empty wallet
+fn new_wallet(wallet: &str, currency: &str) -> WalletHandle {
+    Arc::new(Mutex::new(Wallet {
+        name:     wallet.to_string(),
+        currency: currency.to_string(),
+        history:  Vec::new(),
+        held:     Vec::new(),
+    }))
+}
+
+/// The records in the file at `path`, unparsed: its non-empty lines
+/// (without the newline), or its binary frames (without the length)
+fn read_entries(path: &Path, format: LedgerFormat) -> io::Result<Vec<Vec<u8>>> {
+    match format {
+        LedgerFormat::Jsonl => BufReader::new(File::open(path)?)
+            .lines()
+            .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()))
+            .map(|line| line.map(String::into_bytes))
+            .collect(),
+        LedgerFormat::Binary => {
+            let bytes = fs::read(path)?;
+            let mut rest = bytes.as_slice();
+            let mut entries = Vec::new();
+            while !rest.is_empty() {
+                let entry = read_prefixed(&mut rest).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: truncated record {}", path.display(), entries.len() + 1),
+                    )
+                })?;
+                entries.push(entry.to_vec());
+            }
+            Ok(entries)
+        }
+    }
+}
+
+/// `entry` as stored in a `format` file: a line, or a length-prefixed frame
+fn frame(entry: &[u8], format: LedgerFormat) -> Vec<u8> {
+    let mut out = Vec::new();
+    match format {
+        LedgerFormat::Jsonl => {
+            out.extend_from_slice(entry);
+            out.push(b'\n');
+        }
+        LedgerFormat::Binary => write_prefixed(&mut out, entry),
+    }
+    out
+}
+
+/// Parse one entry from `read_entries`
+fn decode(entry: &[u8], format: LedgerFormat, n_squared: &BigUint) -> Result<Record, String> {
+    match format {
+        LedgerFormat::Jsonl => {
+            let line = std::str::from_utf8(entry).map_err(|e| e.to_string())?;
+            parse_line(line, n_squared)
+        }
+        LedgerFormat::Binary => Record::from_bytes(entry, n_squared),
+    }
+}
+
+/// One JSONL line, newline included, recording `ct` for `wallet`'s
+/// balance, or its held sub-balance if `held`
+fn record_line(wallet: &Wallet, ct: &PaillierCiphertext, held: bool) -> io::Result<String> {
+    let mut line = serde_json::to_string(&RecordLine {
+        wallet:   wallet.name.clone(),
+        currency: wallet.currency.clone(),
+        c:        ct.c.to_str_radix(10),
+        held,
+    })?;
+    line.push('\n');
+    Ok(line)
+}
+
+/// Helper: append `buf` to `file`, whose last record ends at `offset`.
+/// A write that fails partway is cut back to `offset`, so the next
+/// append doesn't land after a torn record the ledger couldn't reload.
+fn write_records(file: &mut File, offset: u64, buf: &[u8]) -> io::Result<()> {
+    let written = file.write_all(buf).and_then(|()| file.flush());
+    if written.is_err() {
+        // if this fails too, the file stays longer than `offset` and
+        // `check` reports the mismatch
+        let _ = file.set_len(offset).and_then(|()| file.seek(SeekFrom::Start(offset)));
+    }
+    written
+}
+
+/// Atomically replace the file at `path` with `contents`, returning it
+/// reopened for appending
+fn replace_file(path: &Path, contents: &[u8]) -> io::Result<File> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut out = File::create(&tmp)?;
+    out.write_all(contents)?;
+    out.sync_all()?;
+    fs::rename(&tmp, path)?;
+    OpenOptions::new().append(true).open(path)
+}
+
+fn parse_line(line: &str, n_squared: &BigUint) -> Result<Record, String> {
+    let rec: RecordLine = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let c = BigUint::parse_bytes(rec.c.as_bytes(), 10)
+        .ok_or("`c` is not a decimal integer")?;
+    Ok(Record {
+        wallet:   rec.wallet,
+        currency: rec.currency,
+        ct:       PaillierCiphertext::new(c, n_squared.clone()),
+        held:     rec.held,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::StoreError;
+
+    /// A stand-in ciphertext; the ledger never looks inside one
+    fn ct(c: u32) -> PaillierCiphertext {
+        PaillierCiphertext::new(BigUint::from(c), BigUint::from(1_000_003u32))
+    }
+
+    #[test]
+    fn has_entries_ignores_empty_wallets() {
+        let ledger = Ledger::default();
+        ledger.wallet("alice", "USD").unwrap();
+        assert!(!ledger.has_entries("alice"));
+
+        let handle = ledger.wallet("alice", "EUR").unwrap();
+        ledger.append(&mut handle.lock().unwrap(), ct(7)).unwrap();
+        assert!(ledger.has_entries("alice"));
+        assert!(!ledger.has_entries("bob"));
+    }
+
+    /// Helper: the `c` of each wallet's latest entry and of its history's
+    /// last, which must agree
+    fn tails(ledger: &Ledger) -> Vec<(String, String, Option<BigUint>)> {
+        ledger.wallets().iter().map(|handle| {
+            let wallet = handle.lock().unwrap();
+            let latest = wallet.latest().map(|ct| ct.c.clone());
+            assert_eq!(latest, wallet.history().last().map(|ct| ct.c.clone()), "{}/{}", wallet.name, wallet.currency);
+            (wallet.name.clone(), wallet.currency.clone(), latest)
+        }).collect()
+    }
+
+    #[test]
+    fn latest_tracks_interleaved_appends_and_transfers() {
+        let path = std::env::temp_dir().join(format!("ledger-latest-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let n_squared = BigUint::from(1_000_003u32);
+        let ledger = Ledger::open(&path, &n_squared).unwrap();
+
+        let wallets = [("alice", "USD"), ("bob", "USD"), ("alice", "EUR"), ("carol", "USD")];
+        for i in 0..200u32 {
+            let (name, currency) = wallets[i as usize % wallets.len()];
+            ledger.append(&mut ledger.wallet(name, currency).unwrap().lock().unwrap(), ct(i)).unwrap();
+            if i % 7 == 0 {
+                // a hold touches the held sub-balance without a new balance
+                let handle = ledger.wallet(name, currency).unwrap();
+                ledger.append_held(&mut handle.lock().unwrap(), None, ct(500_000 + i)).unwrap();
+            }
+        }
+        ledger.with_wallet_pair("bob", "alice", "USD", |bob, alice| {
+            Ok::<_, StoreError>(ledger.append_all(vec![(alice, ct(900_001)), (bob, ct(900_002))])?)
+        })
+        .unwrap();
+
+        let expected = vec![
+            ("alice".to_string(), "EUR".to_string(), Some(BigUint::from(198u32))),
+            ("alice".to_string(), "USD".to_string(), Some(BigUint::from(900_001u32))),
+            ("bob".to_string(),   "USD".to_string(), Some(BigUint::from(900_002u32))),
+            ("carol".to_string(), "USD".to_string(), Some(BigUint::from(199u32))),
+        ];
+        assert_eq!(tails(&ledger), expected);
+        assert_eq!(ledger.wallet("alice", "USD").unwrap().lock().unwrap().history().len(), 51);
+
+        // replaying the file rebuilds the same tails
+        drop(ledger);
+        let reopened = Ledger::open(&path, &n_squared).unwrap();
+        assert_eq!(tails(&reopened), expected);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn binary_ledgers_reload_the_same_records() {
+        let path = std::env::temp_dir().join(format!("ledger-binary-{}.bin", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let n_squared = BigUint::from(1_000_003u32);
+        let ledger = Ledger::open_with_format(&path, &n_squared, LedgerFormat::Binary).unwrap();
+        for (i, name) in ["alice", "bob", "alice"].into_iter().enumerate() {
+            ledger.append(&mut ledger.wallet(name, "USD").unwrap().lock().unwrap(), ct(1_000 + i as u32)).unwrap();
+        }
+        ledger.append(&mut ledger.wallet("alice", "EUR").unwrap().lock().unwrap(), ct(999_999)).unwrap();
+        let handle = ledger.wallet("bob", "USD").unwrap();
+        ledger.append_held(&mut handle.lock().unwrap(), Some(ct(7)), ct(8)).unwrap();
+        let expected = tails(&ledger);
+        drop((handle, ledger));
+
+        let bytes = fs::read(&path).unwrap();
+        assert_ne!(bytes.first(), Some(&b'{'));
+        let reopened = Ledger::open_with_format(&path, &n_squared, LedgerFormat::Binary).unwrap();
+        assert_eq!(tails(&reopened), expected);
+        let alice = reopened.wallet("alice", "USD").unwrap();
+        let alice = alice.lock().unwrap();
+        assert_eq!(alice.history().iter().map(|ct| ct.c.clone()).collect::<Vec<_>>(),
+                   [BigUint::from(1_000u32), BigUint::from(1_002u32)]);
+        let bob = reopened.wallet("bob", "USD").unwrap();
+        assert_eq!(bob.lock().unwrap().latest_held().unwrap().c, BigUint::from(8u32));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_cap_counts_distinct_wallet_names() {
+        let path = std::env::temp_dir().join(format!("ledger-cap-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let ledger = Ledger::open(&path, &BigUint::from(1_000_003u32)).unwrap().with_max_wallets(2);
+        let append = |wallet: &mut Wallet| Ok::<_, StoreError>(ledger.append(wallet, ct(1))?);
+        ledger.with_wallet("alice", "USD", append).unwrap();
+        // another currency is the same wallet
+        ledger.with_wallet("alice", "EUR", append).unwrap();
+        ledger.with_wallet("bob", "USD", append).unwrap();
+        let refused = ledger.with_wallet("carol", "USD", append);
+        assert!(matches!(refused, Err(StoreError::TooManyWallets(WalletLimitError { max: 2 }))));
+        assert_eq!(ledger.wallet("carol", "USD").unwrap_err(), WalletLimitError { max: 2 });
+        assert!(ledger.with_wallet_pair("alice", "carol", "USD", |_, _| Ok::<_, WalletLimitError>(())).is_err());
+        // existing wallets are still handed out
+        assert!(ledger.with_wallet("bob", "EUR", append).is_ok());
+        assert_eq!(ledger.wallets().len(), 4);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wallets_are_added_only_once_written_to() {
+        let ledger = Ledger::default().with_max_wallets(1);
+        // an operation that fails, or writes nothing, leaves no wallet behind
+        let failed = ledger.with_wallet("alice", "USD", |_| Err::<(), _>(StoreError::Io(io::ErrorKind::Other.into())));
+        assert!(failed.is_err());
+        ledger.with_wallet("bob", "USD", |_| Ok::<_, StoreError>(())).unwrap();
+        assert!(ledger.get("alice", "USD").is_none() && ledger.get("bob", "USD").is_none());
+
+        // so the cap is still free for a write that lands
+        ledger.with_wallet("bob", "USD", |wallet| Ok::<_, StoreError>(ledger.append_held(wallet, None, ct(3))?)).unwrap();
+        assert_eq!(ledger.get("bob", "USD").unwrap().lock().unwrap().latest_held().unwrap().c, BigUint::from(3u32));
+        assert!(ledger.with_wallet("alice", "USD", |_| Ok::<_, WalletLimitError>(())).is_err());
+    }
+
+    #[test]
+    fn check_catches_unwritable_and_changed_files() {
+        let path = std::env::temp_dir().join(format!("ledger-check-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let ledger = Ledger::open(&path, &BigUint::from(1_000_003u32)).unwrap();
+        ledger.append(&mut ledger.wallet("alice", "USD").unwrap().lock().unwrap(), ct(5)).unwrap();
+        assert!(ledger.check().is_ok());
+        assert!(Ledger::default().check().is_ok());
+
+        // a directory where the probe file goes stands in for a full disk
+        let mut probe = path.as_os_str().to_owned();
+        probe.push(".probe");
+        fs::create_dir(&probe).unwrap();
+        assert!(matches!(ledger.check(), Err(IntegrityError::Unwritable(_))));
+        fs::remove_dir(&probe).unwrap();
+        assert!(ledger.check().is_ok());
+
+        let expected = fs::metadata(&path).unwrap().len();
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"torn\":").unwrap();
+        match ledger.check() {
+            Err(IntegrityError::Mismatch { expected: e, actual }) => assert_eq!((e, actual), (expected, expected + 8)),
+            other => panic!("expected a mismatch, got {other:?}"),
+        }
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1 +1,3 @@
-pub mod paillier;
\ No newline at end of file
+pub mod paillier;
+pub mod ledger;
+pub mod store;
@@ -1,133 +1,2076 @@
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use once_cell::sync::Lazy;
+mod audit;
+mod auth;
+mod cache;
+mod cli;
+mod config;
+mod error;
+mod holds;
+mod idempotency;
+mod mask;
+mod metrics;
+mod openapi;
+mod oracle;
+mod pool;
+mod ratelimit;
+mod subscribe;
+mod tenant;
+mod wallet;
+
+use actix_web::error::JsonPayloadError;
+use actix_web::http::header::{EntityTag, ETag, Header, IfNoneMatch};
+use actix_web::dev::Payload;
+use actix_web::middleware::{from_fn, Compress, Condition};
+use actix_web::{web, App, FromRequest, HttpRequest, HttpResponse, HttpServer, Responder};
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info, instrument, warn};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLockReadGuard};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{Signed, Zero};
 
-use num_bigint::BigUint;
-use num_traits::{Zero, FromPrimitive};
+use num_prime::PrimalityTestConfig;
 
 use privacyserver::paillier::{
+    Amount,
+    Balance,
+    KeyGenError,
     PaillierKey,
+    PrimeKind,
     PaillierCiphertext,
+    CtProof,
+    DecryptError,
     encrypt,
-    homomorphic_addition,
+    encrypt_with_factor,
+    decrypt_crt,
+    decode_signed,
+    add_plaintext,
+    add_plaintext_with_policy,
+    OverflowPolicy,
+    homomorphic_subtraction,
+    rerandomize,
+    prove_decryption,
+    verify_ciphertext,
+    is_valid_ciphertext,
 };
+use privacyserver::ledger::{default_currency, Ledger};
+use privacyserver::store::{Account, LedgerStore};
+
+use config::Config;
+use error::ApiError;
+use ratelimit::RateLimiter;
+use tenant::Tenant;
+use wallet::normalize_wallet;
+
+/// Parsed once at the top of `main`
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+fn config() -> &'static Config {
+    CONFIG.get().expect("config is set before the server starts")
+}
 
-/// Single ledger entry, storing the raw ciphertext
-struct Record {
-    wallet: String,
-    ct:     PaillierCiphertext,
+/// The current tenant's append‐only ledger; the default tenant's is
+/// replayed from `config().ledger_path` in `main`
+fn ledger() -> &'static Ledger {
+    &tenant::current().ledger
 }
 
-/// In‐memory, append‐only ledger
-static LEDGER: Lazy<Mutex<Vec<Record>>> =
-    Lazy::new(|| Mutex::new(Vec::new()));
+/// The current tenant's Paillier keypair, loaded or generated when the
+/// tenant is opened and replaced by `/admin/rotate-key`
+fn key() -> Arc<PaillierKey> {
+    tenant::current().key.read().unwrap().clone()
+}
+
+/// The current tenant's rotation gate, held shared by every handler that
+/// uses the key and exclusively by a key rotation. A handler holding it
+/// sees the same key throughout, and a rotation waits for in-flight
+/// requests to finish under the old key. Each tenant has its own, so a
+/// rotation pauses only its tenant.
+fn rotation_gate() -> RwLockReadGuard<'static, ()> {
+    tenant::current().rotation.read().unwrap()
+}
 
-/// Generate one Paillier keypair on startup
-static KEY: Lazy<PaillierKey> = Lazy::new(|| {
-    // e.g. 2048-bit modulus; pick your size
-    PaillierKey::new(2048)
-});
+/// Result of `key_self_test` on the current key; `/healthz` reports it
+static KEY_HEALTHY: AtomicBool = AtomicBool::new(false);
 
-/// Helper: get the last encrypted balance for `wallet`,
-/// or an encryption of zero if none exists yet.
-fn last_balance(wallet: &str) -> PaillierCiphertext {
-    let ledger = LEDGER.lock().unwrap();
-    if let Some(rec) = ledger.iter().rev().find(|r| r.wallet == wallet) {
-        rec.ct.clone()
+/// Helper: does `key` round-trip an encryption of 1?
+fn key_self_test(key: &PaillierKey) -> bool {
+    let one = BigUint::from(1u32);
+    decrypt_crt(key, &encrypt(key, &one)).is_ok_and(|m| m == one)
+}
+
+/// Load the keypair from `path`, generating and saving a fresh one
+/// (or, with `--primes-file`, building it from the given primes) on
+/// first startup
+fn load_or_generate_key(path: &Path, primes_file: Option<&Path>) -> io::Result<PaillierKey> {
+    let primes = primes_file.map(read_primes).transpose()?;
+    if path.exists() {
+        let key = PaillierKey::from_json(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if primes.is_some_and(|(p, q)| key.reveal_primes() != (&p, &q)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the saved key was not built from --primes-file",
+            ));
+        }
+        return Ok(key);
+    }
+
+    let key = match primes {
+        Some((p, q)) => PaillierKey::from_primes(p, q),
+        None         => generate_key(),
+    };
+    let key = key.map_err(io::Error::other)?;
+    fs::write(path, key.to_json())?;
+    Ok(key)
+}
+
+/// Helper: the primes `p` and `q` from the first two lines of `path`
+fn read_primes(path: &Path) -> io::Result<(BigUint, BigUint)> {
+    let text = fs::read_to_string(path)?;
+    let mut lines = text.lines().map(|l| l.trim().parse::<BigUint>());
+    match (lines.next(), lines.next()) {
+        (Some(Ok(p)), Some(Ok(q))) => Ok((p, q)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "--primes-file must hold p and q as two decimal lines",
+        )),
+    }
+}
+
+/// Generate a keypair of `config().key_bits`, lighter-tested under `--dev`
+fn generate_key() -> Result<PaillierKey, KeyGenError> {
+    if config().dev {
+        warn!("--dev key generation uses a weakened primality test; don't use this key in production");
+        // one fixed-base round instead of the default five: plenty to
+        // reject composites in practice, but not a key to trust
+        let mut light = PrimalityTestConfig::default();
+        light.sprp_trials        = 1;
+        light.sprp_random_trials = 0;
+        PaillierKey::new_with_config(config().key_bits, PrimeKind::Standard, light)
     } else {
-        encrypt(&KEY, &BigUint::zero())
+        PaillierKey::new(config().key_bits)
+    }
+}
+
+/// Helper: the digest of the default tenant's admin token, read from
+/// `ADMIN_TOKEN`. When unset, its admin endpoints reject every request.
+fn root_admin_digest() -> Option<String> {
+    std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()).map(|t| auth::digest(&t))
+}
+
+/// Helper: does `req` carry the current tenant's admin token, as
+/// `Authorization: Bearer <token>`?
+fn is_admin(req: &HttpRequest) -> bool {
+    let Some(expected) = tenant::current().admin_digest.as_deref() else {
+        return false;
+    };
+    auth::bearer(req).is_some_and(|token| auth::secret_eq(&auth::digest(token), expected))
+}
+
+/// Helper: `Err(Unauthorized)` unless `req` carries the admin token
+fn require_admin(req: &HttpRequest) -> Result<(), ApiError> {
+    if is_admin(req) { Ok(()) } else { Err(ApiError::Unauthorized) }
+}
+
+/// Helper: `encrypt` under the server key, recording its duration. The
+/// randomness comes from the tenant's pool while it has any ready.
+fn timed_encrypt(m: &BigUint) -> PaillierCiphertext {
+    let start = Instant::now();
+    let key = key();
+    let ct = match tenant::current().randomness.pop(&key) {
+        Some(factor) => {
+            metrics::POOL_HITS.inc();
+            encrypt_with_factor(&*key, m, &factor)
+        }
+        None => {
+            metrics::POOL_MISSES.inc();
+            encrypt(&*key, m)
+        }
+    };
+    metrics::ENCRYPT_SECONDS.observe(start.elapsed());
+    ct
+}
+
+/// Helper: `decrypt_crt` under the server key, recording its duration.
+/// Repeat decryptions of the same `c` are answered from the tenant's
+/// cache, sized by `config().decrypt_cache` and cleared when the key
+/// rotates.
+fn timed_decrypt(ct: &PaillierCiphertext) -> Result<BigUint, DecryptError> {
+    let cache = &tenant::current().decrypt_cache;
+    if let Some(m) = cache.get(&ct.c) {
+        metrics::DECRYPT_HITS.inc();
+        return Ok(m);
+    }
+    metrics::DECRYPT_MISSES.inc();
+    let start = Instant::now();
+    let m = decrypt_crt(&key(), ct)?;
+    metrics::DECRYPT_SECONDS.observe(start.elapsed());
+    cache.insert(ct.c.clone(), m.clone());
+    Ok(m)
+}
+
+/// Helper: `Err(InvalidCiphertext)` (400) unless a client-supplied `ct`
+/// passes `is_valid_ciphertext`
+fn check_ciphertext(ct: &PaillierCiphertext, key: &PaillierKey) -> Result<(), ApiError> {
+    if is_valid_ciphertext(ct, key) {
+        return Ok(());
+    }
+    let why = if ct.c >= key.modulus { DecryptError::OutOfRange } else { DecryptError::Malformed };
+    debug!(c = %mask::ciphertext(&ct.c), %why, "rejected a client ciphertext");
+    Err(ApiError::InvalidCiphertext { why, c: Some(ct.c.clone()) })
+}
+
+/// The `LedgerStore` handlers read and write wallet balances through:
+/// the app's `web::Data<Arc<dyn LedgerStore>>` if it registered one (e.g.
+/// an `InMemoryLedger` in tests), else the current tenant's `ledger()`
+struct Store(Arc<dyn LedgerStore>);
+
+impl FromRequest for Store {
+    type Error  = actix_web::Error;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let store = match req.app_data::<web::Data<Arc<dyn LedgerStore>>>() {
+            Some(store) => Arc::clone(store.get_ref()),
+            None        => tenant::current().ledger.clone(),
+        };
+        std::future::ready(Ok(Store(store)))
+    }
+}
+
+impl std::ops::Deref for Store {
+    type Target = dyn LedgerStore;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
     }
 }
 
 /// Incoming transaction request now carries plaintext `amount`
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct TxRequest {
-    wallet: String,
-    amount: u64,
+    wallet:   String,
+    amount:   Amount,
+    #[serde(default = "default_currency")]
+    currency: String,
 }
 
 /// Response wrapping the new ciphertext
 #[derive(Serialize)]
 struct TxResponse {
-    wallet: String,
+    wallet:   String,
+    currency: String,
     /// the Paillier ciphertext of the new net balance, as a decimal string
-    c:      String,
+    c:        String,
+    /// the new entry's index in the wallet's history, so concurrent
+    /// responses can be ordered; restarts from 0 after `/compact`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq:      Option<usize>,
+}
+
+impl TxResponse {
+    /// Response for `ct`, appended as entry `seq` of the wallet's history
+    fn appended(wallet: &str, currency: &str, ct: &PaillierCiphertext, seq: Option<usize>, radix: Radix) -> Self {
+        TxResponse {
+            wallet:   wallet.to_string(),
+            currency: currency.to_string(),
+            c:        radix.format(&ct.c),
+            seq,
+        }
+    }
+}
+
+/// How ciphertexts are written in requests and responses: in base 10
+/// (the default) or 16, chosen with `?radix=`, or with `?encoding=base64`
+/// as the base64 of their little-endian bytes, the most compact
+#[derive(Debug, Clone, Copy)]
+enum Radix {
+    Base(u32),
+    Base64,
+}
+
+impl Default for Radix {
+    fn default() -> Self {
+        Radix::Base(10)
+    }
+}
+
+impl<'de> Deserialize<'de> for Radix {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "10"     => Ok(Radix::Base(10)),
+            "16"     => Ok(Radix::Base(16)),
+            "base64" => Ok(Radix::Base64),
+            other    => Err(serde::de::Error::custom(format!(
+                "radix must be 10 or 16, or encoding base64, got {other}"
+            ))),
+        }
+    }
+}
+
+impl Radix {
+    fn format(self, v: &BigUint) -> String {
+        match self {
+            Radix::Base(radix) => v.to_str_radix(radix),
+            Radix::Base64      => BASE64.encode(v.to_bytes_le()),
+        }
+    }
+
+    fn parse(self, field: &str, s: &str) -> Result<BigUint, ApiError> {
+        match self {
+            Radix::Base(radix) => BigUint::parse_bytes(s.as_bytes(), radix).ok_or_else(|| {
+                ApiError::InvalidRequest(format!("`{field}` is not a base-{radix} integer"))
+            }),
+            Radix::Base64 => {
+                let bytes = BASE64.decode(s).ok().filter(|b| !b.is_empty()).ok_or_else(|| {
+                    ApiError::InvalidRequest(format!("`{field}` is not valid base64"))
+                })?;
+                // ciphertexts, what this encoding is for, are all below n²
+                let v = BigUint::from_bytes_le(&bytes);
+                if v >= key().n_squared {
+                    return Err(ApiError::InvalidRequest(format!("`{field}` must be below n²")));
+                }
+                Ok(v)
+            }
+        }
+    }
+}
+
+/// Query string for endpoints whose only option is `radix`
+#[derive(Deserialize)]
+struct RadixQuery {
+    #[serde(default, alias = "encoding")]
+    radix: Radix,
+}
+
+/// A request's `Idempotency-Key` and what it was sent with
+struct Idem {
+    key:     String,
+    request: idempotency::Request,
+}
+
+/// Helper: the request's `Idempotency-Key` header, if any, for
+/// `operation` with `body`
+fn idempotency_key(req: &HttpRequest, operation: &'static str, body: &impl Serialize) -> Option<Idem> {
+    req.headers()
+        .get("Idempotency-Key")
+        .and_then(|v| v.to_str().ok())
+        .map(|key| Idem { key: key.to_string(), request: idempotency::Request::new(operation, body) })
+}
+
+/// Helper: the stored response if a locked `wallet` already processed
+/// `idem`, or 422 if its key came with a different request
+fn replay(wallet: &str, currency: &str, idem: Option<&Idem>) -> Result<Option<HttpResponse>, ApiError> {
+    let Some(idem) = idem else {
+        return Ok(None);
+    };
+    let body = tenant::current().idempotency
+        .get(wallet, currency, &idem.key, &idem.request)
+        .map_err(|e| ApiError::IdempotencyKeyReused { operation: e.operation })?;
+    Ok(body.map(|body| HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("Idempotent-Replayed", "true"))
+        .body(body)))
+}
+
+/// Helper: a 200 with `response`, recorded under `idem` for replays
+fn respond(wallet: &str, currency: &str, idem: Option<&Idem>, response: impl Serialize) -> HttpResponse {
+    if let Some(idem) = idem {
+        let body = serde_json::to_string(&response).expect("response serialization cannot fail");
+        tenant::current().idempotency.insert(wallet, currency, &idem.key, idem.request.clone(), body);
+    }
+    HttpResponse::Ok().json(response)
+}
+
+/// Path for per-wallet reads: `/{wallet}` or `/{wallet}/{currency}`
+#[derive(Deserialize)]
+struct AccountPath {
+    wallet:   String,
+    #[serde(default = "default_currency")]
+    currency: String,
+}
+
+impl AccountPath {
+    /// The path with its wallet in canonical form (see `normalize_wallet`)
+    fn normalized(self) -> Result<AccountPath, ApiError> {
+        Ok(AccountPath { wallet: normalize_wallet(&self.wallet)?, ..self })
+    }
+}
+
+/// Query string for `/credit` and `/debit`
+#[derive(Deserialize)]
+struct TxQuery {
+    #[serde(default, alias = "encoding")]
+    radix:   Radix,
+    /// preview the result without recording anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Would-be result of a `?dry_run=true` credit or debit
+#[derive(Serialize)]
+struct DryRunResponse {
+    wallet:   String,
+    currency: String,
+    /// what the new net-balance ciphertext would be
+    c:        String,
+    /// the would-be balance, for admin-token holders only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    balance:  Option<Balance>,
 }
 
 /// POST /credit
-/// { "wallet": "...", "amount": 100 }
-async fn credit(body: web::Json<TxRequest>) -> impl Responder {
-    // 1) turn the u64 into a BigUint
-    let m = BigUint::from_u64(body.amount).unwrap();
-
-    // 2) encrypt(m) then homomorphically add to prior balance
-    let ct_m    = encrypt(&KEY, &m);
-    let prev_ct = last_balance(&body.wallet);
-    let new_ct  = homomorphic_addition(&prev_ct, &ct_m, &KEY.n_squared);
-
-    // 3) append to ledger
-    LEDGER.lock().unwrap().push(Record {
-        wallet: body.wallet.clone(),
-        ct:     new_ct.clone(),
-    });
-
-    // 4) return the new net‐balance ciphertext
-    HttpResponse::Ok().json(TxResponse {
-        wallet: body.wallet.clone(),
-        c:      new_ct.c.to_str_radix(10),
+/// { "wallet": "...", "amount": 100, "currency": "USD" }
+/// `currency` is optional throughout and defaults to USD.
+/// With an `Idempotency-Key` header, a repeat of the same key on the
+/// same wallet returns the original response without crediting again;
+/// reusing the key for a different request or endpoint gets 422.
+/// `?dry_run=true` previews the credit instead (see `preview`).
+#[instrument(skip_all, fields(operation = "credit", wallet = %body.wallet))]
+async fn credit(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<TxQuery>,
+    body:  web::Json<TxRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::authorize(&req, &body.wallet)?;
+    if query.dry_run {
+        return preview(&req, &*store, &body, query.radix, false);
+    }
+    let idem = idempotency_key(&req, "credit", &*body);
+    credit_wallet(&*store, &body.wallet, &body.currency, body.amount.get(), idem.as_ref(), query.radix)
+}
+
+/// Incoming batch of credits for one wallet
+#[derive(Serialize, Deserialize)]
+struct BatchCreditRequest {
+    wallet:   String,
+    amounts:  Vec<Amount>,
+    #[serde(default = "default_currency")]
+    currency: String,
+}
+
+/// POST /credit/batch
+/// { "wallet": "...", "amounts": [100, 250, 5] }
+/// At most `--max-batch-len` amounts (1000 by default).
+/// Same net result as one `/credit` per amount, but costs a single
+/// encryption and appends a single ledger record. Takes an
+/// `Idempotency-Key` like `/credit`.
+#[instrument(skip_all, fields(operation = "credit_batch", wallet = %body.wallet))]
+async fn credit_batch(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<BatchCreditRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::authorize(&req, &body.wallet)?;
+    if body.amounts.is_empty() {
+        return Err(ApiError::EmptyBatch);
+    }
+    if body.amounts.len() > config().max_batch_len {
+        return Err(ApiError::BatchTooLarge { max: config().max_batch_len });
+    }
+
+    // sum the amounts in plaintext; BigUint so the total can't overflow
+    let m: BigUint = body.amounts.iter().map(Amount::get).sum();
+    let idem = idempotency_key(&req, "credit_batch", &*body);
+    credit_wallet(&*store, &body.wallet, &body.currency, &m, idem.as_ref(), query.radix)
+}
+
+/// Helper: add `m` to `wallet`'s `currency` balance and append the new
+/// entry, unless `idem` was already processed. A new balance past the
+/// signed range is rejected with 400, clamped, or let wrap, per
+/// `config().overflow_policy`.
+fn credit_wallet(
+    store:    &dyn LedgerStore,
+    wallet:   &str,
+    currency: &str,
+    m:        &BigUint,
+    idem:     Option<&Idem>,
+    radix:    Radix,
+) -> Result<HttpResponse, ApiError> {
+    let _gate = rotation_gate();
+    let key = key();
+    let wallet = normalize_wallet(wallet)?;
+
+    update_wallet(store, &wallet, currency, idem, radix, &metrics::CREDITS, |prev_ct| {
+        // 1) fold m straight into the prior balance; past max_plaintext it
+        //    would wrap into the range the signed encoding reads as
+        //    negative, so the policy decides. The decryption dominates, so
+        //    time it as one
+        let start = Instant::now();
+        let sum = add_plaintext_with_policy(&prev_ct, m, &key, config().overflow_policy);
+        metrics::DECRYPT_SECONDS.observe(start.elapsed());
+        let (sum, added) = sum?;
+        if &added < m {
+            warn!(currency, "credit saturated at the largest balance");
+        }
+
+        // 2) re-randomize so the stored entry can't be linked to the last one
+        Ok((rerandomize(&sum, &key), BigInt::from(added)))
     })
 }
 
+/// Helper: replace `wallet`'s `currency` balance through `store` with
+/// what `step` makes of it, and respond with the new ciphertext. `step`
+/// gets the latest ciphertext (an encryption of zero for a new wallet)
+/// and returns the new one and the plaintext change, for the audit.
+/// The wallet stays locked from the `idem` replay check until the entry
+/// is appended, counted in `counter` and published.
+fn update_wallet(
+    store:    &dyn LedgerStore,
+    wallet:   &str,
+    currency: &str,
+    idem:     Option<&Idem>,
+    radix:    Radix,
+    counter:  &metrics::Counter,
+    step:     impl FnOnce(PaillierCiphertext) -> Result<(PaillierCiphertext, BigInt), ApiError>,
+) -> Result<HttpResponse, ApiError> {
+    let mut step = Some(step);
+    // a replayed response, or why `step` refused
+    let mut early = None;
+    let change = Cell::new(BigInt::zero());
+    let mut response = None;
+
+    store.update(
+        wallet,
+        currency,
+        &mut |latest| {
+            let next = match replay(wallet, currency, idem) {
+                Ok(Some(original)) => Err(Ok(original)),
+                Ok(None)           => {
+                    let step = step.take().expect("update runs `next` once");
+                    step(latest.cloned().unwrap_or_else(|| timed_encrypt(&BigUint::zero()))).map_err(Err)
+                }
+                Err(e) => Err(Err(e)),
+            };
+            match next {
+                Ok((ct, delta)) => {
+                    change.set(delta);
+                    Some(ct)
+                }
+                Err(outcome) => {
+                    early = Some(outcome);
+                    None
+                }
+            }
+        },
+        &mut |ct, seq| {
+            audit::record(currency, change.take());
+            counter.inc();
+            subscribe::publish(wallet, currency, ct);
+            let tx = TxResponse::appended(wallet, currency, ct, Some(seq), radix);
+            response = Some(respond(wallet, currency, idem, tx));
+        },
+    )?;
+    match (early, response) {
+        (Some(outcome), _)     => outcome,
+        (None, Some(response)) => Ok(response),
+        (None, None)           => Err(ApiError::Internal("the ledger store skipped an update".into())),
+    }
+}
+
+/// A client-encrypted credit: `c = Enc(m; r)` with a proof that the
+/// client knows `m` and `r`
+#[derive(Serialize, Deserialize)]
+struct SubmitRequest {
+    wallet:   String,
+    c:        String,
+    proof:    SubmitProof,
+    #[serde(default = "default_currency")]
+    currency: String,
+}
+
+/// `CtProof` on the wire, as integer strings in the `?radix=` base
+#[derive(Serialize, Deserialize)]
+struct SubmitProof {
+    a:  String,
+    z1: String,
+    z2: String,
+}
+
+/// POST /submit
+/// { "wallet": "...", "c": "<decimal>", "proof": { "a": ..., "z1": ..., "z2": ... } }
+/// Credits the wallet with a ciphertext the client encrypted itself.
+/// Rejected with 400 unless the proof shows `c` is a genuine encryption,
+/// so a malformed value can't poison the wallet's homomorphic sum. The
+/// decrypted amount then goes through the same range check as `/credit`.
+/// Takes an `Idempotency-Key` like `/credit`.
+#[instrument(skip_all, fields(operation = "submit", wallet = %body.wallet))]
+async fn submit(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<SubmitRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::authorize(&req, &body.wallet)?;
+    let radix = query.radix;
+    let m = {
+        let _gate = rotation_gate();
+        let key = key();
+        let ct = PaillierCiphertext::new(radix.parse("c", &body.c)?, key.n_squared.clone());
+        check_ciphertext(&ct, &key)?;
+        let proof = CtProof {
+            a:  radix.parse("proof.a", &body.proof.a)?,
+            z1: radix.parse("proof.z1", &body.proof.z1)?,
+            z2: radix.parse("proof.z2", &body.proof.z2)?,
+        };
+        if !verify_ciphertext(&ct, &proof, &key) {
+            return Err(ApiError::InvalidProof);
+        }
+        timed_decrypt(&ct)?
+    };
+    let idem = idempotency_key(&req, "submit", &*body);
+    credit_wallet(&*store, &body.wallet, &body.currency, &m, idem.as_ref(), radix)
+}
+
 /// POST /debit
 /// { "wallet": "...", "amount": 40 }
-async fn debit(body: web::Json<TxRequest>) -> impl Responder {
-    let m = BigUint::from_u64(body.amount).unwrap();
+/// Rejected with 409 if it would leave the balance negative.
+/// Takes an `Idempotency-Key` and `?dry_run=true` like `/credit`.
+#[instrument(skip_all, fields(operation = "debit", wallet = %body.wallet))]
+async fn debit(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<TxQuery>,
+    body:  web::Json<TxRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::authorize(&req, &body.wallet)?;
+    if query.dry_run {
+        return preview(&req, &*store, &body, query.radix, true);
+    }
+    let idem = idempotency_key(&req, "debit", &*body);
+    let wallet = normalize_wallet(&body.wallet)?;
+    let _gate = rotation_gate();
+    let key = key();
+    let ct_m = timed_encrypt(body.amount.get());
+    let amount = BigInt::from(body.amount.get().clone());
+
+    // hold the wallet lock from the balance check through the append,
+    // so concurrent debits can't both pass against the same balance
+    update_wallet(&*store, &wallet, &body.currency, idem.as_ref(), query.radix, &metrics::DEBITS, |prev_ct| {
+        // the server holds the key, so it can check the prospective balance
+        let available = decode_signed(&timed_decrypt(&prev_ct)?, &key.n_s);
+        if available < amount {
+            metrics::OVERDRAFTS_REJECTED.inc();
+            // the span already names the wallet; the amounts are
+            // plaintexts, so they stay out of the log
+            warn!(currency = %body.currency, "overdraft rejected");
+            return Err(ApiError::InsufficientFunds {
+                attempted: body.amount.clone(),
+                available: available.into(),
+            });
+        }
+
+        // homomorphically subtract from prior balance
+        let new_ct = rerandomize(&homomorphic_subtraction(&prev_ct, &ct_m, &key)?, &key);
+        Ok((new_ct, -amount))
+    })
+}
+
+/// Helper: the ciphertext crediting (or, if `is_debit`, debiting)
+/// `body.amount` would give, rejected just as the real request would
+/// be. Nothing is recorded: `store`, idempotency keys, audit totals and
+/// subscribers are left alone, and it only reads the balance.
+fn preview(
+    req:      &HttpRequest,
+    store:    &dyn LedgerStore,
+    body:     &TxRequest,
+    radix:    Radix,
+    is_debit: bool,
+) -> Result<HttpResponse, ApiError> {
+    let wallet = normalize_wallet(&body.wallet)?;
+    let _gate = rotation_gate();
+    let key = key();
+    let m = body.amount.get();
+
+    // `latest`, not `update`: a preview mustn't create the wallet either
+    let prev_ct = store.latest(&wallet, &body.currency).unwrap_or_else(|| timed_encrypt(&BigUint::zero()));
+    let available = decode_signed(&timed_decrypt(&prev_ct)?, &key.n_s);
+
+    let (new_ct, balance) = if is_debit {
+        if available < BigInt::from(m.clone()) {
+            return Err(ApiError::InsufficientFunds {
+                attempted: body.amount.clone(),
+                available: available.into(),
+            });
+        }
+        let ct = homomorphic_subtraction(&prev_ct, &timed_encrypt(m), &key)?;
+        (ct, available - BigInt::from(m.clone()))
+    } else {
+        let (ct, added) = add_plaintext_with_policy(&prev_ct, m, &key, config().overflow_policy)?;
+        // under `Wrap` the sum may have wrapped into the negative range
+        let n = BigInt::from(key.n_s.clone());
+        let sum = ((available + BigInt::from(added)) % &n + &n) % &n;
+        (ct, decode_signed(&sum.magnitude().clone(), &key.n_s))
+    };
+    let new_ct = rerandomize(&new_ct, &key);
+
+    Ok(HttpResponse::Ok().json(DryRunResponse {
+        wallet,
+        currency: body.currency.clone(),
+        c:        radix.format(&new_ct.c),
+        balance:  is_admin(req).then(|| balance.into()),
+    }))
+}
+
+/// One step of a `/simulate` request
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum SimulatedOp {
+    Credit { amount: Amount },
+    Debit { amount: Amount },
+}
+
+/// POST /simulate/{wallet}[/{currency}]
+/// [{ "op": "credit", "amount": 50 }, { "op": "debit", "amount": 20 }]
+/// What-if analysis: folds the operations onto the wallet's current
+/// ciphertext and returns the result like a `?dry_run=true` response,
+/// with the balance only for admin-token holders. Nothing is recorded,
+/// and no overdraft or overflow check applies between steps.
+#[instrument(skip_all, fields(operation = "simulate", wallet = %path.wallet))]
+async fn simulate(
+    req:   HttpRequest,
+    store: Store,
+    path:  web::Path<AccountPath>,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<Vec<SimulatedOp>>,
+) -> Result<HttpResponse, ApiError> {
+    let AccountPath { wallet, currency } = path.into_inner().normalized()?;
+    let _gate = rotation_gate();
+    let key = key();
+
+    // 1) the net change, so the fold costs one `g^m` however many steps
+    let net: BigInt = body.iter()
+        .map(|op| match op {
+            SimulatedOp::Credit { amount } => BigInt::from(amount.get().clone()),
+            SimulatedOp::Debit { amount }  => -BigInt::from(amount.get().clone()),
+        })
+        .sum();
+    let n = BigInt::from(key.n_s.clone());
+    let shift = ((net % &n + &n) % &n).magnitude().clone();
+
+    // 2) apply it to the current balance, read as in `preview`
+    let prev_ct = store.latest(&wallet, &currency).unwrap_or_else(|| timed_encrypt(&BigUint::zero()));
+    let new_ct = rerandomize(&add_plaintext(&prev_ct, &shift, &key), &key);
+
+    let balance = if is_admin(&req) {
+        Some(decode_signed(&timed_decrypt(&new_ct)?, &key.n_s).into())
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(DryRunResponse {
+        wallet,
+        currency,
+        c: query.radix.format(&new_ct.c),
+        balance,
+    }))
+}
+
+/// Incoming transfer request between two wallets
+#[derive(Deserialize)]
+struct TransferRequest {
+    from:     String,
+    to:       String,
+    amount:   Amount,
+    #[serde(default = "default_currency")]
+    currency: String,
+}
+
+/// Response carrying both wallets' new ciphertexts
+#[derive(Serialize)]
+struct TransferResponse {
+    from: TxResponse,
+    to:   TxResponse,
+}
+
+/// POST /transfer
+/// { "from": "...", "to": "...", "amount": 25, "currency": "USD" }
+/// Rejected with 400 if the recipient's balance would pass the signed
+/// range, unless `--overflow-policy wrap`.
+#[instrument(skip_all, fields(operation = "transfer", from = %body.from, to = %body.to))]
+async fn transfer(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<TransferRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let TransferRequest { from, to, amount, currency } = body.into_inner();
+    let transfer = Transfer { from: &from, to: &to, currency: &currency, amount: &amount };
+    let response = transfer_funds(&req, &*store, transfer, query.radix, Cover::Unchecked)?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Incoming spend from one wallet to another
+#[derive(Deserialize)]
+struct SpendRequest {
+    wallet:   String,
+    to:       String,
+    amount:   Amount,
+    /// require the amount to be the wallet's whole balance, leaving no change
+    #[serde(default)]
+    exact:    bool,
+    #[serde(default = "default_currency")]
+    currency: String,
+}
+
+/// POST /spend
+/// { "wallet": "...", "to": "...", "amount": 25, "exact": false }
+/// A `/transfer` from `wallet` that first checks the balance covers
+/// `amount`, rejecting with 409 otherwise; the change stays in `wallet`.
+/// With `exact: true` the amount must be the whole balance. Responds
+/// like `/transfer`, with both new ciphertexts.
+#[instrument(skip_all, fields(operation = "spend", from = %body.wallet, to = %body.to))]
+async fn spend(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<SpendRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let SpendRequest { wallet, to, amount, exact, currency } = body.into_inner();
+    let cover = if exact { Cover::Exact } else { Cover::Amount };
+    let transfer = Transfer { from: &wallet, to: &to, currency: &currency, amount: &amount };
+    let response = transfer_funds(&req, &*store, transfer, query.radix, cover)?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// What the sender's balance must cover for `transfer_funds` to go ahead
+#[derive(Clone, Copy)]
+enum Cover {
+    /// nothing; the sender may be overdrawn
+    Unchecked,
+    /// at least the amount
+    Amount,
+    /// exactly the amount
+    Exact,
+}
+
+/// A transfer's parties and amount, as the client sent them
+#[derive(Clone, Copy)]
+struct Transfer<'a> {
+    from:     &'a str,
+    to:       &'a str,
+    currency: &'a str,
+    amount:   &'a Amount,
+}
+
+/// Helper: move the amount from one wallet to the other as one write
+/// through `store`, once the sender's balance passes the `cover` check
+fn transfer_funds(
+    req:      &HttpRequest,
+    store:    &dyn LedgerStore,
+    transfer: Transfer,
+    radix:    Radix,
+    cover:    Cover,
+) -> Result<TransferResponse, ApiError> {
+    let Transfer { from, to, currency, amount } = transfer;
+    // only the sender has to authorize; anyone may be paid
+    auth::authorize(req, from)?;
+    let (from, to) = (normalize_wallet(from)?, normalize_wallet(to)?);
+    if from == to {
+        return Err(ApiError::SameWallet);
+    }
+    let _gate = rotation_gate();
+    let key = key();
+
+    // 1) encrypt the amount before taking any lock
+    let ct_m = timed_encrypt(amount.get());
+
+    // 2) the store holds both wallets from reading their balances until
+    //    both records are appended
+    let mut failure = None;
+    let mut response = None;
+    store.update_pair(
+        &from,
+        &to,
+        currency,
+        &mut |from_ct, to_ct| {
+            let zero = || timed_encrypt(&BigUint::zero());
+            let from_ct = from_ct.cloned().unwrap_or_else(zero);
+            let to_ct   = to_ct.cloned().unwrap_or_else(zero);
+            match transfer_entries(&from_ct, &to_ct, currency, amount, &ct_m, &key, cover) {
+                Ok(entries) => Some(entries),
+                Err(e)      => {
+                    failure = Some(e);
+                    None
+                }
+            }
+        },
+        &mut |(from_ct, from_seq), (to_ct, to_seq)| {
+            response = Some(TransferResponse {
+                from: TxResponse::appended(&from, currency, from_ct, Some(from_seq), radix),
+                to:   TxResponse::appended(&to, currency, to_ct, Some(to_seq), radix),
+            });
+            subscribe::publish(&from, currency, from_ct);
+            subscribe::publish(&to, currency, to_ct);
+        },
+    )?;
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    metrics::TRANSFERS.inc();
+    response.ok_or_else(|| ApiError::Internal("the ledger store skipped a transfer".into()))
+}
+
+/// Helper: the sender's and recipient's new entries for moving `amount`
+/// (encrypted as `ct_m`) between balances `from_ct` and `to_ct`, once
+/// the sender passes the `cover` check
+fn transfer_entries(
+    from_ct:  &PaillierCiphertext,
+    to_ct:    &PaillierCiphertext,
+    currency: &str,
+    amount:   &Amount,
+    ct_m:     &PaillierCiphertext,
+    key:      &PaillierKey,
+    cover:    Cover,
+) -> Result<(PaillierCiphertext, PaillierCiphertext), ApiError> {
+    // 3) check the sender's balance, still under both locks
+    if let Cover::Amount | Cover::Exact = cover {
+        let available = decode_signed(&timed_decrypt(from_ct)?, &key.n_s);
+        let wanted = BigInt::from(amount.get().clone());
+        if available < wanted {
+            metrics::OVERDRAFTS_REJECTED.inc();
+            warn!(currency, "spend exceeds available funds");
+            return Err(ApiError::InsufficientFunds { attempted: amount.clone(), available: available.into() });
+        }
+        if let Cover::Exact = cover {
+            if available != wanted {
+                return Err(ApiError::InexactSpend { attempted: amount.clone(), available: available.into() });
+            }
+        }
+    }
+
+    // 4) the recipient's sum gets the same range check as `/credit`, but
+    //    can't be clamped: the sender would lose what didn't fit
+    let policy = match config().overflow_policy {
+        OverflowPolicy::Saturate => OverflowPolicy::Reject,
+        policy                   => policy,
+    };
+    let (to_ct, _) = add_plaintext_with_policy(to_ct, amount.get(), key, policy)?;
+    let from_ct = homomorphic_subtraction(from_ct, ct_m, key)?;
+    Ok((rerandomize(&from_ct, key), rerandomize(&to_ct, key)))
+}
+
+/// GET /net/{wallet}/{currency}, or /net/{wallet} for USD
+/// Returns `{ wallet: "...", currency: "...", c: "<decimal>" }` with a weak
+/// `ETag` for the ciphertext; a matching `If-None-Match` gets 304 and no
+/// body, so polling an idle wallet costs next to nothing.
+#[instrument(skip_all, fields(operation = "net", wallet = %path.wallet))]
+async fn get_net(
+    req:   HttpRequest,
+    store: Store,
+    path:  web::Path<AccountPath>,
+    query: web::Query<RadixQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let AccountPath { wallet, currency } = path.into_inner().normalized()?;
+    if let Some(interval) = config().refresh_on_read {
+        refresh_on_read(&*store, &wallet, &currency, interval)?;
+    }
+    let ct = store.latest(&wallet, &currency).ok_or(ApiError::WalletNotFound)?;
+
+    let tag = ciphertext_etag(&ct);
+    let unchanged = match IfNoneMatch::parse(&req) {
+        Ok(IfNoneMatch::Any)         => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&tag)),
+        Err(_)                       => false,
+    };
+    if unchanged {
+        return Ok(HttpResponse::NotModified().insert_header(ETag(tag)).finish());
+    }
+    Ok(HttpResponse::Ok().insert_header(ETag(tag)).json(TxResponse {
+        wallet,
+        currency,
+        c:   query.radix.format(&ct.c),
+        seq: None,
+    }))
+}
+
+/// Helper: weak ETag for `ct`, the first 16 bytes of SHA-256 over `c`
+fn ciphertext_etag(ct: &PaillierCiphertext) -> EntityTag {
+    let digest = Sha256::digest(ct.c.to_bytes_be());
+    EntityTag::new_weak(digest[..16].iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Helper: append a re-randomization of the account's latest ciphertext,
+/// so repeated reads can't be linked by an unchanged `c`. Skipped if the
+/// last refresh was under `interval` ago, which bounds the extra writes.
+fn refresh_on_read(
+    store:    &dyn LedgerStore,
+    wallet:   &str,
+    currency: &str,
+    interval: Duration,
+) -> Result<(), ApiError> {
+    // a read mustn't create the wallet
+    if store.latest(wallet, currency).is_none() {
+        return Ok(());
+    }
+    let account = (wallet.to_string(), currency.to_string());
+    let _gate = rotation_gate();
+    let key = key();
+
+    // `update` holds the wallet, which keeps two reads from both refreshing
+    let last_refresh = &tenant::current().last_refresh;
+    store.update(
+        wallet,
+        currency,
+        &mut |latest| {
+            let due = last_refresh.lock().unwrap().get(&account).is_none_or(|at| at.elapsed() >= interval);
+            latest.filter(|_| due).map(|ct| rerandomize(ct, &key))
+        },
+        &mut |_, _| {
+            last_refresh.lock().unwrap().insert(account.clone(), Instant::now());
+        },
+    )?;
+    Ok(())
+}
+
+/// Query string for `/net/all`
+#[derive(Deserialize)]
+struct NetAllQuery {
+    /// include each plaintext balance; requires the admin token
+    #[serde(default)]
+    decrypt: bool,
+    #[serde(default, alias = "encoding")]
+    radix:   Radix,
+}
+
+/// One wallet's latest entry in `/net/all`
+#[derive(Serialize)]
+struct NetEntry {
+    wallet:   String,
+    currency: String,
+    c:        String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    balance:  Option<Balance>,
+}
+
+/// GET /net/all[?decrypt=true]
+/// Latest ciphertext of every wallet with records, sorted by wallet then
+/// currency. `decrypt=true` adds the signed balances and needs
+/// `Authorization: Bearer <ADMIN_TOKEN>`.
+#[instrument(skip_all, fields(operation = "net_all"))]
+async fn get_net_all(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<NetAllQuery>,
+) -> Result<HttpResponse, ApiError> {
+    if query.decrypt {
+        require_admin(&req)?;
+    }
+    let _gate = rotation_gate();
+    let key = key();
+
+    // `accounts` copies out, so nothing stays locked while decrypting
+    let mut entries = Vec::new();
+    for Account { wallet, currency, latest, .. } in store.accounts() {
+        let Some(ct) = latest else { continue };
+        let balance = if query.decrypt {
+            Some(decode_signed(&timed_decrypt(&ct)?, &key.n_s).into())
+        } else {
+            None
+        };
+        entries.push(NetEntry { wallet, currency, c: query.radix.format(&ct.c), balance });
+    }
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// Query string for `/history`
+#[derive(Deserialize)]
+struct HistoryQuery {
+    #[serde(default)]
+    offset: usize,
+    limit:  Option<usize>,
+    #[serde(default, alias = "encoding")]
+    radix:  Radix,
+}
+
+const HISTORY_DEFAULT_LIMIT: usize = 50;
+const HISTORY_MAX_LIMIT:     usize = 500;
 
-    // to subtract, encrypt (n - m) which is equivalent to (-m mod n)
-    let neg_m  = &KEY.n - &m;
-    let ct_neg = encrypt(&KEY, &neg_m);
+/// One ledger entry in a history listing
+#[derive(Serialize)]
+struct HistoryEntry {
+    /// position in the wallet's history, starting at 0
+    index: usize,
+    c:     String,
+}
+
+/// GET /history/{wallet}[/{currency}]?offset=0&limit=50
+/// Returns the wallet's entries oldest first; `limit` is capped at 500.
+/// A wallet with no history yields `[]` rather than 404.
+#[instrument(skip_all, fields(operation = "history", wallet = %path.wallet))]
+async fn get_history(
+    store: Store,
+    path:  web::Path<AccountPath>,
+    query: web::Query<HistoryQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let AccountPath { wallet, currency } = path.into_inner().normalized()?;
+    let limit = query.limit.unwrap_or(HISTORY_DEFAULT_LIMIT).min(HISTORY_MAX_LIMIT);
+
+    let entries: Vec<HistoryEntry> = store.history(&wallet, &currency, query.offset, limit)
+        .iter()
+        .enumerate()
+        .map(|(i, ct)| HistoryEntry { index: query.offset + i, c: query.radix.format(&ct.c) })
+        .collect();
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// Response carrying a decrypted balance
+#[derive(Serialize)]
+struct BalanceResponse {
+    wallet:   String,
+    currency: String,
+    /// signed net balance; negative when the wallet is overdrawn
+    balance:  ScaledBalance,
+}
+
+/// A balance in whole units under `--scale`: the plain `Balance` when
+/// the scale is 1, otherwise a decimal string such as `"-12.30"`
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ScaledBalance {
+    Units(Balance),
+    Decimal(String),
+}
 
-    let prev_ct = last_balance(&body.wallet);
-    let new_ct  = homomorphic_addition(&prev_ct, &ct_neg, &KEY.n_squared);
+impl ScaledBalance {
+    fn new(minor: BigInt, scale: u64) -> Self {
+        if scale == 1 {
+            return ScaledBalance::Units(minor.into());
+        }
+        let sign = if minor.is_negative() { "-" } else { "" };
+        let scale_big = BigUint::from(scale);
+        let (whole, frac) = (minor.magnitude() / &scale_big, minor.magnitude() % &scale_big);
+        let places = scale.ilog10() as usize;
+        ScaledBalance::Decimal(format!("{sign}{whole}.{frac:0>places$}"))
+    }
+}
+
+/// POST /decrypt/{wallet}[/{currency}]
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Returns `{ wallet: "...", currency: "...", balance: <integer> }`, or
+/// with `--scale` the balance in whole units, e.g. `"12.34"`
+#[instrument(skip_all, fields(operation = "decrypt", wallet = %path.wallet))]
+async fn decrypt_balance(
+    req:   HttpRequest,
+    store: Store,
+    path:  web::Path<AccountPath>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    let _gate = rotation_gate();
+
+    let AccountPath { wallet, currency } = path.into_inner().normalized()?;
+    let ct = store.latest(&wallet, &currency).ok_or(ApiError::WalletNotFound)?;
+
+    let balance = ScaledBalance::new(decode_signed(&timed_decrypt(&ct)?, &key().n_s), config().scale);
+    Ok(HttpResponse::Ok().json(BalanceResponse { wallet, currency, balance }))
+}
+
+/// Wallet and minimum balance to check it against
+#[derive(Deserialize)]
+struct ThresholdRequest {
+    wallet:    String,
+    threshold: Amount,
+    #[serde(default = "default_currency")]
+    currency:  String,
+}
+
+#[derive(Serialize)]
+struct ThresholdResponse {
+    /// whether the balance is at least the threshold
+    ok: bool,
+}
 
-    LEDGER.lock().unwrap().push(Record {
-        wallet: body.wallet.clone(),
-        ct:     new_ct.clone(),
-    });
+/// Each `/check-threshold` answer leaks one bit of the balance, so a
+/// binary search could recover it; limited like `/decrypt-ciphertext`
+static THRESHOLD_LIMIT: Lazy<RateLimiter> = Lazy::new(|| RateLimiter::new(10, 1.0 / 6.0));
 
-    HttpResponse::Ok().json(TxResponse {
-        wallet: body.wallet.clone(),
-        c:      new_ct.c.to_str_radix(10),
+/// Helper: `Err(RateLimited)` unless `limit` has a token left for `req`'s
+/// client IP. A request with no peer address can't be counted, so it's
+/// refused too.
+fn rate_limit(req: &HttpRequest, limit: &RateLimiter) -> Result<(), ApiError> {
+    match req.peer_addr() {
+        Some(addr) if limit.allow(addr.ip()) => Ok(()),
+        _                                    => Err(ApiError::RateLimited),
+    }
+}
+
+/// POST /check-threshold
+/// { "wallet": "alice", "threshold": 100, "currency": "USD" (optional) }
+/// Returns `{ ok: balance >= threshold }`; the balance itself never leaves
+/// the server. Needs the wallet's API key, or the admin token for any
+/// wallet (including one with no key), since enough answers reveal the
+/// balance. Rate limited per client IP; 429 once the limit is hit.
+#[instrument(skip_all, fields(operation = "check_threshold", wallet = %body.wallet))]
+async fn check_threshold(
+    req:   HttpRequest,
+    store: Store,
+    body:  web::Json<ThresholdRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::authorize_owner(&req, &body.wallet)?;
+    rate_limit(&req, &THRESHOLD_LIMIT)?;
+
+    let _gate = rotation_gate();
+    let wallet = normalize_wallet(&body.wallet)?;
+    let ct = store.latest(&wallet, &body.currency).ok_or(ApiError::WalletNotFound)?;
+    let balance = decode_signed(&timed_decrypt(&ct)?, &key().n_s);
+    Ok(HttpResponse::Ok().json(ThresholdResponse { ok: balance >= BigInt::from(body.threshold.get().clone()) }))
+}
+
+/// Plaintext to encrypt under the server key
+#[derive(Deserialize)]
+struct EncryptRequest {
+    amount: Amount,
+}
+
+#[derive(Serialize)]
+struct CiphertextResponse {
+    c: String,
+}
+
+/// POST /encrypt
+/// { "amount": 100 }
+/// Returns a fresh encryption of `amount` under the server key.
+#[instrument(skip_all, fields(operation = "encrypt"))]
+async fn encrypt_amount(
+    query: web::Query<RadixQuery>,
+    body:  web::Json<EncryptRequest>,
+) -> impl Responder {
+    let _gate = rotation_gate();
+    let ct = timed_encrypt(body.amount.get());
+    HttpResponse::Ok().json(CiphertextResponse { c: query.radix.format(&ct.c) })
+}
+
+/// The public half of the server key, as decimal strings
+#[derive(Serialize)]
+struct PubkeyResponse {
+    n: String,
+    g: String,
+}
+
+/// GET /pubkey
+/// Everything a client needs to encrypt locally: `c = gᵐ · rⁿ mod n²`
+#[instrument(skip_all, fields(operation = "pubkey"))]
+async fn get_pubkey() -> impl Responder {
+    let key = key();
+    HttpResponse::Ok().json(PubkeyResponse {
+        n: key.n.to_str_radix(10),
+        g: key.g.to_str_radix(10),
     })
 }
 
-/// GET /net/{wallet}
-/// Returns `{ wallet: "...", c: "<decimal>" }`
-async fn get_net(path: web::Path<String>) -> impl Responder {
-    let wallet = path.into_inner();
-    let ledger = LEDGER.lock().unwrap();
-    if let Some(rec) = ledger.iter().rev().find(|r| r.wallet == wallet) {
-        HttpResponse::Ok().json(TxResponse {
-            wallet: wallet.clone(),
-            c:      rec.ct.c.to_str_radix(10),
+#[derive(Serialize)]
+struct FingerprintResponse {
+    /// hex SHA-256 of the public key `(n, g)`
+    fingerprint: String,
+}
+
+/// GET /pubkey/fingerprint
+/// Cheap check for clients caching `/pubkey`: a different fingerprint
+/// means the key has been rotated and the cached one is stale.
+#[instrument(skip_all, fields(operation = "pubkey_fingerprint"))]
+async fn get_pubkey_fingerprint() -> impl Responder {
+    HttpResponse::Ok().json(FingerprintResponse {
+        fingerprint: key().public_key().fingerprint(),
+    })
+}
+
+/// Client-computed ciphertext to decrypt
+#[derive(Deserialize)]
+struct CiphertextRequest {
+    /// integer string in the `?radix=` base (decimal by default); must be
+    /// a valid ciphertext under the server key
+    c: String,
+}
+
+#[derive(Serialize)]
+struct PlaintextResponse {
+    /// signed plaintext, as in `/decrypt`
+    plaintext: Balance,
+}
+
+/// `/decrypt-ciphertext` is a decryption oracle, so each client gets a
+/// burst of 10 calls and one more every 6 seconds after that
+static ORACLE_LIMIT: Lazy<RateLimiter> = Lazy::new(|| RateLimiter::new(10, 1.0 / 6.0));
+
+/// POST /decrypt-ciphertext
+/// { "c": "<decimal, or hex with ?radix=16>" }
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Decrypts a ciphertext the client built homomorphically, e.g. a sum of
+/// balances. Any wallet's ciphertext is public at `/net`, so this is as
+/// guarded as `/decrypt`, and rate limited per client IP on top: 429 once
+/// the limit is hit.
+#[instrument(skip_all, fields(operation = "decrypt_ciphertext"))]
+async fn decrypt_ciphertext(
+    req:   HttpRequest,
+    query: web::Query<RadixQuery>,
+    body:  web::Json<CiphertextRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    rate_limit(&req, &ORACLE_LIMIT)?;
+
+    let _gate = rotation_gate();
+    let key = key();
+    let c = query.radix.parse("c", &body.c)?;
+
+    let ct = PaillierCiphertext::new(c, key.n_squared.clone());
+    check_ciphertext(&ct, &key)?;
+    let plaintext = decode_signed(&timed_decrypt(&ct)?, &key.n_s).into();
+    Ok(HttpResponse::Ok().json(PlaintextResponse { plaintext }))
+}
+
+/// Decryption proof, as decimal strings
+#[derive(Serialize)]
+struct ProofBody {
+    a: String,
+    z: String,
+}
+
+/// Response carrying a balance plus a proof that it's correct
+#[derive(Serialize)]
+struct BalanceProofResponse {
+    wallet:   String,
+    currency: String,
+    balance:  Balance,
+    /// the ciphertext the proof is about
+    c:        String,
+    proof:    ProofBody,
+}
+
+/// GET /balance/proof/{wallet}[/{currency}]
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Returns the balance with a proof that `c` decrypts to it, which
+/// anyone holding the public key can check via `verify_decryption`.
+#[instrument(skip_all, fields(operation = "balance_proof", wallet = %path.wallet))]
+async fn balance_proof(
+    req:   HttpRequest,
+    store: Store,
+    path:  web::Path<AccountPath>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    Ok(HttpResponse::Ok().json(proven_balance(&store, path.into_inner())?))
+}
+
+/// GET /net/{wallet}/verified
+/// Mounted only with `--verified-net`, since it reveals the balance to
+/// any caller. The wallet's latest ciphertext, its balance, and a proof
+/// that one decrypts to the other, in a single round trip; the same
+/// response as `/balance/proof/{wallet}`.
+#[instrument(skip_all, fields(operation = "net_verified", wallet = %path.wallet))]
+async fn get_net_verified(
+    store: Store,
+    path:  web::Path<AccountPath>,
+) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(proven_balance(&store, path.into_inner())?))
+}
+
+/// Helper: the account's latest ciphertext and balance, with a proof of
+/// decryption tying them together
+fn proven_balance(store: &Store, path: AccountPath) -> Result<BalanceProofResponse, ApiError> {
+    let _gate = rotation_gate();
+    let key = key();
+
+    let AccountPath { wallet, currency } = path.normalized()?;
+    let ct = store.latest(&wallet, &currency).ok_or(ApiError::WalletNotFound)?;
+
+    let proof = prove_decryption(&key, &ct)?;
+    Ok(BalanceProofResponse {
+        wallet,
+        currency,
+        balance: decode_signed(&proof.m, &key.n_s).into(),
+        c:       ct.c.to_str_radix(10),
+        proof:   ProofBody {
+            a: proof.a.to_str_radix(10),
+            z: proof.z.to_str_radix(10),
+        },
+    })
+}
+
+/// Options for `/compact`
+#[derive(Deserialize)]
+struct CompactQuery {
+    /// re-randomize the surviving ciphertext so it can't be linked to
+    /// the entry it replaces
+    #[serde(default)]
+    rerandomize: bool,
+}
+
+#[derive(Serialize)]
+struct CompactResponse {
+    wallet:   String,
+    currency: String,
+    /// number of history entries dropped
+    removed:  usize,
+}
+
+/// POST /compact/{wallet}[/{currency}]?rerandomize=true
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Collapses the wallet's history to a single entry holding its current
+/// net ciphertext.
+#[instrument(skip_all, fields(operation = "compact", wallet = %path.wallet))]
+async fn compact(
+    req:   HttpRequest,
+    store: Store,
+    path:  web::Path<AccountPath>,
+    query: web::Query<CompactQuery>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    let _gate = rotation_gate();
+    let key = key();
+
+    // the store holds the wallet so no credit/debit lands between reading
+    // the latest entry and rewriting the history
+    let AccountPath { wallet, currency } = path.into_inner().normalized()?;
+    let removed = store.compact(&wallet, &currency, &mut |latest| {
+        if query.rerandomize { rerandomize(latest, &key) } else { latest.clone() }
+    })?;
+    Ok(HttpResponse::Ok().json(CompactResponse {
+        wallet,
+        currency,
+        removed: removed.ok_or(ApiError::WalletNotFound)?,
+    }))
+}
+
+/// POST /admin/reset/{wallet}[/{currency}]
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Appends a fresh encryption of zero, so the wallet's balance reads 0
+/// from then on; its history is kept. For tests and account closure;
+/// `/admin/audit` counts it as a debit of the old balance.
+#[instrument(skip_all, fields(operation = "reset", wallet = %path.wallet))]
+async fn reset_wallet(
+    req:   HttpRequest,
+    store: Store,
+    path:  web::Path<AccountPath>,
+    query: web::Query<RadixQuery>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    let _gate = rotation_gate();
+
+    let AccountPath { wallet, currency } = path.into_inner().normalized()?;
+    if store.latest(&wallet, &currency).is_none() {
+        return Err(ApiError::WalletNotFound);
+    }
+    let key = key();
+    let zero = timed_encrypt(&BigUint::zero());
+
+    // the zeroed balance leaves the books, as if debited
+    let change = Cell::new(BigInt::zero());
+    let mut failed = None;
+    let mut response = None;
+    store.update(
+        &wallet,
+        &currency,
+        &mut |latest| match timed_decrypt(latest?) {
+            Ok(prev) => {
+                change.set(-decode_signed(&prev, &key.n_s));
+                Some(zero.clone())
+            }
+            Err(e) => {
+                failed = Some(e);
+                None
+            }
+        },
+        &mut |ct, seq| {
+            audit::record(&currency, change.take());
+            subscribe::publish(&wallet, &currency, ct);
+            response = Some(TxResponse::appended(&wallet, &currency, ct, Some(seq), query.radix));
+        },
+    )?;
+    if let Some(e) = failed {
+        return Err(e.into());
+    }
+    response.ok_or(ApiError::WalletNotFound).map(|tx| HttpResponse::Ok().json(tx))
+}
+
+#[derive(Serialize)]
+struct RotateResponse {
+    /// number of wallets whose balance was re-encrypted
+    wallets: usize,
+    /// the new public modulus, as a decimal string
+    n:       String,
+}
+
+/// GET /healthz
+/// 200 once the key has passed its startup self-test, 503 otherwise
+#[instrument(skip_all, level = "debug", fields(operation = "healthz"))]
+async fn healthz() -> Result<HttpResponse, ApiError> {
+    if !KEY_HEALTHY.load(Ordering::Acquire) {
+        return Err(ApiError::NotReady);
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "ok" })))
+}
+
+/// GET /healthz/deep
+/// Like `/healthz`, and also runs `Ledger::check` on every tenant's
+/// ledger: 503 with the first failure's details if a ledger's file
+/// can't be written or doesn't end where memory expects
+#[instrument(skip_all, level = "debug", fields(operation = "healthz_deep"))]
+async fn healthz_deep() -> Result<HttpResponse, ApiError> {
+    if !KEY_HEALTHY.load(Ordering::Acquire) {
+        return Err(ApiError::NotReady);
+    }
+    web::block(|| {
+        tenant::all().into_iter().try_for_each(|tenant| {
+            tenant.ledger.check().map_err(|error| {
+                warn!(tenant = tenant.name.as_deref(), %error, "ledger failed its integrity check");
+                ApiError::LedgerUnhealthy { tenant: tenant.name.clone(), error }
+            })
         })
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Query string for `/admin/wallets`
+#[derive(Deserialize)]
+struct WalletsQuery {
+    /// list each wallet with its number of entries
+    #[serde(default)]
+    counts: bool,
+}
+
+/// One wallet in `/admin/wallets?counts=true`
+#[derive(Serialize)]
+struct WalletCount {
+    wallet:  String,
+    /// balance entries across all of the wallet's currencies
+    entries: usize,
+}
+
+/// GET /admin/wallets[?counts=true]
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Every wallet with ledger entries, in any currency, sorted: an array of
+/// identifiers, or with `counts=true` of `{ wallet, entries }` objects.
+#[instrument(skip_all, fields(operation = "list_wallets"))]
+async fn list_wallets(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<WalletsQuery>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for account in store.accounts() {
+        if account.entries > 0 {
+            *counts.entry(account.wallet).or_default() += account.entries;
+        }
+    }
+
+    if query.counts {
+        let entries: Vec<_> = counts.into_iter()
+            .map(|(wallet, entries)| WalletCount { wallet, entries })
+            .collect();
+        Ok(HttpResponse::Ok().json(entries))
     } else {
-        HttpResponse::NotFound().body("No records for that wallet")
+        Ok(HttpResponse::Ok().json(counts.into_keys().collect::<Vec<_>>()))
+    }
+}
+
+/// One entry in `/admin/idempotency`
+#[derive(Serialize)]
+struct IdempotencyEntry {
+    key:           String,
+    /// the endpoint the key was used on, e.g. `credit`
+    operation:     &'static str,
+    wallet:        String,
+    currency:      String,
+    /// seconds until the stored response expires
+    ttl_remaining: u64,
+}
+
+/// GET /admin/idempotency
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Every `Idempotency-Key` whose response is still being replayed, with
+/// the wallet it was used on and the seconds it has left.
+#[instrument(skip_all, fields(operation = "list_idempotency"))]
+async fn list_idempotency(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    let entries: Vec<_> = tenant::current().idempotency.active().into_iter()
+        .map(|k| IdempotencyEntry {
+            key:           k.key,
+            operation:     k.operation,
+            wallet:        k.wallet,
+            currency:      k.currency,
+            ttl_remaining: k.remaining.as_secs(),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[derive(Serialize)]
+struct RevokeResponse {
+    key:     String,
+    /// stored responses dropped, one per wallet the key was used on
+    revoked: usize,
+}
+
+/// DELETE /admin/idempotency/{key}
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Forgets the key's stored responses, so a retry carrying it runs
+/// again instead of replaying; 404 if none are stored.
+#[instrument(skip_all, fields(operation = "revoke_idempotency"))]
+async fn revoke_idempotency(req: HttpRequest, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    let key = path.into_inner();
+    let revoked = tenant::current().idempotency.revoke(&key);
+    if revoked == 0 {
+        return Err(ApiError::IdempotencyKeyNotFound);
+    }
+    info!(revoked, "idempotency key revoked");
+    Ok(HttpResponse::Ok().json(RevokeResponse { key, revoked }))
+}
+
+/// Query string for `/admin/compare`
+#[derive(Deserialize)]
+struct CompareQuery {
+    a:        String,
+    b:        String,
+    #[serde(default = "default_currency")]
+    currency: String,
+}
+
+/// Which of the two compared wallets holds more
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Comparison {
+    AGreater,
+    Equal,
+    BGreater,
+}
+
+#[derive(Serialize)]
+struct CompareResponse {
+    result: Comparison,
+}
+
+/// GET /admin/compare?a=<wallet>&b=<wallet>[&currency=USD]
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Decrypts only `enc(a) · enc(b)⁻¹`, the signed difference of the two
+/// balances, and returns its sign as `{ result: "a_greater" | "equal" |
+/// "b_greater" }`; neither balance nor the gap between them is revealed.
+#[instrument(skip_all, fields(operation = "compare", a = %query.a, b = %query.b))]
+async fn compare(
+    req:   HttpRequest,
+    store: Store,
+    query: web::Query<CompareQuery>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    let _gate = rotation_gate();
+    let key = key();
+
+    let a = normalize_wallet(&query.a)?;
+    let b = normalize_wallet(&query.b)?;
+    let ct_a = store.latest(&a, &query.currency).ok_or(ApiError::WalletNotFound)?;
+    let ct_b = store.latest(&b, &query.currency).ok_or(ApiError::WalletNotFound)?;
+
+    let diff = decode_signed(&timed_decrypt(&homomorphic_subtraction(&ct_a, &ct_b, &key)?)?, &key.n_s);
+    let result = match diff.sign() {
+        Sign::Plus   => Comparison::AGreater,
+        Sign::NoSign => Comparison::Equal,
+        Sign::Minus  => Comparison::BGreater,
+    };
+    Ok(HttpResponse::Ok().json(CompareResponse { result }))
+}
+
+/// POST /admin/rotate-key
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Replaces the keypair with a fresh one, re-encrypting every wallet's
+/// net balance under it. Each wallet's history collapses to that single
+/// entry, since the old ciphertexts mean nothing under the new key.
+#[instrument(skip_all, fields(operation = "rotate_key"))]
+async fn rotate_key(req: HttpRequest, store: Store) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+
+    // generating the key is slow, so do it before blocking any request
+    let new_key = web::block(generate_key).await??;
+    if !key_self_test(&new_key) {
+        return Err(ApiError::Internal("new key failed its self-test".into()));
+    }
+    let tenant = tenant::current();
+    let wallets = web::block(move || tenant::within(tenant, || swap_key(&*store, new_key))).await??;
+
+    Ok(HttpResponse::Ok().json(RotateResponse {
+        wallets,
+        n: key().n.to_str_radix(10),
+    }))
+}
+
+/// Helper: re-encrypt everything in `store` under `new_key` and make it
+/// the tenant's key, returning how many wallets were migrated.
+///
+/// The ledger and key files are each replaced atomically, but not
+/// together: a crash between the two renames leaves a ledger the key
+/// on disk can't decrypt.
+fn swap_key(store: &dyn LedgerStore, new_key: PaillierKey) -> Result<usize, ApiError> {
+    // waits for in-flight requests, which finish under the old key, and
+    // holds off new ones until the swap is done, so nothing can change
+    // between listing the accounts and replacing them
+    let _gate = tenant::current().rotation.write().unwrap();
+    let old_key = key();
+
+    let reencrypt = |ct: &PaillierCiphertext| -> Result<_, ApiError> {
+        Ok(encrypt(&new_key, &decrypt_crt(&old_key, ct)?))
+    };
+    let mut accounts = store.accounts();
+    accounts.retain(|account| account.latest.is_some());
+    for account in &mut accounts {
+        account.latest = account.latest.as_ref().map(reencrypt).transpose()?;
+        account.held   = account.held.as_ref().map(reencrypt).transpose()?;
+    }
+    let migrated = accounts.len();
+
+    // stage the key, swap in the ledger, then the key
+    let tenant = tenant::current();
+    let key_path = &tenant.key_path;
+    let staged = key_path.with_extension("json.tmp");
+    fs::write(&staged, new_key.to_json())?;
+    store.replace_all(&accounts)?;
+    fs::rename(&staged, key_path)?;
+
+    tenant.randomness.reset(&new_key);
+    *tenant.key.write().unwrap() = Arc::new(new_key);
+    tenant.decrypt_cache.clear();
+    for Account { wallet, currency, latest, .. } in &accounts {
+        if let Some(ct) = latest {
+            subscribe::publish(wallet, currency, ct);
+        }
+    }
+    Ok(migrated)
+}
+
+/// GET /metrics
+/// Prometheus text exposition format
+#[instrument(skip_all, level = "debug", fields(operation = "metrics"))]
+async fn get_metrics() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render())
+}
+
+/// Every route that reads or changes a tenant's wallets or key, mounted
+/// at the root for the default tenant and under `/{tenant}` for the rest
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/register", web::post().to(auth::register))
+       .route("/credit", web::post().to(credit))
+       .route("/credit/batch", web::post().to(credit_batch))
+       .route("/submit", web::post().to(submit))
+       .route("/debit",  web::post().to(debit))
+       .route("/hold", web::post().to(holds::hold))
+       .route("/capture", web::post().to(holds::capture))
+       .route("/release", web::post().to(holds::release))
+       .route("/transfer", web::post().to(transfer))
+       .route("/spend", web::post().to(spend))
+       .route("/simulate/{wallet}", web::post().to(simulate))
+       .route("/simulate/{wallet}/{currency}", web::post().to(simulate))
+       // before `/net/{wallet}`, which would otherwise match it
+       .route("/net/all", web::get().to(get_net_all))
+       .route("/net/{wallet}", web::get().to(get_net))
+       // likewise before `/net/{wallet}/{currency}`
+       .configure(|cfg| {
+           if config().verified_net {
+               cfg.route("/net/{wallet}/verified", web::get().to(get_net_verified));
+           }
+       })
+       .route("/net/{wallet}/{currency}", web::get().to(get_net))
+       .route("/history/{wallet}", web::get().to(get_history))
+       .route("/history/{wallet}/{currency}", web::get().to(get_history))
+       .route("/decrypt/{wallet}", web::post().to(decrypt_balance))
+       .route("/decrypt/{wallet}/{currency}", web::post().to(decrypt_balance))
+       .route("/check-threshold", web::post().to(check_threshold))
+       .route("/encrypt", web::post().to(encrypt_amount))
+       .route("/pubkey", web::get().to(get_pubkey))
+       .route("/pubkey/fingerprint", web::get().to(get_pubkey_fingerprint))
+       .route("/decrypt-ciphertext", web::post().to(decrypt_ciphertext))
+       .route("/balance/proof/{wallet}", web::get().to(balance_proof))
+       .route("/balance/proof/{wallet}/{currency}", web::get().to(balance_proof))
+       .route("/compact/{wallet}", web::post().to(compact))
+       .route("/compact/{wallet}/{currency}", web::post().to(compact))
+       .route("/subscribe/{wallet}", web::get().to(subscribe::subscribe))
+       .route("/subscribe/{wallet}/{currency}", web::get().to(subscribe::subscribe))
+       .route("/admin/reset/{wallet}", web::post().to(reset_wallet))
+       .route("/admin/reset/{wallet}/{currency}", web::post().to(reset_wallet))
+       .route("/admin/rotate-key", web::post().to(rotate_key))
+       .route("/admin/wallets", web::get().to(list_wallets))
+       .route("/admin/compare", web::get().to(compare))
+       .route("/admin/idempotency", web::get().to(list_idempotency))
+       .route("/admin/idempotency/{key}", web::delete().to(revoke_idempotency))
+       .route("/admin/audit", web::get().to(audit::audit));
+    if config().oracle_mode {
+        oracle::configure(cfg);
     }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    Lazy::force(&KEY);
-    println!("Starting server on 127.0.0.1:8085");
-    HttpServer::new(|| {
+    // `privacyserver decrypt ...` and `privacyserver selftest` run
+    // offline and exit
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("decrypt")  => std::process::exit(cli::decrypt_command(&args[1..])),
+        Some("selftest") => std::process::exit(cli::selftest_command(&args[1..])),
+        _                => {}
+    }
+
+    // filtered by `RUST_LOG` (e.g. `RUST_LOG=privacyserver=debug`), info by
+    // default. Each handler's span closes with its duration, e.g.
+    //   INFO credit{operation="credit" wallet=alice}: privacyserver: close time.busy=2.1ms time.idle=9.6µs
+    // Spans skip the handler arguments, so amounts and balances never
+    // reach the log. Colors only go to a terminal, not to a log file.
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(io::stdout().is_terminal())
+        .init();
+
+    let config = match Config::from_env() {
+        Ok(config) => CONFIG.get_or_init(|| config),
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(2);
+        }
+    };
+
+    let key = match load_or_generate_key(&config.key_path, config.primes_file.as_deref()) {
+        Ok(key) => key,
+        Err(e) => {
+            error!("failed to load or generate the keypair: {e}");
+            std::process::exit(1);
+        }
+    };
+    KEY_HEALTHY.store(key_self_test(&key), Ordering::Release);
+    let root = Tenant::new(
+        None,
+        key,
+        &config.key_path,
+        &config.ledger_path,
+        &config.api_keys_path,
+        root_admin_digest(),
+    )?;
+    if let Err(e) = tenant::init_root(root) {
+        error!("failed to total the ledger for auditing: {e}");
+        std::process::exit(1);
+    }
+    info!(bind = %config.bind, "starting server");
+    let max_body_bytes = config.max_body_bytes;
+    let oracle_mode = config.oracle_mode;
+    let verified_net = config.verified_net;
+    let compression = config.compression;
+    let tenants = config.tenants_dir.is_some();
+    if oracle_mode {
+        warn!("oracle mode: /oracle/* will encrypt and decrypt anything; for testing only");
+    }
+    if verified_net {
+        warn!("/net/{{wallet}}/verified is mounted; balances are readable without the admin token");
+    }
+    HttpServer::new(move || {
         App::new()
-            .route("/credit", web::post().to(credit))
-            .route("/debit",  web::post().to(debit))
-            .route("/net/{wallet}", web::get().to(get_net))
+            // `/history` and `/net/all` can run to megabytes of digits
+            .wrap(Condition::new(compression, Compress::default()))
+            // malformed bodies and queries get the same JSON error shape
+            .app_data(web::JsonConfig::default()
+                .limit(max_body_bytes)
+                .error_handler(|e, _| match e {
+                    JsonPayloadError::Overflow { limit }
+                    | JsonPayloadError::OverflowKnownLength { limit, .. } => {
+                        ApiError::PayloadTooLarge { limit }.into()
+                    }
+                    e => ApiError::InvalidRequest(e.to_string()).into(),
+                }))
+            .app_data(web::QueryConfig::default().error_handler(|e, _| {
+                ApiError::InvalidRequest(e.to_string()).into()
+            }))
+            .configure(routes)
+            .route("/healthz", web::get().to(healthz))
+            .route("/healthz/deep", web::get().to(healthz_deep))
+            .route("/metrics", web::get().to(get_metrics))
+            .route("/openapi.json", web::get().to(openapi::openapi))
+            // after every root route, so `/{tenant}` can't shadow one
+            .configure(|cfg| {
+                if tenants {
+                    cfg.route("/admin/tenants", web::post().to(tenant::create_tenant));
+                    cfg.service(web::scope("/{tenant}").wrap(from_fn(tenant::resolve)).configure(routes));
+                }
+            })
     })
-    .bind(("127.0.0.1", 8085))?
+    .bind(config.bind.as_str())?
+    // on SIGINT/SIGTERM actix stops accepting connections and gives
+    // in-flight requests this long to finish before `run` resolves
+    .shutdown_timeout(30)
     .run()
-    .await
+    .await?;
+
+    // every append is already written through, so this only makes sure
+    // the OS has it on disk before we exit. To check by hand: credit a
+    // wallet, send SIGINT, restart, and `/net/{wallet}` still has it.
+    for tenant in tenant::all() {
+        tenant.ledger.sync()?;
+    }
+    info!("ledger flushed; shut down cleanly");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+    use privacyserver::store::InMemoryLedger;
+    use serde_json::json;
+
+    /// Set up the config and the default tenant once per test binary,
+    /// with a 512-bit key and its files in a fresh temp directory
+    pub fn init() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            let dir = std::env::temp_dir().join(format!("privacyserver-unit-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+            let args = ["--key-bits", "512", "--ledger-path", &file("ledger.jsonl"),
+                        "--key-path", &file("key.json"), "--api-keys-path", &file("api_keys.json")];
+            let config = CONFIG.get_or_init(|| Config::from_args(args.map(String::from).to_vec()).unwrap());
+
+            let key = PaillierKey::new(config.key_bits).unwrap();
+            KEY_HEALTHY.store(key_self_test(&key), Ordering::Release);
+            let root = Tenant::new(
+                None,
+                key,
+                &config.key_path,
+                &config.ledger_path,
+                &config.api_keys_path,
+                Some(auth::digest("test-admin")),
+            );
+            tenant::init_root(root.unwrap()).unwrap();
+        });
+    }
+
+    #[actix_web::test]
+    async fn handlers_write_through_the_registered_store() {
+        init();
+        let store = Arc::new(InMemoryLedger::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(store.clone() as Arc<dyn LedgerStore>))
+                .configure(routes),
+        )
+        .await;
+
+        let requests = [
+            ("/credit", json!({ "wallet": "store-alice", "amount": 100 })),
+            ("/debit", json!({ "wallet": "store-alice", "amount": 40 })),
+            ("/transfer", json!({ "from": "store-alice", "to": "store-bob", "amount": 25 })),
+        ];
+        for (path, body) in requests {
+            let resp = test::call_service(&app, test::TestRequest::post().uri(path).set_json(body).to_request()).await;
+            assert!(resp.status().is_success(), "{path}: {}", resp.status());
+        }
+
+        let key = key();
+        let balance = |wallet| decrypt_crt(&key, &store.latest(wallet, "USD").unwrap()).unwrap();
+        assert_eq!(balance("store-alice"), BigUint::from(35u32));
+        assert_eq!(balance("store-bob"), BigUint::from(25u32));
+        assert_eq!(store.history("store-alice", "USD", 0, 10).len(), 3);
+        // nothing reached the tenant's own ledger
+        assert!(ledger().get("store-alice", "USD").is_none());
+    }
+
+    #[actix_web::test]
+    async fn every_handler_reads_the_registered_store() {
+        init();
+        let store = Arc::new(InMemoryLedger::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(store.clone() as Arc<dyn LedgerStore>))
+                .configure(routes),
+        )
+        .await;
+        let call = async |req: test::TestRequest| {
+            let req = req.insert_header(("Authorization", "Bearer test-admin")).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success(), "{}", resp.status());
+            test::read_body_json::<serde_json::Value, _>(resp).await
+        };
+        let post = |uri: &str, body: serde_json::Value| test::TestRequest::post().uri(uri).set_json(body);
+        let key = key();
+        let balance = || decrypt_crt(&key, &store.latest("store-carol", "USD").unwrap()).unwrap();
+
+        call(post("/credit", json!({ "wallet": "store-carol", "amount": 100 }))).await;
+        let dry_run = call(post("/credit?dry_run=true", json!({ "wallet": "store-carol", "amount": 5 }))).await;
+        assert_eq!(dry_run["balance"], 105);
+        let simulated = call(post("/simulate/store-carol", json!([{ "op": "debit", "amount": 10 }]))).await;
+        assert_eq!(simulated["balance"], 90);
+
+        for (uri, amount) in [("/hold", 30), ("/capture", 10), ("/release", 5)] {
+            call(post(uri, json!({ "wallet": "store-carol", "amount": amount }))).await;
+        }
+        assert_eq!(balance(), BigUint::from(75u32));
+        let net = call(test::TestRequest::get().uri("/net/all?decrypt=true")).await;
+        assert_eq!(net, json!([{ "wallet": "store-carol", "currency": "USD", "c": net[0]["c"], "balance": 75 }]));
+        let wallets = call(test::TestRequest::get().uri("/admin/wallets?counts=true")).await;
+        assert_eq!(wallets, json!([{ "wallet": "store-carol", "entries": 3 }]));
+
+        let compacted = call(post("/compact/store-carol", json!({}))).await;
+        assert_eq!(compacted["removed"], 2);
+        assert_eq!(store.history("store-carol", "USD", 0, 10).len(), 1);
+        call(post("/admin/reset/store-carol", json!({}))).await;
+        assert_eq!(balance(), BigUint::zero());
+        assert!(ledger().get("store-carol", "USD").is_none());
+    }
+
+    #[actix_web::test]
+    async fn rate_limits_refuse_requests_without_a_peer_address() {
+        init();
+        let app = test::init_service(App::new().configure(routes)).await;
+        // `TestRequest` has no peer address unless given one
+        let requests = [
+            ("/decrypt-ciphertext", json!({ "c": "1" })),
+            ("/check-threshold", json!({ "wallet": "nobody", "threshold": 1 })),
+        ];
+        for (uri, body) in requests {
+            let req = test::TestRequest::post()
+                .uri(uri)
+                .insert_header(("Authorization", "Bearer test-admin"))
+                .set_json(body)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS, "{uri}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn hex_round_trips_and_rejects_non_hex() {
+        let v = BigUint::parse_bytes(b"123456789012345678901234567890", 10).unwrap();
+        let hex = Radix::Base(16).format(&v);
+        assert_eq!(hex, "18ee90ff6c373e0ee4e3f0ad2");
+        assert_eq!(Radix::Base(16).parse("c", &hex).unwrap(), v);
+        assert_eq!(Radix::Base(16).parse("c", &hex.to_uppercase()).unwrap(), v);
+        assert_eq!(Radix::default().parse("c", &Radix::default().format(&v)).unwrap(), v);
+        assert!(matches!(Radix::Base(16).parse("c", "12g4"), Err(ApiError::InvalidRequest(_))));
+    }
+
+    #[actix_web::test]
+    async fn base64_round_trips_below_n_squared() {
+        init();
+        let key = key();
+        let ct = encrypt(&*key, &BigUint::from(9u32));
+        let encoded = Radix::Base64.format(&ct.c);
+        assert_eq!(BASE64.decode(&encoded).unwrap(), ct.c.to_bytes_le());
+        assert_eq!(Radix::Base64.parse("c", &encoded).unwrap(), ct.c);
+
+        let too_big = Radix::Base64.format(&key.n_squared);
+        for bad in [too_big.as_str(), "", "not base64!"] {
+            assert!(matches!(Radix::Base64.parse("c", bad), Err(ApiError::InvalidRequest(_))), "{bad}");
+        }
+    }
 }
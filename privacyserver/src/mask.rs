@@ -0,0 +1,34 @@
+use num_bigint::BigUint;
+
+use crate::config;
+
+/// Digits `ciphertext` keeps at each end
+const KEPT: usize = 8;
+
+/// `c` as it should appear in a log line or error body: only its first
+/// and last 8 decimal digits, unless `--full-ciphertext-logs` is set.
+/// Whole ciphertexts are bulky, and let anyone reading the logs link a
+/// value to wherever else it shows up.
+pub fn ciphertext(c: &BigUint) -> String {
+    let digits = c.to_str_radix(10);
+    if config().full_ct_logs || digits.len() <= 2 * KEPT {
+        return digits;
+    }
+    format!("{}…{}", &digits[..KEPT], &digits[digits.len() - KEPT..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_ciphertexts_keep_only_their_ends() {
+        crate::tests::init();
+        let c: BigUint = "123456789012345678901234567890".parse().unwrap();
+        assert_eq!(ciphertext(&c), "12345678…34567890");
+        // nothing is hidden from values short enough to show whole
+        let short: BigUint = "1234567890123456".parse().unwrap();
+        assert_eq!(ciphertext(&short), "1234567890123456");
+        assert_eq!(ciphertext(&BigUint::from(0u8)), "0");
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use privacyserver::paillier::keygen_retries;
+
+/// Monotonic counter
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub const fn new() -> Self {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Upper bounds of the duration histogram buckets, in seconds
+const BUCKETS: [f64; 10] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0];
+
+/// Duration histogram with fixed buckets (non-cumulative internally)
+pub struct Histogram {
+    buckets:   [AtomicU64; BUCKETS.len()],
+    count:     AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Histogram {
+            buckets:   [const { AtomicU64::new(0) }; BUCKETS.len()],
+            count:     AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|&le| secs <= le) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+pub static CREDITS:             Counter   = Counter::new();
+pub static DEBITS:              Counter   = Counter::new();
+pub static TRANSFERS:           Counter   = Counter::new();
+pub static OVERDRAFTS_REJECTED: Counter   = Counter::new();
+pub static ENCRYPT_SECONDS:     Histogram = Histogram::new();
+pub static DECRYPT_SECONDS:     Histogram = Histogram::new();
+pub static DECRYPT_HITS:        Counter   = Counter::new();
+pub static DECRYPT_MISSES:      Counter   = Counter::new();
+pub static POOL_HITS:           Counter   = Counter::new();
+pub static POOL_MISSES:         Counter   = Counter::new();
+
+/// All metrics in the Prometheus text exposition format
+pub fn render() -> String {
+    let mut out = String::new();
+    counter(&mut out, "privacyserver_credits_total", "Successful credits", CREDITS.get());
+    counter(&mut out, "privacyserver_debits_total", "Successful debits", DEBITS.get());
+    counter(&mut out, "privacyserver_transfers_total", "Successful transfers", TRANSFERS.get());
+    counter(&mut out, "privacyserver_overdrafts_rejected_total",
+            "Debits rejected for insufficient funds", OVERDRAFTS_REJECTED.get());
+    histogram(&mut out, "privacyserver_encrypt_duration_seconds",
+              "Time spent encrypting", &ENCRYPT_SECONDS);
+    histogram(&mut out, "privacyserver_decrypt_duration_seconds",
+              "Time spent decrypting", &DECRYPT_SECONDS);
+    counter(&mut out, "privacyserver_decrypt_cache_hits_total",
+            "Decryptions answered from the cache", DECRYPT_HITS.get());
+    counter(&mut out, "privacyserver_decrypt_cache_misses_total",
+            "Decryptions that ran in full", DECRYPT_MISSES.get());
+    counter(&mut out, "privacyserver_randomness_pool_hits_total",
+            "Encryptions that used precomputed randomness", POOL_HITS.get());
+    counter(&mut out, "privacyserver_randomness_pool_misses_total",
+            "Encryptions that found the randomness pool empty", POOL_MISSES.get());
+    counter(&mut out, "privacyserver_keygen_retries_total",
+            "Prime pairs key generation discarded and redrew", keygen_retries());
+    out
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, h: &Histogram) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    let mut cumulative = 0;
+    for (le, bucket) in BUCKETS.iter().zip(&h.buckets) {
+        cumulative += bucket.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{le=\"{le}\"}} {cumulative}");
+    }
+    let count = h.count.load(Ordering::Relaxed);
+    let sum   = h.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+    let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+    let _ = writeln!(out, "{name}_sum {sum}");
+    let _ = writeln!(out, "{name}_count {count}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let h = Histogram::new();
+        for ms in [0.2, 3.0, 3.0, 2000.0] {
+            h.observe(Duration::from_secs_f64(ms / 1000.0));
+        }
+        let mut out = String::new();
+        histogram(&mut out, "t", "test", &h);
+        assert!(out.contains("t_bucket{le=\"0.0005\"} 1\n"));
+        assert!(out.contains("t_bucket{le=\"0.0025\"} 1\n"));
+        assert!(out.contains("t_bucket{le=\"0.005\"} 3\n"));
+        assert!(out.contains("t_bucket{le=\"1\"} 3\n"));
+        // past the last bound, counted only in +Inf
+        assert!(out.contains("t_bucket{le=\"+Inf\"} 4\n"));
+        assert!(out.contains("t_count 4\n"));
+    }
+}
@@ -0,0 +1,592 @@
+use actix_web::HttpResponse;
+use once_cell::sync::Lazy;
+use serde_json::{json, Map, Value};
+use tracing::instrument;
+
+use crate::config;
+use crate::config::Config;
+
+/// What a successful response carries
+enum Body {
+    /// JSON matching the named schema in `components.schemas`
+    Json(&'static str),
+    /// a JSON array of the named schema
+    List(&'static str),
+    /// plain text, e.g. `/metrics`
+    Text,
+    /// a WebSocket upgrade
+    Upgrade,
+}
+
+/// One mounted route. Path parameters come from the `{...}` segments of
+/// `path`; `params` names query parameters and headers in `parameter`.
+struct Route {
+    method:   &'static str,
+    path:     &'static str,
+    summary:  &'static str,
+    request:  Option<&'static str>,
+    response: Body,
+    params:   &'static [&'static str],
+    /// statuses besides 200 the handler can answer with
+    errors:   &'static [u16],
+    /// requires `Authorization: Bearer <ADMIN_TOKEN>`
+    admin:    bool,
+}
+
+/// Every route `main` always mounts, in the same order. A new handler
+/// needs a row here too, or clients generated from the spec won't see it.
+const ROUTES: &[Route] = &[
+    Route { method: "post", path: "/register", summary: "Mint an API key for a wallet",
+            request: Some("RegisterRequest"), response: Body::Json("RegisterResponse"),
+            params: &[], errors: &[400, 401], admin: false },
+    Route { method: "post", path: "/credit", summary: "Add an amount to a wallet's balance",
+            request: Some("TxRequest"), response: Body::Json("TxOutcome"),
+            params: &["radix", "dry_run", "Idempotency-Key"], errors: &[400, 401, 413, 422, 429, 500], admin: false },
+    Route { method: "post", path: "/credit/batch", summary: "Credit several amounts in one ledger record",
+            request: Some("BatchCreditRequest"), response: Body::Json("TxResponse"),
+            params: &["radix", "Idempotency-Key"], errors: &[400, 401, 413, 422, 429, 500], admin: false },
+    Route { method: "post", path: "/submit", summary: "Credit a client-encrypted ciphertext with a proof",
+            request: Some("SubmitRequest"), response: Body::Json("TxResponse"),
+            params: &["radix", "Idempotency-Key"], errors: &[400, 401, 422, 429, 500], admin: false },
+    Route { method: "post", path: "/debit", summary: "Subtract an amount from a wallet's balance",
+            request: Some("TxRequest"), response: Body::Json("TxOutcome"),
+            params: &["radix", "dry_run", "Idempotency-Key"], errors: &[400, 401, 409, 422, 429, 500], admin: false },
+    Route { method: "post", path: "/hold", summary: "Move an amount from the balance onto hold",
+            request: Some("TxRequest"), response: Body::Json("HoldResponse"),
+            params: &["radix", "Idempotency-Key"], errors: &[400, 401, 409, 422, 429, 500], admin: false },
+    Route { method: "post", path: "/capture", summary: "Finalize held funds as a debit",
+            request: Some("TxRequest"), response: Body::Json("HoldResponse"),
+            params: &["radix", "Idempotency-Key"], errors: &[400, 401, 409, 422, 429, 500], admin: false },
+    Route { method: "post", path: "/release", summary: "Return held funds to the balance",
+            request: Some("TxRequest"), response: Body::Json("HoldResponse"),
+            params: &["radix", "Idempotency-Key"], errors: &[400, 401, 409, 422, 429, 500], admin: false },
+    Route { method: "post", path: "/transfer", summary: "Move an amount between two wallets",
+            request: Some("TransferRequest"), response: Body::Json("TransferResponse"),
+            params: &["radix"], errors: &[400, 401, 429, 500], admin: false },
+    Route { method: "post", path: "/spend", summary: "Transfer only if the balance covers the amount",
+            request: Some("SpendRequest"), response: Body::Json("TransferResponse"),
+            params: &["radix"], errors: &[400, 401, 409, 429, 500], admin: false },
+    Route { method: "post", path: "/simulate/{wallet}", summary: "Preview a sequence of USD credits and debits",
+            request: Some("SimulateRequest"), response: Body::Json("DryRunResponse"),
+            params: &["radix"], errors: &[400], admin: false },
+    Route { method: "post", path: "/simulate/{wallet}/{currency}", summary: "Preview a sequence of credits and debits",
+            request: Some("SimulateRequest"), response: Body::Json("DryRunResponse"),
+            params: &["radix"], errors: &[400], admin: false },
+    Route { method: "get", path: "/net/all", summary: "Latest ciphertext of every wallet",
+            request: None, response: Body::List("NetEntry"),
+            params: &["radix", "decrypt"], errors: &[401], admin: false },
+    Route { method: "get", path: "/net/{wallet}", summary: "Latest balance ciphertext, in USD",
+            request: None, response: Body::Json("TxResponse"),
+            params: &["radix", "If-None-Match"], errors: &[304, 400, 404], admin: false },
+    Route { method: "get", path: "/net/{wallet}/{currency}", summary: "Latest balance ciphertext",
+            request: None, response: Body::Json("TxResponse"),
+            params: &["radix", "If-None-Match"], errors: &[304, 400, 404], admin: false },
+    Route { method: "get", path: "/history/{wallet}", summary: "Balance ciphertexts oldest first, in USD",
+            request: None, response: Body::List("HistoryEntry"),
+            params: &["radix", "offset", "limit"], errors: &[400], admin: false },
+    Route { method: "get", path: "/history/{wallet}/{currency}", summary: "Balance ciphertexts oldest first",
+            request: None, response: Body::List("HistoryEntry"),
+            params: &["radix", "offset", "limit"], errors: &[400], admin: false },
+    Route { method: "post", path: "/decrypt/{wallet}", summary: "Decrypt a wallet's USD balance",
+            request: None, response: Body::Json("BalanceResponse"),
+            params: &[], errors: &[400, 401, 404], admin: true },
+    Route { method: "post", path: "/decrypt/{wallet}/{currency}", summary: "Decrypt a wallet's balance",
+            request: None, response: Body::Json("BalanceResponse"),
+            params: &[], errors: &[400, 401, 404], admin: true },
+    Route { method: "post", path: "/check-threshold", summary: "Whether a balance is at least a threshold",
+            request: Some("ThresholdRequest"), response: Body::Json("ThresholdResponse"),
+            params: &[], errors: &[400, 401, 404, 429], admin: false },
+    Route { method: "post", path: "/encrypt", summary: "Encrypt an amount under the server key",
+            request: Some("EncryptRequest"), response: Body::Json("CiphertextResponse"),
+            params: &["radix"], errors: &[400], admin: false },
+    Route { method: "get", path: "/pubkey", summary: "The server's public key",
+            request: None, response: Body::Json("PubkeyResponse"),
+            params: &[], errors: &[], admin: false },
+    Route { method: "get", path: "/pubkey/fingerprint", summary: "SHA-256 fingerprint of the public key",
+            request: None, response: Body::Json("FingerprintResponse"),
+            params: &[], errors: &[], admin: false },
+    Route { method: "post", path: "/decrypt-ciphertext", summary: "Decrypt a client-built ciphertext",
+            request: Some("CiphertextRequest"), response: Body::Json("PlaintextResponse"),
+            params: &["radix"], errors: &[400, 401, 429], admin: true },
+    Route { method: "get", path: "/balance/proof/{wallet}", summary: "USD balance with a decryption proof",
+            request: None, response: Body::Json("BalanceProofResponse"),
+            params: &[], errors: &[400, 401, 404], admin: true },
+    Route { method: "get", path: "/balance/proof/{wallet}/{currency}", summary: "Balance with a decryption proof",
+            request: None, response: Body::Json("BalanceProofResponse"),
+            params: &[], errors: &[400, 401, 404], admin: true },
+    Route { method: "post", path: "/compact/{wallet}", summary: "Collapse a wallet's USD history",
+            request: None, response: Body::Json("CompactResponse"),
+            params: &["rerandomize"], errors: &[400, 401, 404, 500], admin: true },
+    Route { method: "post", path: "/compact/{wallet}/{currency}", summary: "Collapse a wallet's history",
+            request: None, response: Body::Json("CompactResponse"),
+            params: &["rerandomize"], errors: &[400, 401, 404, 500], admin: true },
+    Route { method: "get", path: "/subscribe/{wallet}", summary: "WebSocket of new USD balance ciphertexts",
+            request: None, response: Body::Upgrade,
+            params: &[], errors: &[400], admin: false },
+    Route { method: "get", path: "/subscribe/{wallet}/{currency}", summary: "WebSocket of new balance ciphertexts",
+            request: None, response: Body::Upgrade,
+            params: &[], errors: &[400], admin: false },
+    Route { method: "post", path: "/admin/reset/{wallet}", summary: "Set a wallet's USD balance to zero",
+            request: None, response: Body::Json("TxResponse"),
+            params: &["radix"], errors: &[400, 401, 500], admin: true },
+    Route { method: "post", path: "/admin/reset/{wallet}/{currency}", summary: "Set a wallet's balance to zero",
+            request: None, response: Body::Json("TxResponse"),
+            params: &["radix"], errors: &[400, 401, 500], admin: true },
+    Route { method: "post", path: "/admin/rotate-key", summary: "Replace the keypair, re-encrypting every balance",
+            request: None, response: Body::Json("RotateResponse"),
+            params: &[], errors: &[401, 500], admin: true },
+    Route { method: "get", path: "/admin/wallets", summary: "Every wallet with ledger entries",
+            request: None, response: Body::Json("WalletList"),
+            params: &["counts"], errors: &[401], admin: true },
+    Route { method: "get", path: "/admin/compare", summary: "Which of two wallets holds more",
+            request: None, response: Body::Json("CompareResponse"),
+            params: &["a", "b", "currency"], errors: &[400, 401, 404], admin: true },
+    Route { method: "get", path: "/admin/idempotency", summary: "Idempotency keys still being replayed",
+            request: None, response: Body::List("IdempotencyEntry"),
+            params: &[], errors: &[401], admin: true },
+    Route { method: "delete", path: "/admin/idempotency/{key}", summary: "Forget an idempotency key",
+            request: None, response: Body::Json("RevokeResponse"),
+            params: &[], errors: &[401, 404], admin: true },
+    Route { method: "get", path: "/admin/audit", summary: "Per-currency totals against credits and debits",
+            request: None, response: Body::List("AuditEntry"),
+            params: &[], errors: &[401], admin: true },
+    Route { method: "get", path: "/healthz", summary: "Whether the key passed its self-test",
+            request: None, response: Body::Json("Health"),
+            params: &[], errors: &[503], admin: false },
+    Route { method: "get", path: "/healthz/deep", summary: "Like /healthz, and whether every ledger file is intact",
+            request: None, response: Body::Json("Health"),
+            params: &[], errors: &[503], admin: false },
+    Route { method: "get", path: "/metrics", summary: "Prometheus metrics",
+            request: None, response: Body::Text,
+            params: &[], errors: &[], admin: false },
+    Route { method: "get", path: "/openapi.json", summary: "This document",
+            request: None, response: Body::Text,
+            params: &[], errors: &[], admin: false },
+];
+
+/// Mounted with `--verified-net`
+const VERIFIED_ROUTES: &[Route] = &[
+    Route { method: "get", path: "/net/{wallet}/verified", summary: "USD balance, ciphertext and proof",
+            request: None, response: Body::Json("BalanceProofResponse"),
+            params: &[], errors: &[400, 404], admin: false },
+];
+
+/// Mounted with `--oracle-mode`
+const ORACLE_ROUTES: &[Route] = &[
+    Route { method: "post", path: "/oracle/encrypt", summary: "Raw encryption, optionally with fixed randomness",
+            request: Some("OracleEncryptRequest"), response: Body::Json("CiphertextResponse"),
+            params: &["radix"], errors: &[400], admin: false },
+    Route { method: "post", path: "/oracle/decrypt", summary: "Raw decryption to a plaintext in [0, n)",
+            request: Some("CiphertextRequest"), response: Body::Json("OraclePlaintextResponse"),
+            params: &["radix"], errors: &[400], admin: false },
+    Route { method: "post", path: "/oracle/add", summary: "Homomorphic sum of two ciphertexts",
+            request: Some("OracleAddRequest"), response: Body::Json("CiphertextResponse"),
+            params: &["radix"], errors: &[400], admin: false },
+];
+
+/// Mounted with `--tenants-dir`, outside the `/{tenant}` scope
+const TENANT_ROUTES: &[Route] = &[
+    Route { method: "post", path: "/admin/tenants", summary: "Create a tenant with its own key, ledger and admin token",
+            request: Some("CreateTenantRequest"), response: Body::Json("CreateTenantResponse"),
+            params: &[], errors: &[400, 401, 409, 500], admin: true },
+];
+
+/// The spec, built once for the running configuration
+static SPEC: Lazy<Value> = Lazy::new(|| spec(config()));
+
+/// GET /openapi.json
+/// OpenAPI 3.0 description of every route this server mounts, with its
+/// request and response schemas, for generating client SDKs
+#[instrument(skip_all, level = "debug", fields(operation = "openapi"))]
+pub async fn openapi() -> HttpResponse {
+    HttpResponse::Ok().json(&*SPEC)
+}
+
+/// The OpenAPI document for a server started with `config`
+pub fn spec(config: &Config) -> Value {
+    let mut routes: Vec<&Route> = ROUTES.iter().collect();
+    if config.verified_net {
+        routes.extend(VERIFIED_ROUTES);
+    }
+    if config.oracle_mode {
+        routes.extend(ORACLE_ROUTES);
+    }
+    if config.tenants_dir.is_some() {
+        routes.extend(TENANT_ROUTES);
+    }
+
+    let mut paths = Map::new();
+    for route in routes {
+        let ops = paths.entry(route.path).or_insert_with(|| json!({}));
+        ops[route.method] = operation(route);
+    }
+
+    let mut description = String::from(
+        "Wallet balances kept as Paillier ciphertexts. Amounts and balances are JSON numbers \
+         when they fit in 64 bits and decimal strings otherwise.",
+    );
+    if config.tenants_dir.is_some() {
+        description.push_str(
+            " Every path except /healthz, /healthz/deep, /metrics, /openapi.json and \
+             /admin/tenants is also served under /{tenant}, against that tenant's own ledger, \
+             key and admin token. A tenant must first be created with POST /admin/tenants; \
+             any other name answers 404.",
+        );
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title":   "privacyserver",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": description,
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "bearer": {
+                    "type":        "http",
+                    "scheme":      "bearer",
+                    "description": "The admin token, or for wallet-moving endpoints the wallet's API key",
+                },
+            },
+        },
+    })
+}
+
+/// Helper: the OpenAPI operation object for `route`
+fn operation(route: &Route) -> Value {
+    let mut parameters: Vec<Value> = route.path
+        .split('/')
+        .filter_map(|s| s.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } }))
+        .collect();
+    parameters.extend(route.params.iter().map(|name| parameter(name)));
+
+    let content = |schema: Value| json!({ "application/json": { "schema": schema } });
+    let mut responses = Map::new();
+    responses.insert("200".into(), match route.response {
+        Body::Json(name) => json!({ "description": "Success", "content": content(schema_ref(name)) }),
+        Body::List(name) => json!({
+            "description": "Success",
+            "content": content(json!({ "type": "array", "items": schema_ref(name) })),
+        }),
+        Body::Text       => json!({ "description": "Success", "content": { "text/plain": {} } }),
+        Body::Upgrade    => json!({ "description": "Not a WebSocket handshake" }),
+    });
+    if let Body::Upgrade = route.response {
+        responses.insert("101".into(), json!({ "description": "Switched to a WebSocket" }));
+    }
+    for &status in route.errors {
+        let response = match status {
+            304 => json!({ "description": "The ciphertext matches `If-None-Match`" }),
+            _   => json!({ "description": status_text(status), "content": content(schema_ref("ErrorResponse")) }),
+        };
+        responses.insert(status.to_string(), response);
+    }
+
+    let mut op = json!({
+        "summary":    route.summary,
+        "parameters": parameters,
+        "responses":  responses,
+    });
+    if let Some(name) = route.request {
+        op["requestBody"] = json!({ "required": true, "content": content(schema_ref(name)) });
+    }
+    if route.admin || route.errors.contains(&401) {
+        op["security"] = json!([{ "bearer": [] }]);
+    }
+    op
+}
+
+/// Helper: a query parameter or header named in `Route::params`
+fn parameter(name: &str) -> Value {
+    let (location, schema, description) = match name {
+        "radix"           => ("query", json!({ "type": "string", "enum": ["10", "16", "base64"], "default": "10" }),
+                              "Encoding of ciphertexts in the request and response; also accepted as `encoding`"),
+        "dry_run"         => ("query", json!({ "type": "boolean", "default": false }),
+                              "Preview the result without recording anything"),
+        "decrypt"         => ("query", json!({ "type": "boolean", "default": false }),
+                              "Include plaintext balances; requires the admin token"),
+        "offset"          => ("query", json!({ "type": "integer", "minimum": 0, "default": 0 }),
+                              "Entries to skip"),
+        "limit"           => ("query", json!({ "type": "integer", "minimum": 0, "maximum": 500 }),
+                              "Most entries to return"),
+        "counts"          => ("query", json!({ "type": "boolean", "default": false }),
+                              "List each wallet with its number of entries"),
+        "rerandomize"     => ("query", json!({ "type": "boolean", "default": false }),
+                              "Re-randomize the surviving ciphertext"),
+        "a" | "b"         => ("query", json!({ "type": "string" }),
+                              "A wallet to compare"),
+        "currency"        => ("query", json!({ "type": "string", "default": "USD" }),
+                              "Currency of both wallets"),
+        "Idempotency-Key" => ("header", json!({ "type": "string" }),
+                              "Replay the original response for a retry with the same key and body; \
+                               422 if the key was used for a different request"),
+        "If-None-Match"   => ("header", json!({ "type": "string" }),
+                              "An `ETag` from an earlier response; 304 if the balance is unchanged"),
+        _ => unreachable!("undocumented parameter `{name}`"),
+    };
+    let required = matches!(name, "a" | "b");
+    json!({ "name": name, "in": location, "required": required, "schema": schema, "description": description })
+}
+
+/// Helper: the reason phrase documented for an error status
+fn status_text(status: u16) -> &'static str {
+    match status {
+        400 => "Invalid request, wallet, amount, or ciphertext",
+        401 => "Missing or invalid admin token or API key",
+        404 => "Not found",
+        409 => "Insufficient funds, or the name is taken",
+        413 => "Request body too large",
+        422 => "The `Idempotency-Key` was used for a different request",
+        429 => "Rate limited, or the wallet limit is reached",
+        500 => "Storage or internal error",
+        503 => "The key has not passed its self-test, or a ledger failed its integrity check",
+        _   => unreachable!("undocumented status {status}"),
+    }
+}
+
+/// Helper: `$ref` to a schema in `components.schemas`
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+/// Helper: an object schema whose `required` fields are those not
+/// listed in `optional`
+fn object(properties: Value, optional: &[&str]) -> Value {
+    let required: Vec<&String> = properties.as_object()
+        .expect("properties are an object")
+        .keys()
+        .filter(|k| !optional.contains(&k.as_str()))
+        .collect();
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// Every request and response body, mirroring the serde types in `main`,
+/// `auth`, `holds`, `audit` and `oracle`
+fn schemas() -> Value {
+    let string = json!({ "type": "string" });
+    let ciphertext = json!({ "type": "string", "description": "Ciphertext in the `?radix=` encoding" });
+    let currency = json!({ "type": "string", "default": "USD" });
+    let amount = schema_ref("Amount");
+    let balance = schema_ref("Balance");
+    let tx = schema_ref("TxResponse");
+
+    // two `json!` calls, since one would pass the macro recursion limit
+    let mut schemas = json!({
+        "Amount": {
+            "description": "Non-negative integer, as a number or a decimal string",
+            "oneOf": [
+                { "type": "integer", "minimum": 0 },
+                { "type": "string", "pattern": "^[0-9]+$" },
+            ],
+        },
+        "Balance": {
+            "description": "Signed integer; a decimal string when it doesn't fit in 64 bits",
+            "oneOf": [
+                { "type": "integer" },
+                { "type": "string", "pattern": "^-?[0-9]+$" },
+            ],
+        },
+        "ErrorResponse": object(json!({
+            "code":    { "type": "string", "description": "Stable identifier, e.g. `WALLET_NOT_FOUND`" },
+            "message": string,
+            "details": { "type": "object", "description": "Extra data, e.g. an overdraft's amounts" },
+        }), &["details"]),
+        "RegisterRequest": object(json!({ "wallet": string }), &[]),
+        "RegisterResponse": object(json!({ "wallet": string, "api_key": string }), &[]),
+        "CreateTenantRequest": object(json!({ "name": string }), &[]),
+        "CreateTenantResponse": object(json!({
+            "tenant":      string,
+            "admin_token": { "type": "string", "description": "The tenant's admin token, shown only this once" },
+        }), &[]),
+        "TxRequest": object(json!({ "wallet": string, "amount": amount, "currency": currency }), &["currency"]),
+        "TxResponse": object(json!({
+            "wallet":   string,
+            "currency": string,
+            "c":        ciphertext,
+            "seq":      { "type": "integer", "description": "The new entry's index in the wallet's history" },
+        }), &["seq"]),
+        "DryRunResponse": object(json!({
+            "wallet":   string,
+            "currency": string,
+            "c":        ciphertext,
+            "balance":  { "allOf": [balance], "description": "The would-be balance, for the admin token only" },
+        }), &["balance"]),
+        "TxOutcome": {
+            "description": "`TxResponse`, or `DryRunResponse` with `?dry_run=true`",
+            "oneOf": [tx, schema_ref("DryRunResponse")],
+        },
+        "BatchCreditRequest": object(json!({
+            "wallet":   string,
+            "amounts":  { "type": "array", "items": amount, "minItems": 1 },
+            "currency": currency,
+        }), &["currency"]),
+        "SubmitRequest": object(json!({
+            "wallet":   string,
+            "c":        ciphertext,
+            "proof":    object(json!({ "a": string, "z1": string, "z2": string }), &[]),
+            "currency": currency,
+        }), &["currency"]),
+    });
+    let rest = json!({
+        "HoldResponse": object(json!({
+            "wallet":   string,
+            "currency": string,
+            "c":        ciphertext,
+            "held":     ciphertext,
+        }), &[]),
+        "TransferRequest": object(json!({
+            "from":     string,
+            "to":       string,
+            "amount":   amount,
+            "currency": currency,
+        }), &["currency"]),
+        "SpendRequest": object(json!({
+            "wallet":   string,
+            "to":       string,
+            "amount":   amount,
+            "exact":    { "type": "boolean", "default": false },
+            "currency": currency,
+        }), &["exact", "currency"]),
+        "SimulateRequest": {
+            "type":  "array",
+            "items": object(json!({
+                "op":     { "type": "string", "enum": ["credit", "debit"] },
+                "amount": amount,
+            }), &[]),
+        },
+        "TransferResponse": object(json!({ "from": tx, "to": tx }), &[]),
+        "NetEntry": object(json!({
+            "wallet":   string,
+            "currency": string,
+            "c":        ciphertext,
+            "balance":  balance,
+        }), &["balance"]),
+        "HistoryEntry": object(json!({ "index": { "type": "integer" }, "c": ciphertext }), &[]),
+        "BalanceResponse": object(json!({
+            "wallet":   string,
+            "currency": string,
+            "balance":  {
+                "description": "Signed balance, or with `--scale` a decimal string in whole units",
+                "oneOf": [balance, { "type": "string", "pattern": "^-?[0-9]+\\.[0-9]+$" }],
+            },
+        }), &[]),
+        "ThresholdRequest": object(json!({
+            "wallet":    string,
+            "threshold": amount,
+            "currency":  currency,
+        }), &["currency"]),
+        "ThresholdResponse": object(json!({ "ok": { "type": "boolean" } }), &[]),
+        "EncryptRequest": object(json!({ "amount": amount }), &[]),
+        "CiphertextRequest": object(json!({ "c": ciphertext }), &[]),
+        "CiphertextResponse": object(json!({ "c": ciphertext }), &[]),
+        "PubkeyResponse": object(json!({ "n": string, "g": string }), &[]),
+        "FingerprintResponse": object(json!({ "fingerprint": string }), &[]),
+        "PlaintextResponse": object(json!({ "plaintext": balance }), &[]),
+        "BalanceProofResponse": object(json!({
+            "wallet":   string,
+            "currency": string,
+            "balance":  balance,
+            "c":        string,
+            "proof":    object(json!({ "a": string, "z": string }), &[]),
+        }), &[]),
+        "CompactResponse": object(json!({
+            "wallet":   string,
+            "currency": string,
+            "removed":  { "type": "integer" },
+        }), &[]),
+        "RotateResponse": object(json!({ "wallets": { "type": "integer" }, "n": string }), &[]),
+        "WalletList": {
+            "description": "Wallet identifiers, or with `counts=true` objects with entry counts",
+            "oneOf": [
+                { "type": "array", "items": string },
+                { "type": "array", "items": object(json!({
+                    "wallet":  string,
+                    "entries": { "type": "integer" },
+                }), &[]) },
+            ],
+        },
+        "CompareResponse": object(json!({
+            "result": { "type": "string", "enum": ["a_greater", "equal", "b_greater"] },
+        }), &[]),
+        "IdempotencyEntry": object(json!({
+            "key":           string,
+            "wallet":        string,
+            "currency":      string,
+            "ttl_remaining": { "type": "integer", "description": "Seconds until the response expires" },
+        }), &[]),
+        "RevokeResponse": object(json!({ "key": string, "revoked": { "type": "integer" } }), &[]),
+        "AuditEntry": object(json!({
+            "currency": string,
+            "total":    balance,
+            "expected": balance,
+            "balanced": { "type": "boolean" },
+        }), &[]),
+        "Health": object(json!({ "status": { "type": "string", "enum": ["ok"] } }), &[]),
+        "OracleEncryptRequest": object(json!({
+            "m": { "type": "string", "description": "Plaintext in [0, n)" },
+            "r": { "type": "string", "description": "Randomness in (0, n); fresh if absent" },
+        }), &["r"]),
+        "OraclePlaintextResponse": object(json!({
+            "m": { "type": "string", "description": "Raw plaintext in [0, n)" },
+        }), &[]),
+        "OracleAddRequest": object(json!({ "a": ciphertext, "b": ciphertext }), &[]),
+    });
+
+    if let (Some(all), Value::Object(rest)) = (schemas.as_object_mut(), rest) {
+        all.extend(rest);
+    }
+    schemas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec_for(args: &[&str]) -> Value {
+        spec(&Config::from_args(args.iter().map(|a| a.to_string()).collect()).unwrap())
+    }
+
+    /// Every `$ref` under `value`
+    fn refs<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(r)) = map.get("$ref") {
+                    out.push(r);
+                }
+                map.values().for_each(|v| refs(v, out));
+            }
+            Value::Array(items) => items.iter().for_each(|v| refs(v, out)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn every_ref_names_a_schema() {
+        let spec = spec_for(&["--verified-net", "--oracle-mode", "--tenants-dir", "tenants"]);
+        let mut found = Vec::new();
+        refs(&spec, &mut found);
+        assert!(!found.is_empty());
+        for r in found {
+            let name = r.strip_prefix("#/components/schemas/").unwrap();
+            assert!(spec["components"]["schemas"].get(name).is_some(), "no schema `{name}`");
+        }
+    }
+
+    #[test]
+    fn optional_routes_follow_the_flags() {
+        let plain = spec_for(&[]);
+        assert!(plain["paths"].get("/credit").is_some());
+        for path in ["/net/{wallet}/verified", "/oracle/encrypt", "/admin/tenants"] {
+            assert!(plain["paths"].get(path).is_none(), "{path} without its flag");
+        }
+
+        let full = spec_for(&["--verified-net", "--oracle-mode", "--tenants-dir", "tenants"]);
+        for path in ["/net/{wallet}/verified", "/oracle/encrypt", "/admin/tenants"] {
+            assert!(full["paths"].get(path).is_some(), "no {path}");
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use actix_web::{web, HttpResponse};
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use privacyserver::paillier::{encrypt_with_randomness, homomorphic_addition, PaillierCiphertext};
+
+use crate::error::ApiError;
+use crate::{check_ciphertext, key, rotation_gate, timed_decrypt, timed_encrypt, RadixQuery};
+
+/// Mount the raw crypto endpoints for interoperability testing; `main`
+/// only does so with `--oracle-mode`. They encrypt, decrypt and add
+/// under the server key with no ledger, rate limit or signed encoding in
+/// the way, which makes them an unrestricted decryption oracle: never
+/// enable them on a server holding real balances.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/oracle/encrypt", web::post().to(encrypt))
+       .route("/oracle/decrypt", web::post().to(decrypt))
+       .route("/oracle/add", web::post().to(add));
+}
+
+/// Raw plaintext to encrypt, integer strings in the `?radix=` base
+#[derive(Deserialize)]
+struct EncryptRequest {
+    /// plaintext in `[0, n)`
+    m: String,
+    /// randomness in `(0, n)`; fresh if absent. Fixing it makes the
+    /// ciphertext reproducible for comparing against other libraries.
+    r: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DecryptRequest {
+    c: String,
+}
+
+#[derive(Deserialize)]
+struct AddRequest {
+    a: String,
+    b: String,
+}
+
+#[derive(Serialize)]
+struct CiphertextResponse {
+    c: String,
+}
+
+#[derive(Serialize)]
+struct PlaintextResponse {
+    /// raw plaintext in `[0, n)`, not decoded as signed
+    m: String,
+}
+
+/// POST /oracle/encrypt
+/// { "m": "42", "r": "12345" }
+/// `c = gᵐ · rⁿ mod n²`.
+#[instrument(skip_all, fields(operation = "oracle_encrypt"))]
+async fn encrypt(
+    query: web::Query<RadixQuery>,
+    body:  web::Json<EncryptRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let _gate = rotation_gate();
+    let key = key();
+    let m = query.radix.parse("m", &body.m)?;
+    if m >= key.n {
+        return Err(ApiError::InvalidRequest("`m` must be below n".to_string()));
+    }
+    let ct = match &body.r {
+        Some(r) => {
+            let r = query.radix.parse("r", r)?;
+            if r.is_zero() || r >= key.n {
+                return Err(ApiError::InvalidRequest("`r` must be in (0, n)".to_string()));
+            }
+            encrypt_with_randomness(&*key, &m, &r)
+        }
+        None => timed_encrypt(&m),
+    };
+    Ok(HttpResponse::Ok().json(CiphertextResponse { c: query.radix.format(&ct.c) }))
+}
+
+/// POST /oracle/decrypt
+/// { "c": "..." }
+#[instrument(skip_all, fields(operation = "oracle_decrypt"))]
+async fn decrypt(
+    query: web::Query<RadixQuery>,
+    body:  web::Json<DecryptRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let _gate = rotation_gate();
+    let ct = ciphertext(&query, "c", &body.c)?;
+    let m = timed_decrypt(&ct)?;
+    Ok(HttpResponse::Ok().json(PlaintextResponse { m: query.radix.format(&m) }))
+}
+
+/// POST /oracle/add
+/// { "a": "...", "b": "..." }
+/// `a · b mod n²`, which encrypts the sum of the plaintexts. Not
+/// re-randomized, so the result is exactly what any implementation
+/// should compute.
+#[instrument(skip_all, fields(operation = "oracle_add"))]
+async fn add(
+    query: web::Query<RadixQuery>,
+    body:  web::Json<AddRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let _gate = rotation_gate();
+    let a = ciphertext(&query, "a", &body.a)?;
+    let b = ciphertext(&query, "b", &body.b)?;
+    let sum = homomorphic_addition(&a, &b)?;
+    Ok(HttpResponse::Ok().json(CiphertextResponse { c: query.radix.format(&sum.c) }))
+}
+
+/// Helper: parse `field` as a ciphertext under the server key, rejecting
+/// anything `is_valid_ciphertext` doesn't accept
+fn ciphertext(query: &RadixQuery, field: &str, s: &str) -> Result<PaillierCiphertext, ApiError> {
+    let key = key();
+    let ct = PaillierCiphertext::new(query.radix.parse(field, s)?, key.n_squared.clone());
+    check_ciphertext(&ct, &key)?;
+    Ok(ct)
+}
@@ -1,42 +1,520 @@
-// src/lib.rs
+pub mod range_proof;
+pub mod threshold;
 
-use rand::thread_rng;
+use rand::{thread_rng, CryptoRng, RngCore};
 use num_bigint::{BigUint, RandBigInt};
-use num_traits::One;
+use num_traits::{One, ToPrimitive, Zero};
+use num_bigint::BigInt;
 use num_prime::nt_funcs::is_prime;
 use num_prime::PrimalityTestConfig;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::sync::atomic::{compiler_fence, AtomicU64, Ordering};
+use std::thread;
+use tracing::debug;
 
-/// A Paillier keypair
+/// A Paillier keypair, optionally with the Damgård–Jurik generalization
+/// (`s > 1`) for plaintexts larger than `n`
 #[derive(Debug)]
 pub struct PaillierKey {
     pub n:         BigUint,
     pub n_squared: BigUint,
+    /// plaintexts live mod `n^s`, ciphertexts mod `n^(s+1)`; 1 is
+    /// standard Paillier
+    pub s:         u32,
+    /// `n^s`, the plaintext modulus
+    pub n_s:       BigUint,
+    /// `n^(s+1)`, the ciphertext modulus; `n_squared` when `s == 1`
+    pub modulus:   BigUint,
     pub g:         BigUint,
     pub lambda:    BigUint,
+    /// λ⁻¹ mod `n^s`
     pub mu:        BigUint,
+    /// the factors of `n`; secret, see `reveal_primes`
+    p:             BigUint,
+    q:             BigUint,
+    crt:           CrtParams,
+}
+
+/// Values precomputed from `p` and `q` for `decrypt_crt`
+#[derive(Debug)]
+struct CrtParams {
+    p_squared: BigUint,
+    q_squared: BigUint,
+    /// L_p(g^(p-1) mod p²)⁻¹ mod p
+    hp:        BigUint,
+    /// L_q(g^(q-1) mod q²)⁻¹ mod q
+    hq:        BigUint,
+    /// q⁻¹ mod p, for recombination
+    q_inv:     BigUint,
+}
+
+impl Drop for PaillierKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Helper: overwrite `x`'s digits in place with zeros, leaving it 0.
+/// `assign_from_slice` reuses the existing buffer, which is already long
+/// enough for as many zero digits as `x` has.
+fn wipe(x: &mut BigUint) {
+    let zeros = vec![0u32; x.bits().div_ceil(32) as usize];
+    x.assign_from_slice(&zeros);
+}
+
+/// Which kind of primes key generation draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrimeKind {
+    /// any probable prime
+    #[default]
+    Standard,
+    /// safe primes `p = 2p' + 1` with `p'` also prime. Much slower to
+    /// find: expect minutes rather than seconds for 2048-bit keys.
+    Safe,
+}
+
+/// How many prime pairs key generation draws before giving up
+const MAX_KEYGEN_ATTEMPTS: usize = 100;
+
+/// Prime pairs redrawn by key generation since startup
+static KEYGEN_RETRIES: AtomicU64 = AtomicU64::new(0);
+
+/// How many times key generation, across every key made in this process,
+/// has thrown away a prime pair and drawn another. Retries are rare with
+/// a healthy RNG, so a climbing count points at poor entropy.
+pub fn keygen_retries() -> u64 {
+    KEYGEN_RETRIES.load(Ordering::Relaxed)
 }
 
 impl PaillierKey {
     /// Generate a new keypair with `bits` total size.
-    pub fn new(bits: usize) -> Self {
-        let p = gen_prime(bits/2);
-        let q = gen_prime(bits/2);
+    pub fn new(bits: usize) -> Result<Self, KeyGenError> {
+        PaillierKey::new_with_options(bits, PrimeKind::Standard)
+    }
+
+    /// Generate a new keypair with `bits` total size from `kind` primes.
+    /// Redraws the primes whenever they don't form a valid key (`p == q`,
+    /// or `gcd(n, λ) != 1`), up to a bounded number of attempts.
+    pub fn new_with_options(bits: usize, kind: PrimeKind) -> Result<Self, KeyGenError> {
+        PaillierKey::new_with_config(bits, kind, PrimalityTestConfig::default())
+    }
+
+    /// Like `new_with_options`, but testing candidate primes with
+    /// `config`. A lighter config than the default speeds up development
+    /// startup at the cost of a (small) chance of accepting a composite;
+    /// don't use one in production.
+    ///
+    /// `p` and `q` are searched for on two threads at once, which roughly
+    /// halves the wait. Each draws from its own `thread_rng`, seeded
+    /// independently from the OS on first use in that thread.
+    pub fn new_with_config(
+        bits:   usize,
+        kind:   PrimeKind,
+        config: PrimalityTestConfig,
+    ) -> Result<Self, KeyGenError> {
+        PaillierKey::from_prime_pairs(|| {
+            thread::scope(|scope| {
+                let q = scope.spawn(|| draw_prime(bits/2, kind, config, &mut thread_rng()));
+                let p = draw_prime(bits/2, kind, config, &mut thread_rng());
+                Ok((p?, q.join().expect("prime search thread panicked")?))
+            })
+        })
+    }
+
+    /// Like `new_with_config`, but drawing the primes from `rng`, e.g. a
+    /// hardware source, or a seeded one for reproducible tests. With a
+    /// single `rng` the primes are found one after the other.
+    pub fn new_with_rng(
+        bits:   usize,
+        kind:   PrimeKind,
+        config: PrimalityTestConfig,
+        rng:    &mut (impl RngCore + CryptoRng),
+    ) -> Result<Self, KeyGenError> {
+        PaillierKey::from_prime_pairs(|| {
+            let p = draw_prime(bits/2, kind, config, rng)?;
+            let q = draw_prime(bits/2, kind, config, rng)?;
+            Ok((p, q))
+        })
+    }
+
+    /// The first valid key from pairs drawn by `draw_pair`, redrawing up
+    /// to `MAX_KEYGEN_ATTEMPTS` times. Each redraw is logged at DEBUG and
+    /// counted in `keygen_retries`. A prime search that gives up ends key
+    /// generation at once: the RNG is not producing usable numbers.
+    fn from_prime_pairs(
+        mut draw_pair: impl FnMut() -> Result<(BigUint, BigUint), PrimeGenError>,
+    ) -> Result<Self, KeyGenError> {
+        for attempt in 1..=MAX_KEYGEN_ATTEMPTS {
+            let (p, q) = draw_pair().map_err(KeyGenError::PrimeGen)?;
+            match PaillierKey::from_checked_primes(p, q, 1) {
+                Ok(key) => return Ok(key),
+                Err(reason) => {
+                    KEYGEN_RETRIES.fetch_add(1, Ordering::Relaxed);
+                    debug!(attempt, %reason, "unusable primes; redrawing");
+                }
+            }
+        }
+        Err(KeyGenError::AttemptsExhausted(MAX_KEYGEN_ATTEMPTS))
+    }
+
+    /// Generate a Damgård–Jurik keypair with `bits` total size: like `new`,
+    /// but encrypting plaintexts up to `n^s` into ciphertexts mod
+    /// `n^(s+1)`. `s == 1` is the same as `new`.
+    pub fn new_with_s(bits: usize, s: u32) -> Result<Self, KeyGenError> {
+        let key = PaillierKey::new(bits)?;
+        PaillierKey::from_checked_primes(key.p.clone(), key.q.clone(), s)
+    }
+
+    /// Build a keypair from the primes `p` and `q`, e.g. fixed ones for a
+    /// deterministic deployment. Both must be distinct probable primes.
+    pub fn from_primes(p: BigUint, q: BigUint) -> Result<Self, KeyGenError> {
+        PaillierKey::from_primes_with_s(p, q, 1)
+    }
+
+    /// Like `from_primes`, with the Damgård–Jurik exponent `s` (at least 1)
+    pub fn from_primes_with_s(p: BigUint, q: BigUint, s: u32) -> Result<Self, KeyGenError> {
+        let config = Some(PrimalityTestConfig::default());
+        if !is_prime(&p, config).probably() || !is_prime(&q, config).probably() {
+            return Err(KeyGenError::NotPrime);
+        }
+        PaillierKey::from_checked_primes(p, q, s)
+    }
+
+    /// `from_primes_with_s` for primes already known to be prime
+    fn from_checked_primes(p: BigUint, q: BigUint, s: u32) -> Result<Self, KeyGenError> {
+        if s == 0 {
+            return Err(KeyGenError::InvalidS);
+        }
+        if p == q {
+            return Err(KeyGenError::EqualPrimes);
+        }
 
         let n         = &p * &q;
         let n_squared = &n * &n;
+        let n_s       = n.pow(s);
+        let modulus   = &n_s * &n;
         let g         = &n + BigUint::one();
         let lambda    = (&p - BigUint::one()) * (&q - BigUint::one());
-        let mu        = lambda.modinv(&n)
-                             .expect("λ must be invertible mod n");
+        // fails exactly when gcd(n, λ) != 1
+        let mu        = lambda.modinv(&n_s)
+                             .ok_or(KeyGenError::NotInvertible("λ mod n^s"))?;
+
+        let p_squared = &p * &p;
+        let q_squared = &q * &q;
+        let hp        = l_function(&g.modpow(&(&p - BigUint::one()), &p_squared), &p)
+                            .and_then(|l| l.modinv(&p))
+                            .ok_or(KeyGenError::NotInvertible("h_p mod p"))?;
+        let hq        = l_function(&g.modpow(&(&q - BigUint::one()), &q_squared), &q)
+                            .and_then(|l| l.modinv(&q))
+                            .ok_or(KeyGenError::NotInvertible("h_q mod q"))?;
+        let q_inv     = q.modinv(&p)
+                         .ok_or(KeyGenError::NotInvertible("q mod p"))?;
+        let crt = CrtParams { p_squared, q_squared, hp, hq, q_inv };
+
+        Ok(PaillierKey { n, n_squared, s, n_s, modulus, g, lambda, mu, p, q, crt })
+    }
+
+    /// The secret factors `(p, q)` of `n`. Anyone holding them can
+    /// decrypt everything, so only hand them to code that needs them.
+    pub fn reveal_primes(&self) -> (&BigUint, &BigUint) {
+        (&self.p, &self.q)
+    }
+
+    /// Overwrite the secret values (`λ`, `μ`, `p`, `q` and the CRT
+    /// parameters) with zeros, leaving the public key intact. The key can
+    /// still encrypt, but decryption fails with `DecryptError::Erased`
+    /// and `validate` with false. Runs on drop too.
+    ///
+    /// Best effort: copies made by earlier arithmetic, `to_json`, or a
+    /// reallocation are out of reach.
+    pub fn zeroize(&mut self) {
+        let CrtParams { p_squared, q_squared, hp, hq, q_inv } = &mut self.crt;
+        for secret in [&mut self.lambda, &mut self.mu, &mut self.p, &mut self.q,
+                       p_squared, q_squared, hp, hq, q_inv] {
+            wipe(secret);
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// Are the key's values consistent: `n = p·q` and
+    /// `λ = (p-1)(q-1)`?
+    pub fn validate(&self) -> bool {
+        self.n == &self.p * &self.q
+            && self.lambda == (&self.p - BigUint::one()) * (&self.q - BigUint::one())
+    }
 
-        PaillierKey { n, n_squared, g, lambda, mu }
+    /// Export the keypair as JSON, with every value as a decimal string.
+    pub fn to_json(&self) -> String {
+        let repr = KeyRepr {
+            n:      self.n.to_str_radix(10),
+            g:      self.g.to_str_radix(10),
+            lambda: self.lambda.to_str_radix(10),
+            mu:     self.mu.to_str_radix(10),
+            p:      self.p.to_str_radix(10),
+            q:      self.q.to_str_radix(10),
+            s:      self.s,
+        };
+        serde_json::to_string(&repr).expect("key serialization cannot fail")
+    }
+
+    /// Import a keypair exported by `to_json`, rejecting files whose
+    /// values are inconsistent with each other.
+    pub fn from_json(s: &str) -> Result<PaillierKey, KeyError> {
+        let repr: KeyRepr = serde_json::from_str(s).map_err(KeyError::Json)?;
+        let parse = |field: &'static str, v: &str| {
+            BigUint::parse_bytes(v.as_bytes(), 10).ok_or(KeyError::InvalidNumber(field))
+        };
+        let n      = parse("n", &repr.n)?;
+        let g      = parse("g", &repr.g)?;
+        let lambda = parse("lambda", &repr.lambda)?;
+        let mu     = parse("mu", &repr.mu)?;
+        let p      = parse("p", &repr.p)?;
+        let q      = parse("q", &repr.q)?;
+
+        if n != &p * &q {
+            return Err(KeyError::Inconsistent("n != p·q"));
+        }
+        if g != &n + BigUint::one() {
+            return Err(KeyError::Inconsistent("g != n + 1"));
+        }
+        if lambda != (&p - BigUint::one()) * (&q - BigUint::one()) {
+            return Err(KeyError::Inconsistent("λ != (p-1)(q-1)"));
+        }
+        if repr.s == 0 {
+            return Err(KeyError::Inconsistent("s must be at least 1"));
+        }
+        if lambda.modinv(&n.pow(repr.s)).as_ref() != Some(&mu) {
+            return Err(KeyError::Inconsistent("μ != λ⁻¹ mod n^s"));
+        }
+        let config = Some(PrimalityTestConfig::default());
+        if !is_prime(&p, config).probably() || !is_prime(&q, config).probably() {
+            return Err(KeyError::Inconsistent("p and q must be prime"));
+        }
+
+        PaillierKey::from_checked_primes(p, q, repr.s)
+            .map_err(|_| KeyError::Inconsistent("p and q do not form a valid key"))
     }
 }
 
-/// Generate a random prime of exactly `bits` length.
-fn gen_prime(bits: usize) -> BigUint {
-    let mut rng = thread_rng();
-    loop {
+/// Portable JSON form of a keypair; `n²` and `n^(s+1)` are recomputed
+/// on load
+#[derive(Serialize, Deserialize)]
+struct KeyRepr {
+    n:      String,
+    g:      String,
+    lambda: String,
+    mu:     String,
+    p:      String,
+    q:      String,
+    /// absent in key files written before Damgård–Jurik support
+    #[serde(default = "standard_s")]
+    s:      u32,
+}
+
+fn standard_s() -> u32 {
+    1
+}
+
+/// Why a keypair couldn't be imported
+#[derive(Debug)]
+pub enum KeyError {
+    /// not valid JSON, or missing fields
+    Json(serde_json::Error),
+    /// the named field isn't a decimal integer
+    InvalidNumber(&'static str),
+    /// the values don't form a valid keypair
+    Inconsistent(&'static str),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Json(e)            => write!(f, "malformed key JSON: {e}"),
+            KeyError::InvalidNumber(fld) => write!(f, "key field `{fld}` is not a decimal integer"),
+            KeyError::Inconsistent(why)  => write!(f, "inconsistent key: {why}"),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// Why a keypair couldn't be generated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyGenError {
+    /// `p == q`
+    EqualPrimes,
+    /// `p` or `q` failed the primality test
+    NotPrime,
+    /// the named value has no modular inverse, so the primes are unusable
+    NotInvertible(&'static str),
+    /// every attempt drew unusable primes
+    AttemptsExhausted(usize),
+    /// the Damgård–Jurik exponent `s` was 0
+    InvalidS,
+    /// a prime search gave up
+    PrimeGen(PrimeGenError),
+}
+
+impl fmt::Display for KeyGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyGenError::EqualPrimes          => write!(f, "p and q must be distinct"),
+            KeyGenError::NotPrime             => write!(f, "p and q must be prime"),
+            KeyGenError::NotInvertible(what)  => write!(f, "{what} is not invertible"),
+            KeyGenError::AttemptsExhausted(n) => write!(f, "no valid key found after {n} attempts"),
+            KeyGenError::InvalidS             => write!(f, "s must be at least 1"),
+            KeyGenError::PrimeGen(e)          => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for KeyGenError {}
+
+/// Why `gen_prime` found no prime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrimeGenError {
+    /// none of the given number of candidates was prime. With a working
+    /// RNG this essentially never happens at the default cap (for 1024-bit
+    /// primes, about e⁻²⁸); it points to a broken or exhausted source.
+    CandidatesExhausted(usize),
+}
+
+impl fmt::Display for PrimeGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrimeGenError::CandidatesExhausted(n) => write!(f, "no prime among {n} candidates"),
+        }
+    }
+}
+
+impl std::error::Error for PrimeGenError {}
+
+/// Why a ciphertext couldn't be decrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecryptError {
+    /// `c >= n²`
+    OutOfRange,
+    /// `c` isn't a unit mod n², so no plaintext/randomness pair maps to it
+    Malformed,
+    /// the key's secret values were wiped by `PaillierKey::zeroize`
+    Erased,
+    /// the operation only supports standard Paillier keys (`s = 1`)
+    UnsupportedKey,
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptError::OutOfRange     => write!(f, "ciphertext is not below n²"),
+            DecryptError::Malformed      => write!(f, "ciphertext is not a unit mod n²"),
+            DecryptError::Erased         => write!(f, "the secret key has been zeroized"),
+            DecryptError::UnsupportedKey => write!(f, "the operation needs a key with s = 1"),
+        }
+    }
+}
+
+impl std::error::Error for DecryptError {}
+
+/// What `add_plaintext_with_policy` does with a sum past `max_plaintext`
+/// (`--overflow-policy`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// refuse with `RangeError::Overflow`
+    #[default]
+    Reject,
+    /// add only as much as fits, leaving the balance at `max_plaintext`
+    Saturate,
+    /// add it all, wrapping around into the values that decode as negative
+    Wrap,
+}
+
+/// Why `checked_add_plaintext` refused to add
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeError {
+    /// the ciphertext didn't decrypt
+    Decrypt(DecryptError),
+    /// the sum would pass `max`, the largest value the signed encoding
+    /// holds; at most `headroom` more could have been added
+    Overflow { max: BigUint, headroom: BigUint },
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeError::Decrypt(e)                => write!(f, "{e}"),
+            RangeError::Overflow { headroom, .. } => {
+                write!(f, "sum would overflow the signed range ({headroom} left)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+impl From<DecryptError> for RangeError {
+    fn from(e: DecryptError) -> Self {
+        RangeError::Decrypt(e)
+    }
+}
+
+/// Two ciphertexts can't be combined: they're under different keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MismatchError;
+
+impl fmt::Display for MismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ciphertexts are under different keys (n² differs)")
+    }
+}
+
+impl std::error::Error for MismatchError {}
+
+/// L(u) = (u − 1) / d, or `None` unless u ≡ 1 (mod d), which a valid
+/// ciphertext always gives
+fn l_function(u: &BigUint, d: &BigUint) -> Option<BigUint> {
+    if u.is_zero() {
+        return None;
+    }
+    let u_1 = u - BigUint::one();
+    (&u_1 % d).is_zero().then(|| u_1 / d)
+}
+
+/// How many candidates key generation tests per prime before giving up
+pub const DEFAULT_MAX_PRIME_CANDIDATES: usize = 10_000;
+
+/// A random `kind` prime of exactly `bits` length, drawn from `rng`
+fn draw_prime(
+    bits:   usize,
+    kind:   PrimeKind,
+    config: PrimalityTestConfig,
+    rng:    &mut (impl RngCore + CryptoRng),
+) -> Result<BigUint, PrimeGenError> {
+    match kind {
+        PrimeKind::Standard => gen_prime(bits, config, DEFAULT_MAX_PRIME_CANDIDATES, rng),
+        PrimeKind::Safe     => gen_safe_prime(bits, config, DEFAULT_MAX_PRIME_CANDIDATES, rng),
+    }
+}
+
+/// Generate a random prime of exactly `bits` length, drawing candidates
+/// from `rng` and giving up after `max_candidates` of them.
+///
+/// Each candidate is drawn uniformly from the odd `bits`-bit integers
+/// (forcing the top and low bits only narrows the range; every prime
+/// of that length is odd with its top bit set) and kept if it passes the
+/// primality test. Because candidates are independent, this is
+/// rejection sampling: every `bits`-bit prime is equally likely, unlike
+/// stepping upward from one random start, which favors primes after
+/// long gaps.
+pub fn gen_prime(
+    bits:           usize,
+    config:         PrimalityTestConfig,
+    max_candidates: usize,
+    rng:            &mut (impl RngCore + CryptoRng),
+) -> Result<BigUint, PrimeGenError> {
+    for _ in 0..max_candidates {
         // 1) random < 2^bits
         let mut cand = rng.gen_biguint(bits.try_into().unwrap());
         // 2) ensure high bit is set -> exactly `bits` long
@@ -44,10 +522,31 @@ fn gen_prime(bits: usize) -> BigUint {
         // 3) ensure odd
         cand |= BigUint::one();
         // 4) Miller–Rabin or BPSW probabilistic test
-        if is_prime(&cand, Some(PrimalityTestConfig::default())).probably() {
-            return cand;
+        if is_prime(&cand, Some(config)).probably() {
+            return Ok(cand);
+        }
+    }
+    Err(PrimeGenError::CandidatesExhausted(max_candidates))
+}
+
+/// Generate a random safe prime `p = 2p' + 1` of exactly `bits` length,
+/// drawing candidates from `rng`. Up to `max_candidates` primes `p'` are
+/// tried, each found within `max_candidates` candidates of its own.
+fn gen_safe_prime(
+    bits:           usize,
+    config:         PrimalityTestConfig,
+    max_candidates: usize,
+    rng:            &mut (impl RngCore + CryptoRng),
+) -> Result<BigUint, PrimeGenError> {
+    for _ in 0..max_candidates {
+        // p' is a (bits-1)-bit prime, so 2p' + 1 has exactly `bits` bits
+        let sophie_germain = gen_prime(bits - 1, config, max_candidates, rng)?;
+        let cand = (sophie_germain << 1) + BigUint::one();
+        if is_prime(&cand, Some(config)).probably() {
+            return Ok(cand);
         }
     }
+    Err(PrimeGenError::CandidatesExhausted(max_candidates))
 }
 
 /// A Paillier ciphertext
@@ -55,6 +554,7 @@ fn gen_prime(bits: usize) -> BigUint {
 #[derive(Clone)]
 pub struct PaillierCiphertext {
     pub c:         BigUint,
+    /// the ciphertext modulus: n², or n^(s+1) under a Damgård–Jurik key
     pub n_squared: BigUint,
 }
 
@@ -62,34 +562,1581 @@ impl PaillierCiphertext {
     pub fn new(c: BigUint, n_squared: BigUint) -> Self {
         PaillierCiphertext { c, n_squared }
     }
+
+    /// `self + other`, or `None` if they're under different keys
+    pub fn checked_add(&self, other: &PaillierCiphertext) -> Option<PaillierCiphertext> {
+        homomorphic_addition(self, other).ok()
+    }
+
+    /// Compact binary form: `c`, then `n²`, each as little-endian bytes
+    /// behind a u32 little-endian length
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_prefixed(&mut out, &self.c.to_bytes_le());
+        write_prefixed(&mut out, &self.n_squared.to_bytes_le());
+        out
+    }
+
+    /// Parse the output of `to_bytes`, or `None` if `bytes` isn't exactly
+    /// one such encoding
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let c         = BigUint::from_bytes_le(read_prefixed(&mut bytes)?);
+        let n_squared = BigUint::from_bytes_le(read_prefixed(&mut bytes)?);
+        bytes.is_empty().then(|| PaillierCiphertext::new(c, n_squared))
+    }
+}
+
+/// Append `bytes` to `out` behind their length as a u32 little-endian
+pub(crate) fn write_prefixed(out: &mut Vec<u8>, bytes: &[u8]) {
+    let len = u32::try_from(bytes.len()).expect("field longer than u32::MAX bytes");
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Split one `write_prefixed` field off the front of `input`
+pub(crate) fn read_prefixed<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let (len, rest) = input.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    let (field, rest) = rest.split_at(len);
+    *input = rest;
+    Some(field)
+}
+
+/// Homomorphic addition: `&a + &b` encrypts the sum of the plaintexts.
+/// Panics if `a` and `b` are under different keys; see `checked_add`.
+impl Add for &PaillierCiphertext {
+    type Output = PaillierCiphertext;
+
+    fn add(self, other: &PaillierCiphertext) -> PaillierCiphertext {
+        self.checked_add(other)
+            .expect("cannot add ciphertexts under different keys (n² differs)")
+    }
+}
+
+impl Add<&PaillierCiphertext> for PaillierCiphertext {
+    type Output = PaillierCiphertext;
+
+    fn add(self, other: &PaillierCiphertext) -> PaillierCiphertext {
+        &self + other
+    }
+}
+
+/// Homomorphic scalar multiplication: `&ct * &k` encrypts `k·m`. Without
+/// the key `k` can't be reduced first; see `homomorphic_scalar_mul_with_key`.
+impl Mul<&BigUint> for &PaillierCiphertext {
+    type Output = PaillierCiphertext;
+
+    fn mul(self, k: &BigUint) -> PaillierCiphertext {
+        PaillierCiphertext::new(self.c.modpow(k, &self.n_squared), self.n_squared.clone())
+    }
+}
+
+/// Wire form of a ciphertext: both values as decimal strings
+#[derive(Serialize, Deserialize)]
+struct CiphertextRepr {
+    c:         String,
+    n_squared: String,
+}
+
+impl Serialize for PaillierCiphertext {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CiphertextRepr {
+            c:         self.c.to_str_radix(10),
+            n_squared: self.n_squared.to_str_radix(10),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PaillierCiphertext {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = CiphertextRepr::deserialize(deserializer)?;
+        let c         = parse_decimal::<D::Error>("c", &repr.c)?;
+        let n_squared = parse_decimal::<D::Error>("n_squared", &repr.n_squared)?;
+        Ok(PaillierCiphertext::new(c, n_squared))
+    }
+}
+
+/// Parse a decimal string into a `BigUint`, naming `field` on failure.
+fn parse_decimal<E: de::Error>(field: &str, s: &str) -> Result<BigUint, E> {
+    BigUint::parse_bytes(s.as_bytes(), 10)
+        .ok_or_else(|| E::custom(format!("`{field}` is not a decimal integer")))
+}
+
+/// A non-negative plaintext amount, as carried in requests. On the wire
+/// it's a JSON number, or a decimal string for values beyond `u64` (e.g.
+/// 18-decimal token units), which JSON numbers can't carry exactly.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Amount(BigUint);
+
+impl Amount {
+    pub fn get(&self) -> &BigUint {
+        &self.0
+    }
+
+    /// `encrypt(key, self)`
+    pub fn encrypt_under(&self, key: &PaillierKey) -> PaillierCiphertext {
+        encrypt(key, &self.0)
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(v: u64) -> Self {
+        Amount(BigUint::from(v))
+    }
+}
+
+impl From<BigUint> for Amount {
+    fn from(v: BigUint) -> Self {
+        Amount(v)
+    }
+}
+
+impl From<Amount> for BigUint {
+    fn from(a: Amount) -> Self {
+        a.0
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.to_u64() {
+            Some(v) => serializer.serialize_u64(v),
+            None    => serializer.serialize_str(&self.0.to_str_radix(10)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AmountVisitor;
+
+        impl de::Visitor<'_> for AmountVisitor {
+            type Value = Amount;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a non-negative integer, as a number or a decimal string")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Amount, E> {
+                Ok(Amount::from(v))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Amount, E> {
+                u64::try_from(v)
+                    .map(Amount::from)
+                    .map_err(|_| E::custom(format!("amount must be non-negative, got {v}")))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Amount, E> {
+                // parse_bytes would also take `+` and `_`; only plain digits are an amount
+                if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(E::custom(format!("`{v}` is not a decimal integer")));
+                }
+                parse_decimal("amount", v).map(Amount)
+            }
+        }
+
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+/// A signed, decrypted balance. Serializes like `Amount`: a JSON number
+/// when it fits in 64 bits, otherwise a decimal string.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Balance(BigInt);
+
+impl Balance {
+    pub fn get(&self) -> &BigInt {
+        &self.0
+    }
+}
+
+impl From<BigInt> for Balance {
+    fn from(v: BigInt) -> Self {
+        Balance(v)
+    }
+}
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Balance {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.to_i64() {
+            Some(v) => serializer.serialize_i64(v),
+            None    => serializer.serialize_str(&self.0.to_str_radix(10)),
+        }
+    }
+}
+
+/// The public half of a keypair: enough to encrypt, nothing more
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaillierPublicKey {
+    pub n:         BigUint,
+    pub g:         BigUint,
+    pub n_squared: BigUint,
+    pub s:         u32,
+    pub n_s:       BigUint,
+    pub modulus:   BigUint,
+}
+
+impl PaillierPublicKey {
+    pub fn new(n: BigUint, g: BigUint) -> Self {
+        PaillierPublicKey::new_with_s(n, g, 1)
+    }
+
+    /// Public key for a Damgård–Jurik keypair with exponent `s`
+    pub fn new_with_s(n: BigUint, g: BigUint, s: u32) -> Self {
+        let n_squared = &n * &n;
+        let n_s       = n.pow(s);
+        let modulus   = &n_s * &n;
+        PaillierPublicKey { n, g, n_squared, s, n_s, modulus }
+    }
+    /// SHA-256 of the canonical serialization of `(n, g)`, as lowercase
+    /// hex: each value as its big-endian bytes, prefixed with their
+    /// length as a big-endian u64. Changes whenever the key does, so
+    /// clients can tell a cached key is stale.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for value in [&self.n, &self.g] {
+            let bytes = value.to_bytes_be();
+            hasher.update((bytes.len() as u64).to_be_bytes());
+            hasher.update(bytes);
+        }
+        hasher.finalize().iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+impl PaillierKey {
+    /// Copy of the public components, safe to hand to clients
+    pub fn public_key(&self) -> PaillierPublicKey {
+        PaillierPublicKey {
+            n:         self.n.clone(),
+            g:         self.g.clone(),
+            n_squared: self.n_squared.clone(),
+            s:         self.s,
+            n_s:       self.n_s.clone(),
+            modulus:   self.modulus.clone(),
+        }
+    }
+}
+
+/// Key material that can encrypt: a full `PaillierKey`, or a
+/// `PaillierPublicKey` for callers without the private components
+pub trait EncryptionKey {
+    fn n(&self) -> &BigUint;
+    fn g(&self) -> &BigUint;
+    fn s(&self) -> u32;
+    /// the plaintext modulus `n^s`
+    fn n_s(&self) -> &BigUint;
+    /// the ciphertext modulus `n^(s+1)`
+    fn modulus(&self) -> &BigUint;
+}
+
+impl EncryptionKey for PaillierKey {
+    fn n(&self) -> &BigUint {
+        &self.n
+    }
+
+    fn g(&self) -> &BigUint {
+        &self.g
+    }
+
+    fn s(&self) -> u32 {
+        self.s
+    }
+
+    fn n_s(&self) -> &BigUint {
+        &self.n_s
+    }
+
+    fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
+}
+
+impl EncryptionKey for PaillierPublicKey {
+    fn n(&self) -> &BigUint {
+        &self.n
+    }
+
+    fn g(&self) -> &BigUint {
+        &self.g
+    }
+
+    fn s(&self) -> u32 {
+        self.s
+    }
+
+    fn n_s(&self) -> &BigUint {
+        &self.n_s
+    }
+
+    fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
 }
 
 /// Encrypt `m` under `key`
-pub fn encrypt(key: &PaillierKey, m: &BigUint) -> PaillierCiphertext {
+pub fn encrypt(key: &impl EncryptionKey, m: &BigUint) -> PaillierCiphertext {
+    encrypt_with_rng(key, m, &mut thread_rng())
+}
+
+/// Encrypt `m` under the public key `(n, g)` alone
+pub fn encrypt_with_pubkey(n: &BigUint, g: &BigUint, m: &BigUint) -> PaillierCiphertext {
+    encrypt(&PaillierPublicKey::new(n.clone(), g.clone()), m)
+}
+
+/// Encrypt `m` under `key`, drawing the randomness from `rng`. A seeded
+/// `rng` gives reproducible ciphertexts, so only use one in tests.
+pub fn encrypt_with_rng(
+    key: &impl EncryptionKey,
+    m:   &BigUint,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> PaillierCiphertext {
+    let r: BigUint = rng.gen_biguint_below(key.n());
+    encrypt_with_randomness(key, m, &r)
+}
+
+/// Encrypt `m` under `key` with caller-supplied randomness `r`.
+/// The same `m` and `r` always give the same ciphertext, so this is
+/// for tests and proofs; `r` must be secret and unique in real use.
+pub fn encrypt_with_randomness(key: &impl EncryptionKey, m: &BigUint, r: &BigUint) -> PaillierCiphertext {
+    encrypt_with_factor(key, m, &r.modpow(key.n_s(), key.modulus()))
+}
+
+/// A fresh blinding factor `r^(n^s) mod n^(s+1)` for `encrypt_with_factor`:
+/// the modpow that dominates `encrypt`, done ahead of time. Only the
+/// factor is returned; `r` itself is discarded.
+pub fn randomness_factor(key: &impl EncryptionKey, rng: &mut (impl RngCore + CryptoRng)) -> BigUint {
+    let r: BigUint = rng.gen_biguint_range(&BigUint::one(), key.n());
+    r.modpow(key.n_s(), key.modulus())
+}
+
+/// Encrypt `m` under `key` with a factor from `randomness_factor`. Like
+/// `r` in `encrypt_with_randomness`, each factor must be used only once.
+pub fn encrypt_with_factor(key: &impl EncryptionKey, m: &BigUint, factor: &BigUint) -> PaillierCiphertext {
+    let c = g_pow(key, m) * factor % key.modulus();
+    PaillierCiphertext::new(c, key.modulus().clone())
+}
+
+/// g^m mod n^(s+1). For the usual g = n + 1 and s = 1 the binomial
+/// expansion collapses to 1 + m·n, skipping the modpow entirely; other
+/// keys fall back to it.
+fn g_pow(key: &impl EncryptionKey, m: &BigUint) -> BigUint {
+    if key.s() == 1 && key.g() == &(key.n() + BigUint::one()) {
+        (BigUint::one() + m * key.n()) % key.modulus()
+    } else {
+        key.g().modpow(m, key.modulus())
+    }
+}
+
+/// Re-randomize `ct`: multiply by a fresh `r^(n^s) mod n^(s+1)`, which
+/// leaves the plaintext unchanged but makes the new `c` unlinkable to
+/// the old one
+pub fn rerandomize(ct: &PaillierCiphertext, key: &PaillierKey) -> PaillierCiphertext {
     let mut rng = thread_rng();
-    let r: BigUint = rng.gen_biguint_below(&key.n);
+    let r: BigUint = rng.gen_biguint_range(&BigUint::one(), &key.n);
+
+    let c = &ct.c * r.modpow(&key.n_s, &key.modulus) % &key.modulus;
+    PaillierCiphertext::new(c, key.modulus.clone())
+}
+
+// Side channels: the secret exponents (λ, p − 1, q − 1) pass through
+// num-bigint's `modpow`, which is not constant-time; its running time
+// depends on the exponent's bits. Since callers can submit ciphertexts
+// and time responses, each decryption adds a fresh random multiple of
+// the group order to the exponent: the result is unchanged, but every
+// call runs with a different exponent, so timings don't accumulate
+// into information about the secret. This roughly doubles the cost of
+// each exponentiation. Bignum multiplication and reduction still vary
+// slightly with operand sizes; that smaller leak is not addressed here.
+
+/// Bits of randomness in each exponent blinding factor
+const BLINDING_BITS: u64 = 64;
+
+/// `exp + k·order` for a random `k`, which gives the same `modpow` result
+/// for any base whose multiplicative order divides `order`
+fn blind_exponent(exp: &BigUint, order: &BigUint) -> BigUint {
+    let k = thread_rng().gen_biguint(BLINDING_BITS);
+    exp + k * order
+}
+
+/// Could `ct` be an encryption under `key`: `0 < c < n²` (`n^(s+1)` for
+/// Damgård–Jurik) and `gcd(c, n) == 1`? Check client-supplied ciphertexts
+/// with it before computing on them.
+pub fn is_valid_ciphertext(ct: &PaillierCiphertext, key: &PaillierKey) -> bool {
+    // c is invertible mod n exactly when it's coprime to n, which rules out 0
+    ct.c < key.modulus && ct.c.modinv(&key.n).is_some()
+}
+
+/// Decrypt a Paillier ciphertext, rejecting values that aren't valid
+/// ciphertexts under `key`
+pub fn decrypt(key: &PaillierKey, ct: &PaillierCiphertext) -> Result<BigUint, DecryptError> {
+    if key.lambda.is_zero() {
+        return Err(DecryptError::Erased);
+    }
+    if ct.c >= key.modulus {
+        return Err(DecryptError::OutOfRange);
+    }
+    // |Z*_{n^(s+1)}| = n^s·λ, so λ may be blinded by multiples of it
+    let exp = blind_exponent(&key.lambda, &(&key.n_s * &key.lambda));
+    let x = ct.c.modpow(&exp, &key.modulus);
+    if key.s == 1 {
+        // m = L(c^λ mod n²) · μ mod n, where L(u) = (u − 1) / n
+        let l = l_function(&x, &key.n).ok_or(DecryptError::Malformed)?;
+        return Ok((&l * &key.mu) % &key.n);
+    }
+    // c^λ = (1 + n)^(λ·m) mod n^(s+1); recover λ·m mod n^s from it
+    let l = dj_log(&x, key).ok_or(DecryptError::Malformed)?;
+    Ok((&l * &key.mu) % &key.n_s)
+}
+
+/// The `i < n^s` with `u = (1 + n)^i mod n^(s+1)`, peeled off one power
+/// of `n` at a time (Damgård and Jurik, 2001, §3); `None` if `u` isn't
+/// of that form
+fn dj_log(u: &BigUint, key: &PaillierKey) -> Option<BigUint> {
+    let n = &key.n;
+    let mut i   = BigUint::zero();
+    let mut n_j = n.clone();
+    for j in 1..=key.s {
+        // i is known mod n^(j-1); L(u mod n^(j+1)) is i mod n^j plus the
+        // binomial terms C(i, k)·n^(k-1) for k = 2..=j, so subtract those
+        let mut t1 = l_function(&(u % (&n_j * n)), n)?;
+        let mut t2 = i.clone();
+        let mut n_k    = BigUint::one();
+        let mut k_fact = BigUint::one();
+        for k in 2..=j {
+            i = (i + &n_j - BigUint::one()) % &n_j;
+            t2 = t2 * &i % &n_j;
+            n_k *= n;
+            k_fact *= k;
+            let term = &t2 * &n_k % &n_j * k_fact.modinv(&n_j)? % &n_j;
+            t1 = (t1 + &n_j - term) % &n_j;
+        }
+        i = t1;
+        n_j *= n;
+    }
+    Some(i)
+}
+
+/// Decrypt a Paillier ciphertext using the CRT over `p²` and `q²`.
+/// Equivalent to `decrypt`, but each exponentiation works on half-size
+/// numbers, which is several times faster for large keys. Damgård–Jurik
+/// keys (`s > 1`) fall back to `decrypt`.
+pub fn decrypt_crt(key: &PaillierKey, ct: &PaillierCiphertext) -> Result<BigUint, DecryptError> {
+    if key.s != 1 || key.lambda.is_zero() {
+        return decrypt(key, ct);
+    }
+    if ct.c >= key.n_squared {
+        return Err(DecryptError::OutOfRange);
+    }
+    let crt = &key.crt;
 
-    let c = key.g.modpow(m, &key.n_squared)
-          * r.modpow(&key.n, &key.n_squared)
-          % &key.n_squared;
+    // m_p = L_p(c^(p-1) mod p²) · h_p mod p, and likewise for q;
+    // |Z*_{p²}| = p·(p-1), so p-1 may be blinded by multiples of it
+    let p_1 = &key.p - BigUint::one();
+    let exp = blind_exponent(&p_1, &(&key.p * &p_1));
+    let c_p = &ct.c % &crt.p_squared;
+    let m_p = l_function(&c_p.modpow(&exp, &crt.p_squared), &key.p)
+            .ok_or(DecryptError::Malformed)?
+            * &crt.hp % &key.p;
 
-    PaillierCiphertext::new(c, key.n_squared.clone())
+    let q_1 = &key.q - BigUint::one();
+    let exp = blind_exponent(&q_1, &(&key.q * &q_1));
+    let c_q = &ct.c % &crt.q_squared;
+    let m_q = l_function(&c_q.modpow(&exp, &crt.q_squared), &key.q)
+            .ok_or(DecryptError::Malformed)?
+            * &crt.hq % &key.q;
+
+    // recombine without branching on secret values:
+    // m = m_q + q · ((m_p − m_q) · q⁻¹ mod p)
+    let diff = (&m_p + &key.p - (&m_q % &key.p)) % &key.p;
+    let h    = diff * &crt.q_inv % &key.p;
+    Ok(m_q + h * &key.q)
+}
+
+/// Encode a signed value as a plaintext mod `n` (pass `n^s` under a
+/// Damgård–Jurik key).
+/// Values in `[0, n/2)` are non-negative; `[n/2, n)` holds negatives
+/// as `n - |v|`, matching what homomorphic subtraction produces.
+pub fn encode_signed(v: i128, n: &BigUint) -> BigUint {
+    let magnitude = BigUint::from(v.unsigned_abs()) % n;
+    if v < 0 && !magnitude.is_zero() {
+        n - magnitude
+    } else {
+        magnitude
+    }
 }
 
-/// Decrypt a Paillier ciphertext
-pub fn decrypt(key: &PaillierKey, ct: &PaillierCiphertext) -> BigUint {
-    // m = L(c^λ mod n²) · μ mod n, where L(u) = (u − 1) / n
-    let x = ct.c.modpow(&key.lambda, &key.n_squared);
-    let l = (&x - BigUint::one()) / &key.n;
-    (&l * &key.mu) % &key.n
+/// Largest non-negative value the signed encoding can hold: n^s/2 − 1.
+/// Anything above it decodes as negative.
+pub fn max_plaintext(key: &PaillierKey) -> BigUint {
+    (&key.n_s >> 1) - BigUint::one()
 }
 
-/// Homomorphic addition of two ciphertexts
+/// Decode a plaintext mod `n` per `encode_signed`: values from n/2 up
+/// are negative.
+pub fn decode_signed(m: &BigUint, n: &BigUint) -> BigInt {
+    let half = n >> 1;
+    if m < &half {
+        BigInt::from(m.clone())
+    } else {
+        BigInt::from(m.clone()) - BigInt::from(n.clone())
+    }
+}
+
+/// Homomorphic addition of two ciphertexts, which must be under the
+/// same key
 pub fn homomorphic_addition(
     c1: &PaillierCiphertext,
     c2: &PaillierCiphertext,
+) -> Result<PaillierCiphertext, MismatchError> {
+    if c1.n_squared != c2.n_squared {
+        return Err(MismatchError);
+    }
+    let c = (&c1.c * &c2.c) % &c1.n_squared;
+    Ok(PaillierCiphertext::new(c, c1.n_squared.clone()))
+}
+
+/// Homomorphic sum of all of `cts`. An empty slice gives `c = 1`, the
+/// encryption of zero with randomness 1; like any sum of public
+/// ciphertexts, `rerandomize` it before publishing.
+pub fn homomorphic_sum(cts: &[PaillierCiphertext], n_squared: &BigUint) -> PaillierCiphertext {
+    let c = cts.iter()
+               .fold(BigUint::one(), |acc, ct| acc * &ct.c % n_squared);
+    PaillierCiphertext::new(c, n_squared.clone())
+}
+
+/// Homomorphic `Σ wᵢ·mᵢ` over `(wᵢ, Enc(mᵢ))` pairs under `key`, e.g. to
+/// convert balances in several currencies into one: `∏ cᵢ^wᵢ mod n²`.
+/// An empty slice gives a fresh encryption of zero. The result's
+/// randomness is derived from the inputs', so `rerandomize` it before
+/// publishing.
+pub fn weighted_sum(pairs: &[(BigUint, PaillierCiphertext)], key: &PaillierKey) -> PaillierCiphertext {
+    if pairs.is_empty() {
+        return encrypt(key, &BigUint::zero());
+    }
+    // weights only matter mod the plaintext modulus, so reduce them first
+    let c = pairs.iter().fold(BigUint::one(), |acc, (w, ct)| {
+        acc * ct.c.modpow(&(w % &key.n_s), &key.modulus) % &key.modulus
+    });
+    PaillierCiphertext::new(c, key.modulus.clone())
+}
+
+/// Add plaintext `m` to `ct` without encrypting it first: multiplying by
+/// `g^m` shifts the plaintext by `m`. The result shares `ct`'s randomness,
+/// so `rerandomize` it before publishing.
+pub fn add_plaintext(ct: &PaillierCiphertext, m: &BigUint, key: &PaillierKey) -> PaillierCiphertext {
+    let c = &ct.c * g_pow(key, m) % &key.modulus;
+    PaillierCiphertext::new(c, key.modulus.clone())
+}
+
+/// `add_plaintext`, but only if the sum stays within the signed range:
+/// decrypts `ct` and refuses with `RangeError::Overflow` unless
+/// `current + m <= max_plaintext(key)`, rather than letting the sum wrap
+/// around into the values that decode as negative.
+pub fn checked_add_plaintext(
+    ct:  &PaillierCiphertext,
+    m:   &BigUint,
+    key: &PaillierKey,
+) -> Result<PaillierCiphertext, RangeError> {
+    let headroom = headroom(ct, key)?;
+    if m > &headroom {
+        return Err(RangeError::Overflow { max: max_plaintext(key), headroom });
+    }
+    Ok(add_plaintext(ct, m, key))
+}
+
+/// `add_plaintext` with a sum past `max_plaintext` handled per `policy`.
+/// Returns the new ciphertext and the amount actually added: `m`, or
+/// under `Saturate` possibly less. `Wrap` needs no decryption.
+pub fn add_plaintext_with_policy(
+    ct:     &PaillierCiphertext,
+    m:      &BigUint,
+    key:    &PaillierKey,
+    policy: OverflowPolicy,
+) -> Result<(PaillierCiphertext, BigUint), RangeError> {
+    match policy {
+        OverflowPolicy::Reject   => Ok((checked_add_plaintext(ct, m, key)?, m.clone())),
+        OverflowPolicy::Wrap     => Ok((add_plaintext(ct, m, key), m.clone())),
+        OverflowPolicy::Saturate => {
+            let added = m.min(&headroom(ct, key)?).clone();
+            Ok((add_plaintext(ct, &added, key), added))
+        }
+    }
+}
+
+/// How much can be added to `ct` before it passes `max_plaintext(key)`
+fn headroom(ct: &PaillierCiphertext, key: &PaillierKey) -> Result<BigUint, DecryptError> {
+    let current = decode_signed(&decrypt_crt(key, ct)?, &key.n_s);
+    // current <= max always holds, so the headroom is never negative
+    Ok((BigInt::from(max_plaintext(key)) - current).magnitude().clone())
+}
+
+/// Homomorphic multiplication of a ciphertext by a plaintext scalar `k`,
+/// for a key with `s = 1`; see `homomorphic_scalar_mul_with_key` for
+/// Damgård–Jurik keys
+pub fn homomorphic_scalar_mul(
+    ct: &PaillierCiphertext,
+    k: &BigUint,
     n_squared: &BigUint
 ) -> PaillierCiphertext {
-    let c = (&c1.c * &c2.c) % n_squared;
+    // c^k mod n² encrypts k·m; k only matters mod n, so reduce it first
+    let n = n_squared.sqrt();
+    let k = k % &n;
+    let c = ct.c.modpow(&k, n_squared);
     PaillierCiphertext::new(c, n_squared.clone())
 }
+
+/// Like `homomorphic_scalar_mul`, under any `s`: `k` is reduced mod
+/// `n^s` and the power taken mod `n^(s+1)`
+pub fn homomorphic_scalar_mul_with_key(
+    ct: &PaillierCiphertext,
+    k: &BigUint,
+    key: &impl EncryptionKey
+) -> PaillierCiphertext {
+    let k = k % key.n_s();
+    let c = ct.c.modpow(&k, key.modulus());
+    PaillierCiphertext::new(c, key.modulus().clone())
+}
+
+/// Homomorphic subtraction: returns a ciphertext of `m1 - m2` (mod n).
+/// `Err(Malformed)` if `c2` isn't a unit, which no ciphertext made by
+/// `encrypt` can fail to be.
+pub fn homomorphic_subtraction(
+    c1: &PaillierCiphertext,
+    c2: &PaillierCiphertext,
+    key: &PaillierKey
+) -> Result<PaillierCiphertext, DecryptError> {
+    ciphertext_quotient(c1, c2, &key.modulus)
+}
+
+/// Ciphertext of `a - b` (mod n), computed from the ciphertexts alone.
+/// Whoever holds the key can decrypt it to compare `a` and `b`.
+/// `Err(Malformed)` if `b` isn't a unit.
+pub fn homomorphic_difference(
+    a: &PaillierCiphertext,
+    b: &PaillierCiphertext,
+) -> Result<PaillierCiphertext, DecryptError> {
+    ciphertext_quotient(a, b, &a.n_squared)
+}
+
+/// Ciphertext of `-m` (mod n), i.e. `c⁻¹ mod n²`; `Err(Malformed)` if
+/// `ct` isn't a unit
+pub fn negate(ct: &PaillierCiphertext, key: &PaillierKey) -> Result<PaillierCiphertext, DecryptError> {
+    ciphertext_inverse(ct, &key.modulus)
+}
+
+/// c⁻¹ mod n²; valid ciphertexts are always units mod n², and anything
+/// else is `Malformed`
+fn ciphertext_inverse(ct: &PaillierCiphertext, n_squared: &BigUint) -> Result<PaillierCiphertext, DecryptError> {
+    let inv = ct.c.modinv(n_squared).ok_or(DecryptError::Malformed)?;
+    Ok(PaillierCiphertext::new(inv, n_squared.clone()))
+}
+
+/// c1 · c2⁻¹ mod n²
+fn ciphertext_quotient(
+    c1: &PaillierCiphertext,
+    c2: &PaillierCiphertext,
+    n_squared: &BigUint
+) -> Result<PaillierCiphertext, DecryptError> {
+    let c = &c1.c * ciphertext_inverse(c2, n_squared)?.c % n_squared;
+    Ok(PaillierCiphertext::new(c, n_squared.clone()))
+}
+
+/// Do `a` and `b` encrypt the same value? Decrypts both with `key`.
+pub fn plaintext_eq(
+    key: &PaillierKey,
+    a:   &PaillierCiphertext,
+    b:   &PaillierCiphertext,
+) -> Result<bool, DecryptError> {
+    Ok(decrypt_crt(key, a)? == decrypt_crt(key, b)?)
+}
+
+/// Client state for multiplying two ciphertexts with the key holder's
+/// help. Paillier alone can't do it: `Enc(a)·Enc(b)` encrypts `a + b`,
+/// and no operation on ciphertexts and the public key yields `Enc(a·b)`.
+/// The interactive protocol takes three steps:
+///
+/// 1. `mul_step1` (client): mask the operands as `Enc(a + rₐ)` and
+///    `Enc(b + r_b)` for uniform random `rₐ`, `r_b`
+/// 2. `mul_assist` (key holder): decrypt the masked operands, which
+///    reveals nothing about `a` or `b`, and return an encryption of
+///    their product
+/// 3. `mul_step2` (client): remove the cross terms, since
+///    `(a + rₐ)(b + r_b) = ab + a·r_b + b·rₐ + rₐ·r_b`
+///
+/// Only the `a` and `b` fields are sent; the masks stay with the client.
+pub struct Blinded {
+    /// `Enc(a + rₐ)`, sent to the key holder
+    pub a: PaillierCiphertext,
+    /// `Enc(b + r_b)`, sent to the key holder
+    pub b: PaillierCiphertext,
+    ct_a: PaillierCiphertext,
+    ct_b: PaillierCiphertext,
+    r_a:  BigUint,
+    r_b:  BigUint,
+}
+
+/// Step 1 of multiplying `ct_a` by `ct_b` (see `Blinded`): mask both
+/// operands, needing only the public key
+pub fn mul_step1(ct_a: &PaillierCiphertext, ct_b: &PaillierCiphertext, key: &impl EncryptionKey) -> Blinded {
+    let mut rng = thread_rng();
+    let r_a = rng.gen_biguint_below(key.n_s());
+    let r_b = rng.gen_biguint_below(key.n_s());
+    let mask = |ct: &PaillierCiphertext, r: &BigUint| {
+        PaillierCiphertext::new(&ct.c * encrypt(key, r).c % key.modulus(), key.modulus().clone())
+    };
+    Blinded {
+        a:    mask(ct_a, &r_a),
+        b:    mask(ct_b, &r_b),
+        ct_a: ct_a.clone(),
+        ct_b: ct_b.clone(),
+        r_a,
+        r_b,
+    }
+}
+
+/// Step 2 of multiplying two ciphertexts (see `Blinded`), run by the key
+/// holder on `Blinded::a` and `Blinded::b`: a fresh encryption of the
+/// product of their plaintexts
+pub fn mul_assist(
+    key: &PaillierKey,
+    a:   &PaillierCiphertext,
+    b:   &PaillierCiphertext,
+) -> Result<PaillierCiphertext, DecryptError> {
+    let product = decrypt_crt(key, a)? * decrypt_crt(key, b)? % &key.n_s;
+    Ok(encrypt(key, &product))
+}
+
+/// Step 3 of multiplying two ciphertexts (see `Blinded`): turn the key
+/// holder's `product` of the masked operands into `Enc(a·b)` (mod n), as
+/// `product · Enc(a)^(-r_b) · Enc(b)^(-rₐ) · Enc(-rₐ·r_b)`
+pub fn mul_step2(
+    blinded: &Blinded,
+    product: &PaillierCiphertext,
+    key:     &impl EncryptionKey,
+) -> PaillierCiphertext {
+    let (n_s, modulus) = (key.n_s(), key.modulus());
+    let neg = |v: &BigUint| (n_s - v % n_s) % n_s;
+    let c = &product.c
+        * blinded.ct_a.c.modpow(&neg(&blinded.r_b), modulus) % modulus
+        * blinded.ct_b.c.modpow(&neg(&blinded.r_a), modulus) % modulus
+        * encrypt(key, &neg(&(&blinded.r_a * &blinded.r_b))).c % modulus;
+    PaillierCiphertext::new(c, modulus.clone())
+}
+
+/// Proof that a ciphertext decrypts to `m`: a Fiat–Shamir proof of
+/// knowledge of `r` with `c · g⁻ᵐ = rⁿ mod n²`. Checkable with only the
+/// public `n` and `g`, and reveals nothing beyond `m`.
+#[derive(Debug, Clone)]
+pub struct DecryptionProof {
+    /// the claimed plaintext
+    pub m: BigUint,
+    /// commitment sⁿ mod n²
+    pub a: BigUint,
+    /// response s · rᵉ mod n
+    pub z: BigUint,
+}
+
+/// Decrypt `ct` and prove the result is correct. The proof works in
+/// `n²`, so a Damgård–Jurik key is `Err(UnsupportedKey)`.
+pub fn prove_decryption(
+    key: &PaillierKey,
+    ct:  &PaillierCiphertext,
+) -> Result<DecryptionProof, DecryptError> {
+    if key.s != 1 {
+        return Err(DecryptError::UnsupportedKey);
+    }
+    let m = decrypt_crt(key, ct)?;
+
+    // u = c · g⁻ᵐ = rⁿ mod n²; recover r = u^(n⁻¹ mod φ(n)) mod n
+    let u = &ct.c * g_pow(key, &(&key.n - &m)) % &key.n_squared;
+    let n_inv = key.n.modinv(&key.lambda).expect("n must be invertible mod φ(n)");
+    let r = (&u % &key.n).modpow(&n_inv, &key.n);
+
+    let mut rng = thread_rng();
+    let s = rng.gen_biguint_range(&BigUint::one(), &key.n);
+    let a = s.modpow(&key.n, &key.n_squared);
+    let e = fiat_shamir(&[&key.n, &key.g, &ct.c, &m, &a]);
+    let z = s * r.modpow(&e, &key.n) % &key.n;
+
+    Ok(DecryptionProof { m, a, z })
+}
+
+/// Check that `proof` shows `ct` decrypts to `proof.m` under the public
+/// key `(n, g)`.
+pub fn verify_decryption(
+    n: &BigUint,
+    g: &BigUint,
+    ct: &PaillierCiphertext,
+    proof: &DecryptionProof
+) -> bool {
+    let n_squared = n * n;
+    if &proof.m >= n || ct.c >= n_squared || proof.a >= n_squared {
+        return false;
+    }
+    let Some(g_m_inv) = g.modpow(&proof.m, &n_squared).modinv(&n_squared) else {
+        return false;
+    };
+    let u = &ct.c * g_m_inv % &n_squared;
+    let e = fiat_shamir(&[n, g, &ct.c, &proof.m, &proof.a]);
+
+    // zⁿ == a · uᵉ mod n²
+    proof.z.modpow(n, &n_squared) == &proof.a * u.modpow(&e, &n_squared) % &n_squared
+}
+
+/// Proof that a ciphertext is well-formed: a Fiat–Shamir proof of
+/// knowledge of `m` and `r` with `c = gᵐ · rⁿ mod n²`. Reveals neither.
+#[derive(Debug, Clone)]
+pub struct CtProof {
+    /// commitment gˣ · sⁿ mod n²
+    pub a:  BigUint,
+    /// response x + e·m mod n
+    pub z1: BigUint,
+    /// response s · rᵉ · g^⌊(x + e·m)/n⌋ mod n
+    pub z2: BigUint,
+}
+
+/// Prove knowledge of the `m` and `r` behind `Enc(m; r)`. Panics for a
+/// Damgård–Jurik key: the proof only works in `n²`.
+pub fn prove_ciphertext(m: &BigUint, r: &BigUint, key: &PaillierKey) -> CtProof {
+    assert_eq!(key.s, 1, "ciphertext proofs need a key with s = 1");
+    let c = encrypt_with_randomness(key, m, r).c;
+
+    let mut rng = thread_rng();
+    let x = rng.gen_biguint_below(&key.n);
+    let s = rng.gen_biguint_range(&BigUint::one(), &key.n);
+    let a = g_pow(key, &x) * s.modpow(&key.n, &key.n_squared) % &key.n_squared;
+    let e = fiat_shamir(&[&key.n, &key.g, &c, &a]);
+
+    // the carry of x + e·m past n moves into z2, so gᶻ¹ · z2ⁿ = a · cᵉ
+    // holds for any g, not just g = n + 1
+    let t  = x + &e * m;
+    let z1 = &t % &key.n;
+    let z2 = s * r.modpow(&e, &key.n) % &key.n
+           * key.g.modpow(&(&t / &key.n), &key.n) % &key.n;
+
+    CtProof { a, z1, z2 }
+}
+
+/// Check that `proof` shows whoever built `ct` knows its plaintext and
+/// randomness, i.e. that `ct` is a genuine encryption under `key`.
+/// Always false under a Damgård–Jurik key.
+pub fn verify_ciphertext(ct: &PaillierCiphertext, proof: &CtProof, key: &PaillierKey) -> bool {
+    if key.s != 1 || ct.c.is_zero() || ct.c >= key.n_squared
+        || proof.a.is_zero() || proof.a >= key.n_squared
+        || proof.z1 >= key.n
+        || proof.z2.is_zero() || proof.z2 >= key.n
+    {
+        return false;
+    }
+    let e = fiat_shamir(&[&key.n, &key.g, &ct.c, &proof.a]);
+
+    // gᶻ¹ · z2ⁿ == a · cᵉ mod n²
+    g_pow(key, &proof.z1) * proof.z2.modpow(&key.n, &key.n_squared) % &key.n_squared
+        == &proof.a * ct.c.modpow(&e, &key.n_squared) % &key.n_squared
+}
+
+/// SHA-256 over the length-prefixed big-endian bytes of `parts`,
+/// as a Fiat–Shamir challenge
+fn fiat_shamir(parts: &[&BigUint]) -> BigUint {
+    let mut hasher = Sha256::new();
+    for value in parts {
+        let bytes = value.to_bytes_be();
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    }
+    BigUint::from_bytes_be(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use once_cell::sync::Lazy;
+
+    /// One small key shared by every test; generating keys dominates the runtime
+    static KEY: Lazy<PaillierKey> = Lazy::new(|| PaillierKey::new(512).unwrap());
+    /// Likewise for the Damgård–Jurik tests, with `s = 2`
+    static KEY_S2: Lazy<PaillierKey> = Lazy::new(|| PaillierKey::new_with_s(512, 2).unwrap());
+
+    #[test]
+    fn scalar_mul_multiplies_the_plaintext() {
+        let key = &*KEY;
+        let ct = encrypt(key, &BigUint::from(7u32));
+        let product = homomorphic_scalar_mul(&ct, &BigUint::from(5u32), &key.n_squared);
+        assert_eq!(decrypt(key, &product).unwrap(), BigUint::from(35u32));
+        // k = 0 yields an encryption of zero, and k is taken mod n
+        let zero = homomorphic_scalar_mul(&ct, &BigUint::zero(), &key.n_squared);
+        assert_eq!(decrypt(key, &zero).unwrap(), BigUint::zero());
+        let wrapped = homomorphic_scalar_mul(&ct, &(&key.n + 5u32), &key.n_squared);
+        assert_eq!(decrypt(key, &wrapped).unwrap(), BigUint::from(35u32));
+        let keyed = homomorphic_scalar_mul_with_key(&ct, &BigUint::from(5u32), key);
+        assert_eq!(decrypt(key, &keyed).unwrap(), BigUint::from(35u32));
+        assert_eq!(decrypt(key, &(&ct * &BigUint::from(5u32))).unwrap(), BigUint::from(35u32));
+    }
+
+    #[test]
+    fn scalar_mul_reduces_mod_n_s() {
+        let key = &*KEY_S2;
+        let ct = encrypt(key, &BigUint::from(7u32));
+        // n² + 3 ≡ 3 mod n², but not mod n
+        let k = &key.n * &key.n + 3u32;
+        let product = homomorphic_scalar_mul_with_key(&ct, &k, key);
+        assert_eq!(decrypt(key, &product).unwrap(), BigUint::from(21u32));
+        assert_eq!(decrypt(key, &(&ct * &k)).unwrap(), BigUint::from(21u32));
+    }
+
+    #[test]
+    fn subtraction_leaves_the_signed_difference() {
+        let key = &*KEY;
+        let (hundred, forty) = (encrypt(key, &BigUint::from(100u32)), encrypt(key, &BigUint::from(40u32)));
+        let net = homomorphic_subtraction(&hundred, &forty, key).unwrap();
+        assert_eq!(decrypt(key, &net).unwrap(), BigUint::from(60u32));
+        let net = homomorphic_subtraction(&forty, &hundred, key).unwrap();
+        assert_eq!(decode_signed(&decrypt(key, &net).unwrap(), &key.n), BigInt::from(-60));
+    }
+
+    #[test]
+    fn ciphertexts_round_trip_through_json() {
+        let ct = encrypt(&*KEY, &BigUint::from(42u32));
+        let json = serde_json::to_value(&ct).unwrap();
+        assert_eq!(json["c"], ct.c.to_str_radix(10));
+        assert_eq!(json["n_squared"], ct.n_squared.to_str_radix(10));
+        let back: PaillierCiphertext = serde_json::from_value(json).unwrap();
+        assert_eq!((back.c, back.n_squared), (ct.c, ct.n_squared));
+    }
+
+    #[test]
+    fn malformed_ciphertext_json_is_an_error() {
+        for json in [
+            serde_json::json!({ "c": "12x", "n_squared": "35" }),
+            serde_json::json!({ "c": "-1", "n_squared": "35" }),
+            serde_json::json!({ "c": 12, "n_squared": "35" }),
+            serde_json::json!({ "c": "12" }),
+        ] {
+            assert!(serde_json::from_value::<PaillierCiphertext>(json.clone()).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn crt_decryption_matches_decrypt() {
+        let key = &*KEY;
+        let mut rng = thread_rng();
+        let edges = [BigUint::zero(), BigUint::one(), &key.n - 1u32];
+        for m in edges.into_iter().chain((0..20).map(|_| rng.gen_biguint_below(&key.n))) {
+            let ct = encrypt(key, &m);
+            assert_eq!(decrypt_crt(key, &ct).unwrap(), m);
+            assert_eq!(decrypt(key, &ct).unwrap(), m);
+        }
+    }
+
+    #[test]
+    fn signed_encoding_round_trips() {
+        let n = &KEY.n;
+        for v in [0i128, 1, -1, 50, -30, i64::MAX as i128, i64::MIN as i128] {
+            assert_eq!(decode_signed(&encode_signed(v, n), n), BigInt::from(v));
+        }
+        // the halfway point is the first negative value
+        let half: BigUint = n >> 1;
+        assert_eq!(decode_signed(&(&half - 1u32), n), BigInt::from(&half - 1u32));
+        assert!(decode_signed(&half, n) < BigInt::zero());
+    }
+
+    #[test]
+    fn overdrawn_balance_decodes_as_negative() {
+        let key = &*KEY;
+        let balance = homomorphic_subtraction(
+            &encrypt(key, &encode_signed(50, &key.n)),
+            &encrypt(key, &encode_signed(80, &key.n)),
+            key,
+        ).unwrap();
+        assert_eq!(decode_signed(&decrypt(key, &balance).unwrap(), &key.n), BigInt::from(-30));
+    }
+
+    #[test]
+    fn key_generation_redraws_unusable_primes() {
+        // equal primes, then gcd(21, 12) = 3, then a valid pair
+        let mut pairs = [(17u32, 17u32), (3, 7), (17, 19)].into_iter();
+        let retries = keygen_retries();
+        let key = PaillierKey::from_prime_pairs(|| {
+            let (p, q) = pairs.next().unwrap();
+            Ok((BigUint::from(p), BigUint::from(q)))
+        }).unwrap();
+        assert_eq!(key.n, BigUint::from(323u32));
+        assert!(key.validate());
+        assert!(keygen_retries() >= retries + 2);
+
+        let always_equal = PaillierKey::from_prime_pairs(|| Ok((BigUint::from(17u32), BigUint::from(17u32))));
+        assert_eq!(always_equal.unwrap_err(), KeyGenError::AttemptsExhausted(MAX_KEYGEN_ATTEMPTS));
+    }
+
+    #[test]
+    fn rerandomizing_keeps_the_plaintext_but_not_c() {
+        let key = &*KEY;
+        let ct = encrypt(key, &BigUint::from(99u32));
+        let fresh = rerandomize(&ct, key);
+        assert_ne!(fresh.c, ct.c);
+        assert_eq!(decrypt(key, &fresh).unwrap(), decrypt(key, &ct).unwrap());
+    }
+
+    #[test]
+    fn g_pow_shortcut_matches_modpow() {
+        let key = &*KEY;
+        for m in [BigUint::zero(), BigUint::from(12345u32), &key.n - 1u32] {
+            assert_eq!(g_pow(key, &m), key.g.modpow(&m, &key.modulus));
+            assert_eq!(decrypt(key, &encrypt(key, &m)).unwrap(), m);
+        }
+        // under s = 2 the shortcut doesn't apply, so this takes the modpow path
+        let dj = PaillierKey::from_primes_with_s(key.p.clone(), key.q.clone(), 2).unwrap();
+        let m = &dj.n + 5u32;
+        assert_eq!(decrypt(&dj, &encrypt(&dj, &m)).unwrap(), m);
+    }
+
+    #[test]
+    fn exported_keys_reimport_and_decrypt() {
+        let key = &*KEY;
+        let ct = encrypt(key, &BigUint::from(31337u32));
+        let imported = PaillierKey::from_json(&key.to_json()).unwrap();
+        assert_eq!(decrypt(&imported, &ct).unwrap(), BigUint::from(31337u32));
+        assert_eq!(imported.public_key(), key.public_key());
+    }
+
+    #[test]
+    fn inconsistent_key_json_is_rejected() {
+        let mut json: serde_json::Value = serde_json::from_str(&KEY.to_json()).unwrap();
+        json["lambda"] = serde_json::json!((&KEY.lambda + 2u32).to_string());
+        assert!(matches!(PaillierKey::from_json(&json.to_string()), Err(KeyError::Inconsistent(_))));
+        json["lambda"] = serde_json::json!("not a number");
+        assert!(matches!(PaillierKey::from_json(&json.to_string()), Err(KeyError::InvalidNumber("lambda"))));
+    }
+
+    #[test]
+    fn safe_primes_are_safe() {
+        let config = PrimalityTestConfig::default();
+        for _ in 0..3 {
+            let p = gen_safe_prime(64, config, DEFAULT_MAX_PRIME_CANDIDATES, &mut thread_rng()).unwrap();
+            assert_eq!(p.bits(), 64);
+            assert!(is_prime(&p, Some(config)).probably());
+            assert!(is_prime(&(&p >> 1u32), Some(config)).probably(), "(p - 1) / 2 = {} is composite", &p >> 1u32);
+        }
+    }
+
+    #[test]
+    fn fixed_randomness_gives_identical_ciphertexts() {
+        let key = &*KEY;
+        let (m, r) = (BigUint::from(42u32), BigUint::from(123_456_789u32));
+        let ct = encrypt_with_randomness(key, &m, &r);
+        assert_eq!(ct.to_bytes(), encrypt_with_randomness(key, &m, &r).to_bytes());
+        assert_ne!(ct.c, encrypt_with_randomness(key, &m, &(&r + 1u32)).c);
+        assert_eq!(decrypt(key, &ct).unwrap(), m);
+    }
+
+    #[test]
+    fn non_invertible_primes_are_an_error() {
+        // 3 divides both n = 21 and λ = 12, so λ has no inverse mod n
+        let from = |p: u32, q: u32| PaillierKey::from_primes(BigUint::from(p), BigUint::from(q));
+        assert_eq!(from(3, 7).unwrap_err(), KeyGenError::NotInvertible("λ mod n^s"));
+        assert_eq!(from(5, 11).unwrap_err(), KeyGenError::NotInvertible("λ mod n^s"));
+        assert_eq!(from(7, 7).unwrap_err(), KeyGenError::EqualPrimes);
+        assert_eq!(from(15, 7).unwrap_err(), KeyGenError::NotPrime);
+        assert!(from(17, 19).is_ok());
+    }
+
+    #[test]
+    fn decryption_proofs_fail_for_a_tampered_balance() {
+        let key = &*KEY;
+        let ct = encrypt(key, &BigUint::from(250u32));
+        let proof = prove_decryption(key, &ct).unwrap();
+        assert_eq!(proof.m, BigUint::from(250u32));
+        assert!(verify_decryption(&key.n, &key.g, &ct, &proof));
+
+        let claimed = DecryptionProof { m: BigUint::from(251u32), ..proof.clone() };
+        assert!(!verify_decryption(&key.n, &key.g, &ct, &claimed));
+        let forged = DecryptionProof { z: &proof.z + 1u32, ..proof.clone() };
+        assert!(!verify_decryption(&key.n, &key.g, &ct, &forged));
+        let other = encrypt(key, &BigUint::from(250u32));
+        assert!(!verify_decryption(&key.n, &key.g, &other, &proof));
+
+        // the proof works in n², so a plaintext past n can't be proven
+        let s2 = &*KEY_S2;
+        let big = encrypt(s2, &(&s2.n + 1u32));
+        assert_eq!(prove_decryption(s2, &big).unwrap_err(), DecryptError::UnsupportedKey);
+    }
+
+    /// Best effort: timings on a shared machine are noisy, so this only
+    /// catches a gross dependence of decryption time on the plaintext
+    #[test]
+    fn decryption_time_does_not_track_the_plaintext() {
+        use std::time::{Duration, Instant};
+
+        let key = &*KEY;
+        let small = encrypt(key, &BigUint::one());
+        let large = encrypt(key, &(&key.n - 1u32));
+        let time = |ct: &PaillierCiphertext| {
+            let start = Instant::now();
+            std::hint::black_box(decrypt_crt(key, ct).unwrap());
+            start.elapsed()
+        };
+        // interleaved, so drift in machine load hits both alike
+        let (mut small_times, mut large_times): (Vec<Duration>, Vec<Duration>) =
+            (0..200).map(|_| (time(&small), time(&large))).unzip();
+        small_times.sort();
+        large_times.sort();
+        let ratio = small_times[100].as_secs_f64() / large_times[100].as_secs_f64();
+        assert!((0.67..1.5).contains(&ratio), "median decryption times differ by {ratio:.2}x");
+    }
+
+    #[test]
+    fn blinded_exponents_give_the_same_power() {
+        let key = &*KEY;
+        let base = BigUint::from(7u32);
+        let order = &key.lambda;
+        let exp = thread_rng().gen_biguint_below(order);
+        // 7^λ ≡ 1 mod n, so λ-multiples added to the exponent change nothing
+        let expected = base.modpow(&exp, &key.n);
+        for _ in 0..5 {
+            assert_eq!(base.modpow(&blind_exponent(&exp, order), &key.n), expected);
+        }
+    }
+
+    #[test]
+    fn checked_add_stops_at_max_plaintext() {
+        let key = &*KEY;
+        let max = max_plaintext(key);
+        assert_eq!(max, (&key.n >> 1u32) - 1u32);
+        let almost = encrypt(key, &(&max - 1u32));
+        let full = checked_add_plaintext(&almost, &BigUint::one(), key).unwrap();
+        assert_eq!(decrypt(key, &full).unwrap(), max);
+        let over = checked_add_plaintext(&almost, &BigUint::from(2u32), key);
+        assert_eq!(over.unwrap_err(), RangeError::Overflow { max: max.clone(), headroom: BigUint::one() });
+    }
+
+    #[test]
+    fn plaintext_eq_sees_through_the_randomness() {
+        let key = &*KEY;
+        let a = encrypt(key, &BigUint::from(500u32));
+        let b = encrypt(key, &BigUint::from(500u32));
+        assert_ne!(a.c, b.c);
+        assert!(plaintext_eq(key, &a, &b).unwrap());
+        assert!(!plaintext_eq(key, &a, &encrypt(key, &BigUint::from(501u32))).unwrap());
+        let zero = PaillierCiphertext::new(BigUint::zero(), key.n_squared.clone());
+        assert!(plaintext_eq(key, &a, &zero).is_err());
+
+        // the difference decrypts to 0 exactly when the plaintexts match
+        assert_eq!(decrypt(key, &homomorphic_difference(&a, &b).unwrap()).unwrap(), BigUint::zero());
+        let gap = homomorphic_difference(&encrypt(key, &BigUint::from(503u32)), &a).unwrap();
+        assert_eq!(decrypt(key, &gap).unwrap(), BigUint::from(3u32));
+        assert_eq!(homomorphic_difference(&a, &zero).unwrap_err(), DecryptError::Malformed);
+    }
+
+    #[test]
+    fn amounts_convert_and_encrypt() {
+        let key = &*KEY;
+        let amount = Amount::from(1234u64);
+        assert_eq!(BigUint::from(amount.clone()), BigUint::from(1234u32));
+        assert_eq!(decrypt(key, &amount.encrypt_under(key)).unwrap(), BigUint::from(1234u32));
+
+        // past u64 an amount is carried as a decimal string, both ways
+        let big = Amount::from(BigUint::from(u64::MAX) + 1u32);
+        assert_eq!(serde_json::to_value(&amount).unwrap(), serde_json::json!(1234));
+        assert_eq!(serde_json::to_value(&big).unwrap(), serde_json::json!("18446744073709551616"));
+        assert_eq!(serde_json::from_value::<Amount>(serde_json::json!("18446744073709551616")).unwrap(), big);
+    }
+
+    #[test]
+    fn out_of_range_amounts_are_rejected() {
+        for json in [serde_json::json!(-1), serde_json::json!(1.5), serde_json::json!("-1"),
+                     serde_json::json!("+1"), serde_json::json!("1_000"), serde_json::json!("")] {
+            assert!(serde_json::from_value::<Amount>(json.clone()).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn add_plaintext_shifts_the_plaintext() {
+        let key = &*KEY;
+        let sum = add_plaintext(&encrypt(key, &BigUint::from(5u32)), &BigUint::from(3u32), key);
+        assert_eq!(decrypt(key, &sum).unwrap(), BigUint::from(8u32));
+        // adding n - 1 is subtracting 1
+        let wrapped = add_plaintext(&sum, &(&key.n - 1u32), key);
+        assert_eq!(decrypt(key, &wrapped).unwrap(), BigUint::from(7u32));
+    }
+
+    #[test]
+    fn keys_from_a_light_primality_config_work() {
+        let mut light = PrimalityTestConfig::default();
+        light.sprp_trials = 1;
+        light.sprp_random_trials = 0;
+        let key = PaillierKey::new_with_config(512, PrimeKind::Standard, light).unwrap();
+        assert!(key.validate());
+        let m = BigUint::from(777u32);
+        assert_eq!(decrypt(&key, &encrypt(&key, &m)).unwrap(), m);
+        assert_eq!(decrypt_crt(&key, &encrypt(&key, &m)).unwrap(), m);
+    }
+
+    #[test]
+    fn negation_decodes_as_the_negative() {
+        let key = &*KEY;
+        let neg = negate(&encrypt(key, &BigUint::from(10u32)), key).unwrap();
+        let m = decrypt(key, &neg).unwrap();
+        assert_eq!(decode_signed(&m, &key.n_s), BigInt::from(-10));
+        assert_eq!(decrypt(key, &negate(&neg, key).unwrap()).unwrap(), BigUint::from(10u32));
+        assert_eq!(decrypt(key, &negate(&encrypt(key, &BigUint::zero()), key).unwrap()).unwrap(), BigUint::zero());
+        // a non-unit has no inverse, and is an error rather than a panic
+        let shared = PaillierCiphertext::new(key.p.clone(), key.n_squared.clone());
+        assert_eq!(negate(&shared, key).unwrap_err(), DecryptError::Malformed);
+    }
+
+    #[test]
+    fn fresh_keys_validate() {
+        assert!(KEY.validate());
+        let mut key = PaillierKey::from_json(&KEY.to_json()).unwrap();
+        assert!(key.validate());
+        key.lambda += 2u32;
+        assert!(!key.validate());
+    }
+
+    #[test]
+    fn non_unit_ciphertexts_are_malformed() {
+        let key = &*KEY;
+        for c in [BigUint::zero(), key.n.clone(), key.p.clone()] {
+            let ct = PaillierCiphertext::new(c, key.n_squared.clone());
+            assert_eq!(decrypt(key, &ct), Err(DecryptError::Malformed));
+            assert_eq!(decrypt_crt(key, &ct), Err(DecryptError::Malformed));
+            assert!(!is_valid_ciphertext(&ct, key));
+        }
+    }
+
+    #[test]
+    fn sums_of_many_ciphertexts_decrypt_to_the_total() {
+        let key = &*KEY;
+        let cts: Vec<_> = (1..=10u32).map(|m| encrypt(key, &BigUint::from(m))).collect();
+        assert_eq!(decrypt(key, &homomorphic_sum(&cts, &key.n_squared)).unwrap(), BigUint::from(55u32));
+        assert_eq!(decrypt(key, &homomorphic_sum(&[], &key.n_squared)).unwrap(), BigUint::zero());
+    }
+
+    #[test]
+    fn seeded_rngs_give_reproducible_ciphertexts() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let key = &*KEY;
+        let m = BigUint::from(42u32);
+        let a = encrypt_with_rng(key, &m, &mut StdRng::seed_from_u64(7));
+        let b = encrypt_with_rng(key, &m, &mut StdRng::seed_from_u64(7));
+        let c = encrypt_with_rng(key, &m, &mut StdRng::seed_from_u64(8));
+        assert_eq!(a.c, b.c);
+        assert_ne!(a.c, c.c);
+        assert_eq!(decrypt(key, &c).unwrap(), m);
+    }
+
+    #[test]
+    fn ciphertext_proofs_verify_only_for_their_ciphertext() {
+        let key = &*KEY;
+        let (m, r) = (BigUint::from(250u32), BigUint::from(987_654_321u32));
+        let ct = encrypt_with_randomness(key, &m, &r);
+        let proof = prove_ciphertext(&m, &r, key);
+        assert!(verify_ciphertext(&ct, &proof, key));
+
+        let other = encrypt_with_randomness(key, &(&m + 1u32), &r);
+        assert!(!verify_ciphertext(&other, &proof, key));
+        let tampered = CtProof { z1: &proof.z1 + 1u32, ..proof.clone() };
+        assert!(!verify_ciphertext(&ct, &tampered, key));
+        // a proof for the wrong m doesn't carry over either
+        assert!(!verify_ciphertext(&ct, &prove_ciphertext(&(&m + 1u32), &r, key), key));
+    }
+
+    #[test]
+    fn add_operator_sums_the_plaintexts() {
+        let key = &*KEY;
+        let (a, b) = (encrypt(key, &BigUint::from(19u32)), encrypt(key, &BigUint::from(23u32)));
+        assert_eq!(decrypt(key, &(&a + &b)).unwrap(), BigUint::from(42u32));
+        assert_eq!((a.clone() + &b).c, (&a + &b).c);
+        assert_eq!(a.checked_add(&b).unwrap().c, (&a + &b).c);
+
+        let foreign = PaillierCiphertext::new(b.c.clone(), &key.n_squared + 1u32);
+        assert!(a.checked_add(&foreign).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "different keys")]
+    fn add_operator_panics_across_keys() {
+        let key = &*KEY;
+        let a = encrypt(key, &BigUint::one());
+        let _ = &a + &PaillierCiphertext::new(a.c.clone(), &key.n_squared + 1u32);
+    }
+
+    #[test]
+    fn public_key_encryptions_decrypt_with_the_private_key() {
+        let key = &*KEY;
+        let public = key.public_key();
+        let m = BigUint::from(1_000_001u32);
+        assert_eq!(decrypt(key, &encrypt(&public, &m)).unwrap(), m);
+        assert_eq!(decrypt(key, &encrypt_with_pubkey(&key.n, &key.g, &m)).unwrap(), m);
+        assert_eq!(PaillierPublicKey::new(key.n.clone(), key.g.clone()), public);
+
+        let key = &*KEY_S2;
+        let m = &key.n + 5u32;
+        assert_eq!(decrypt(key, &encrypt(&key.public_key(), &m)).unwrap(), m);
+    }
+
+    #[test]
+    fn s_2_keys_carry_plaintexts_beyond_n() {
+        let key = &*KEY;
+        assert_eq!((key.s, &key.n_s, &key.modulus), (1, &key.n, &key.n_squared));
+        let top = &key.n - 1u32;
+        assert_eq!(decrypt(key, &encrypt(key, &top)).unwrap(), top);
+
+        let key = &*KEY_S2;
+        assert_eq!(key.modulus, key.n.pow(3));
+        for m in [&key.n + 7u32, &key.n * &key.n - 1u32] {
+            let ct = encrypt(key, &m);
+            assert!(ct.c < key.modulus);
+            assert_eq!(decrypt(key, &ct).unwrap(), m);
+        }
+        let sum = homomorphic_addition(&encrypt(key, &key.n), &encrypt(key, &key.n)).unwrap();
+        assert_eq!(decrypt(key, &sum).unwrap(), &key.n * 2u32);
+    }
+
+    #[test]
+    fn fingerprints_identify_the_public_key() {
+        let small = PaillierPublicKey::new(BigUint::from(15u32), BigUint::from(16u32));
+        assert_eq!(small.fingerprint(), "9af9551f795a4c5c0484c1200da196d7c6f559647db093c9f2c5e2500a50ca8e");
+
+        let key = &*KEY;
+        assert_eq!(key.public_key().fingerprint(), key.public_key().fingerprint());
+        assert_eq!(key.public_key().fingerprint().len(), 64);
+        assert_ne!(key.public_key().fingerprint(), KEY_S2.public_key().fingerprint());
+        // the length prefixes keep (n, g) from colliding with a re-split of the same bytes
+        let resplit = PaillierPublicKey::new(BigUint::from(0x0f10u32), BigUint::zero());
+        assert_ne!(small.fingerprint(), resplit.fingerprint());
+    }
+
+    #[test]
+    fn adding_ciphertexts_from_different_keys_is_an_error() {
+        let a = encrypt(&*KEY, &BigUint::from(2u32));
+        let b = encrypt(&*KEY_S2, &BigUint::from(3u32));
+        assert_eq!(homomorphic_addition(&a, &b).unwrap_err(), MismatchError);
+        assert_eq!(homomorphic_addition(&b, &a).unwrap_err(), MismatchError);
+        assert_eq!(decrypt(&KEY, &homomorphic_addition(&a, &a).unwrap()).unwrap(), BigUint::from(4u32));
+    }
+
+    #[test]
+    fn parallel_key_generation_gives_valid_keys() {
+        // every `new` goes through the two-thread search in `new_with_config`
+        let keys: Vec<_> = (0..3).map(|_| PaillierKey::new(256).unwrap()).collect();
+        for key in &keys {
+            assert!(key.validate());
+            assert_ne!(key.p, key.q);
+            assert_eq!((key.p.bits(), key.q.bits()), (128, 128));
+            let m = BigUint::from(77u32);
+            assert_eq!(decrypt(key, &encrypt(key, &m)).unwrap(), m);
+            assert_eq!(decrypt_crt(key, &encrypt(key, &m)).unwrap(), m);
+        }
+        // the two threads' RNGs are seeded independently
+        assert_ne!(keys[0].n, keys[1].n);
+        assert_ne!(keys[1].n, keys[2].n);
+    }
+
+    #[test]
+    fn checked_add_accepts_n_half_minus_one_but_not_n_half() {
+        let key = &*KEY;
+        let half: BigUint = &key.n >> 1;
+        let zero = encrypt(key, &BigUint::zero());
+        let top = checked_add_plaintext(&zero, &(&half - 1u32), key).unwrap();
+        assert_eq!(decrypt(key, &top).unwrap(), &half - 1u32);
+        assert_eq!(
+            checked_add_plaintext(&zero, &half, key).unwrap_err(),
+            RangeError::Overflow { max: max_plaintext(key), headroom: max_plaintext(key) },
+        );
+        // an overdrawn balance has room for its debt on top
+        let overdrawn = encrypt(key, &encode_signed(-1, &key.n));
+        let full = checked_add_plaintext(&overdrawn, &half, key).unwrap();
+        assert_eq!(decrypt(key, &full).unwrap(), &half - 1u32);
+        assert!(checked_add_plaintext(&overdrawn, &(&half + 1u32), key).is_err());
+    }
+
+    /// An RNG stuck at zero: every `gen_prime` candidate is then
+    /// 2^(bits-1) + 1, which 3 divides whenever `bits` is even
+    struct ZeroRng;
+
+    impl RngCore for ZeroRng {
+        fn next_u32(&mut self) -> u32 {
+            0
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            0
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.fill(0);
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            dest.fill(0);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for ZeroRng {}
+
+    #[test]
+    fn a_broken_rng_exhausts_the_candidates() {
+        let config = PrimalityTestConfig::default();
+        assert_eq!(gen_prime(64, config, 50, &mut ZeroRng), Err(PrimeGenError::CandidatesExhausted(50)));
+        assert!(matches!(
+            PaillierKey::new_with_rng(128, PrimeKind::Standard, config, &mut ZeroRng),
+            Err(KeyGenError::PrimeGen(PrimeGenError::CandidatesExhausted(_))),
+        ));
+        // a working RNG gets through the same path
+        assert_eq!(gen_prime(64, config, 10_000, &mut thread_rng()).unwrap().bits(), 64);
+    }
+
+    #[test]
+    fn weighted_sums_weight_each_plaintext() {
+        let key = &*KEY;
+        let pairs = [
+            (BigUint::from(2u32), encrypt(key, &BigUint::from(10u32))),
+            (BigUint::from(3u32), encrypt(key, &BigUint::from(4u32))),
+        ];
+        assert_eq!(decrypt(key, &weighted_sum(&pairs, key)).unwrap(), BigUint::from(32u32));
+        assert_eq!(decrypt(key, &weighted_sum(&[], key)).unwrap(), BigUint::zero());
+        // weights are taken mod n
+        let wrapped = [(&key.n + 2u32, pairs[0].1.clone())];
+        assert_eq!(decrypt(key, &weighted_sum(&wrapped, key)).unwrap(), BigUint::from(20u32));
+    }
+
+    /// Two fixed 256-bit primes, for keys that must be the same every run
+    const P: &str = "89832623605594700308282651133996556597155015442948055892434723408100995745117";
+    const Q: &str = "89570935008921592731648345912217359480680185339441583404202685174411415951337";
+
+    #[test]
+    fn keys_from_known_primes_are_deterministic() {
+        let (p, q): (BigUint, BigUint) = (P.parse().unwrap(), Q.parse().unwrap());
+        let key = PaillierKey::from_primes(p.clone(), q.clone()).unwrap();
+        assert_eq!(key.n, &p * &q);
+        assert_eq!(key.reveal_primes(), (&p, &q));
+        assert!(key.validate());
+        let ct = encrypt(&key, &BigUint::from(2024u32));
+        assert_eq!(decrypt(&key, &ct).unwrap(), BigUint::from(2024u32));
+
+        let again = PaillierKey::from_primes(p, q).unwrap();
+        assert_eq!(again.public_key(), key.public_key());
+        assert_eq!(decrypt_crt(&again, &ct).unwrap(), BigUint::from(2024u32));
+    }
+
+    #[test]
+    fn the_mul_protocol_multiplies_two_ciphertexts() {
+        let key = &*KEY;
+        let public = key.public_key();
+        let products = [(6u32, 7u32, BigUint::from(42u32)), (0, 9, BigUint::zero())];
+        for (a, b, product) in products {
+            let (ct_a, ct_b) = (encrypt(key, &BigUint::from(a)), encrypt(key, &BigUint::from(b)));
+            let blinded = mul_step1(&ct_a, &ct_b, &public);
+            // the key holder sees only the masked operands
+            assert_ne!(decrypt(key, &blinded.a).unwrap(), BigUint::from(a));
+            let assisted = mul_assist(key, &blinded.a, &blinded.b).unwrap();
+            assert_eq!(decrypt(key, &mul_step2(&blinded, &assisted, &public)).unwrap(), product);
+        }
+        // products wrap mod n, so a negative factor gives a negative product
+        let neg = encrypt(key, &encode_signed(-3, &key.n));
+        let blinded = mul_step1(&neg, &encrypt(key, &BigUint::from(5u32)), key);
+        let ct = mul_step2(&blinded, &mul_assist(key, &blinded.a, &blinded.b).unwrap(), key);
+        assert_eq!(decode_signed(&decrypt(key, &ct).unwrap(), &key.n), BigInt::from(-15));
+    }
+
+    #[test]
+    fn each_overflow_policy_handles_a_full_balance() {
+        let key = &*KEY;
+        let max = max_plaintext(key);
+        let near = encrypt(key, &(&max - 2u32));
+        let five = BigUint::from(5u32);
+        let add = |policy| add_plaintext_with_policy(&near, &five, key, policy);
+
+        assert!(matches!(add(OverflowPolicy::Reject), Err(RangeError::Overflow { .. })));
+        let (saturated, added) = add(OverflowPolicy::Saturate).unwrap();
+        assert_eq!((decrypt(key, &saturated).unwrap(), added), (max.clone(), BigUint::from(2u32)));
+        let (wrapped, added) = add(OverflowPolicy::Wrap).unwrap();
+        assert_eq!(added, five);
+        assert!(decode_signed(&decrypt(key, &wrapped).unwrap(), &key.n) < BigInt::zero());
+
+        // with room to spare, every policy adds the whole amount
+        let zero = encrypt(key, &BigUint::zero());
+        for policy in [OverflowPolicy::Reject, OverflowPolicy::Saturate, OverflowPolicy::Wrap] {
+            let (ct, added) = add_plaintext_with_policy(&zero, &five, key, policy).unwrap();
+            assert_eq!((decrypt(key, &ct).unwrap(), added), (five.clone(), five.clone()));
+        }
+    }
+
+    #[test]
+    fn validity_checks_range_and_coprimality() {
+        let key = &*KEY;
+        let valid = |c: BigUint| is_valid_ciphertext(&PaillierCiphertext::new(c, key.n_squared.clone()), key);
+        assert!(is_valid_ciphertext(&encrypt(key, &BigUint::from(3u32)), key));
+        assert!(valid(BigUint::one()));
+        assert!(valid(&key.n_squared - 1u32));
+        // out of range
+        assert!(!valid(key.n_squared.clone()));
+        assert!(!valid(&key.n_squared + 1u32));
+        // sharing a factor with n
+        assert!(!valid(BigUint::zero()));
+        assert!(!valid(&key.q * 3u32));
+        assert!(!valid(&key.n_squared - &key.p));
+    }
+
+    #[test]
+    fn a_fixed_rng_at_tiny_sizes_counts_every_redraw() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        // the only 2-bit prime is 3, so every pair is equal
+        let config = PrimalityTestConfig::default();
+        let retries = keygen_retries();
+        let result = PaillierKey::new_with_rng(4, PrimeKind::Standard, config, &mut StdRng::seed_from_u64(1));
+        assert_eq!(result.unwrap_err(), KeyGenError::AttemptsExhausted(MAX_KEYGEN_ATTEMPTS));
+        assert!(keygen_retries() >= retries + MAX_KEYGEN_ATTEMPTS as u64);
+
+        // 3-bit primes are 5 and 7: half the pairs are equal and redrawn
+        let retries = keygen_retries();
+        let key = PaillierKey::new_with_rng(6, PrimeKind::Standard, config, &mut StdRng::seed_from_u64(1)).unwrap();
+        assert_eq!(key.n, BigUint::from(35u32));
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..20 {
+            PaillierKey::new_with_rng(6, PrimeKind::Standard, config, &mut rng).unwrap();
+        }
+        assert!(keygen_retries() > retries);
+    }
+
+    #[test]
+    fn a_zeroized_key_encrypts_but_cannot_decrypt() {
+        let mut key = PaillierKey::from_json(&KEY.to_json()).unwrap();
+        let ct = encrypt(&key, &BigUint::from(5u32));
+        key.zeroize();
+
+        assert_eq!(decrypt(&key, &ct), Err(DecryptError::Erased));
+        assert_eq!(decrypt_crt(&key, &ct), Err(DecryptError::Erased));
+        assert!(!key.validate());
+        assert_eq!(key.reveal_primes(), (&BigUint::zero(), &BigUint::zero()));
+        // the public half is untouched
+        assert_eq!(key.n, KEY.n);
+        let fresh = encrypt(&key, &BigUint::from(7u32));
+        assert_eq!(decrypt(&KEY, &fresh).unwrap(), BigUint::from(7u32));
+
+        // zeroizing twice, and the drop after it, are harmless
+        key.zeroize();
+        drop(key);
+        drop(PaillierKey::from_json(&KEY_S2.to_json()).unwrap());
+    }
+}
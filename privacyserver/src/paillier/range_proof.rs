@@ -0,0 +1,185 @@
+//! Non-interactive proof that a ciphertext encrypts a value in `[0, 2^bits)`.
+//!
+//! The prover encrypts each bit `bⱼ` of `m` separately, choosing the bit
+//! randomness so that `∏ cⱼ^(2^j) = ct` exactly. Each `cⱼ` carries an OR
+//! proof that either `cⱼ` or `cⱼ·g⁻¹` is an n-th residue, i.e. that it
+//! encrypts 0 or 1. Challenges come from SHA-256 (Fiat–Shamir).
+
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::One;
+use rand::thread_rng;
+
+use super::{fiat_shamir, g_pow, PaillierCiphertext, PaillierKey};
+
+/// Challenges are SHA-256 digests, well below the smallest prime factor of `n`
+const CHALLENGE_BITS: usize = 256;
+
+/// Proof for a single bit ciphertext
+#[derive(Debug, Clone)]
+pub struct BitProof {
+    /// encryption of the bit
+    pub c: BigUint,
+    /// commitments, responses and challenges for the "0" and "1" branches
+    pub a: [BigUint; 2],
+    pub z: [BigUint; 2],
+    pub e: [BigUint; 2],
+}
+
+/// Proof that a ciphertext's plaintext fits in `bits` bits
+#[derive(Debug, Clone)]
+pub struct RangeProof {
+    pub bits: Vec<BitProof>,
+}
+
+/// Prove that `Enc(m; r)` encrypts a value in `[0, 2^bits)`.
+/// If `m` doesn't fit, the resulting proof will not verify. Panics for a
+/// Damgård–Jurik key: the proof only works in `n²`.
+pub fn prove_range(m: &BigUint, r: &BigUint, key: &PaillierKey, bits: usize) -> RangeProof {
+    assert_eq!(key.s, 1, "range proofs need a key with s = 1");
+    let mut rng = thread_rng();
+    let ct = (g_pow(key, m) * r.modpow(&key.n, &key.n_squared)) % &key.n_squared;
+
+    // bit randomness: r₁..r_{k-1} random, r₀ fixed so ∏ rⱼ^(2^j) ≡ r mod n
+    let mut rs: Vec<BigUint> = (0..bits)
+        .map(|_| rng.gen_biguint_range(&BigUint::one(), &key.n))
+        .collect();
+    if let Some((r0, rest)) = rs.split_first_mut() {
+        let tail = rest.iter().enumerate().fold(BigUint::one(), |acc, (j, rj)| {
+            acc * rj.modpow(&(BigUint::one() << (j + 1)), &key.n) % &key.n
+        });
+        *r0 = r * tail.modinv(&key.n).expect("bit randomness must be invertible mod n")
+            % &key.n;
+    }
+
+    let modulus = BigUint::one() << CHALLENGE_BITS;
+    let bit_proofs = rs.iter().enumerate().map(|(j, rj)| {
+        let b = usize::from(m.bit(j as u64));
+        let c = g_pow(key, &BigUint::from(b)) * rj.modpow(&key.n, &key.n_squared)
+              % &key.n_squared;
+        let x = branch_targets(&c, key);
+
+        // simulate the false branch with a random challenge and response
+        let fake = 1 - b;
+        let e_fake = rng.gen_biguint_below(&modulus);
+        let z_fake = rng.gen_biguint_range(&BigUint::one(), &key.n);
+        let a_fake = z_fake.modpow(&key.n, &key.n_squared)
+                   * x[fake].modpow(&e_fake, &key.n_squared)
+                             .modinv(&key.n_squared)
+                             .expect("ciphertext must be invertible mod n²")
+                   % &key.n_squared;
+
+        // run the real branch honestly under the remaining challenge
+        let s = rng.gen_biguint_range(&BigUint::one(), &key.n);
+        let a_real = s.modpow(&key.n, &key.n_squared);
+
+        let mut a = [BigUint::default(), BigUint::default()];
+        a[b]    = a_real;
+        a[fake] = a_fake;
+        let e_total = challenge(key, &ct, j, &c, &a);
+        let e_real  = (&e_total + &modulus - &e_fake) % &modulus;
+        let z_real  = s * rj.modpow(&e_real, &key.n) % &key.n;
+
+        let mut e = [BigUint::default(), BigUint::default()];
+        let mut z = [BigUint::default(), BigUint::default()];
+        e[b] = e_real;
+        e[fake] = e_fake;
+        z[b] = z_real;
+        z[fake] = z_fake;
+        BitProof { c, a, z, e }
+    }).collect();
+
+    RangeProof { bits: bit_proofs }
+}
+
+/// Check that `proof` shows `ct` encrypts a value in `[0, 2^bits)`.
+/// Always false under a Damgård–Jurik key.
+pub fn verify_range(
+    ct: &PaillierCiphertext,
+    proof: &RangeProof,
+    key: &PaillierKey,
+    bits: usize
+) -> bool {
+    if key.s != 1 || proof.bits.len() != bits {
+        return false;
+    }
+
+    let modulus = BigUint::one() << CHALLENGE_BITS;
+    let mut recombined = BigUint::one();
+    for (j, bp) in proof.bits.iter().enumerate() {
+        if bp.c >= key.n_squared {
+            return false;
+        }
+        let x = branch_targets(&bp.c, key);
+
+        // each branch: z^n == a · x^e mod n²
+        for (((z, a), e), x) in bp.z.iter().zip(&bp.a).zip(&bp.e).zip(&x) {
+            let lhs = z.modpow(&key.n, &key.n_squared);
+            let rhs = a * x.modpow(e, &key.n_squared) % &key.n_squared;
+            if lhs != rhs {
+                return false;
+            }
+        }
+        // the two challenges must split the Fiat–Shamir challenge
+        if (&bp.e[0] + &bp.e[1]) % &modulus != challenge(key, &ct.c, j, &bp.c, &bp.a) {
+            return false;
+        }
+
+        recombined = recombined * bp.c.modpow(&(BigUint::one() << j), &key.n_squared)
+                   % &key.n_squared;
+    }
+
+    recombined == ct.c
+}
+
+/// `[c, c·g⁻¹]`: an n-th residue in slot 0 means the bit is 0, slot 1 means 1
+fn branch_targets(c: &BigUint, key: &PaillierKey) -> [BigUint; 2] {
+    let g_inv = key.g.modinv(&key.n_squared).expect("g must be invertible mod n²");
+    [c.clone(), c * g_inv % &key.n_squared]
+}
+
+/// Fiat–Shamir challenge for bit `j`, bound to the key, `ct`, and commitments
+fn challenge(
+    key: &PaillierKey,
+    ct: &BigUint,
+    j: usize,
+    c: &BigUint,
+    a: &[BigUint; 2]
+) -> BigUint {
+    fiat_shamir(&[&key.n, ct, &BigUint::from(j), c, &a[0], &a[1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paillier::encrypt_with_randomness;
+
+    #[test]
+    fn proves_only_values_in_range() {
+        let key = PaillierKey::new(512).unwrap();
+        let r = thread_rng().gen_biguint_range(&BigUint::one(), &key.n);
+
+        let m = BigUint::from(100u32);
+        let ct = encrypt_with_randomness(&key, &m, &r);
+        let proof = prove_range(&m, &r, &key, 32);
+        assert!(verify_range(&ct, &proof, &key, 32));
+        // the proof is for exactly 32 bits, and for this ciphertext only
+        assert!(!verify_range(&ct, &proof, &key, 31));
+        let other = encrypt_with_randomness(&key, &BigUint::from(101u32), &r);
+        assert!(!verify_range(&other, &proof, &key, 32));
+
+        let too_big = BigUint::one() << 40;
+        let ct = encrypt_with_randomness(&key, &too_big, &r);
+        assert!(!verify_range(&ct, &prove_range(&too_big, &r, &key, 32), &key, 32));
+
+        // a Damgård–Jurik key can't check a proof made in n²
+        let s2 = PaillierKey::from_primes_with_s(key.p.clone(), key.q.clone(), 2).unwrap();
+        assert!(!verify_range(&ct, &proof, &s2, 32));
+    }
+
+    #[test]
+    #[should_panic(expected = "range proofs need a key with s = 1")]
+    fn damgard_jurik_keys_cannot_prove() {
+        let key = PaillierKey::new_with_s(512, 2).unwrap();
+        prove_range(&BigUint::one(), &BigUint::one(), &key, 8);
+    }
+}
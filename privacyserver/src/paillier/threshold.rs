@@ -0,0 +1,208 @@
+//! t-of-n threshold decryption.
+//!
+//! The decryption exponent `d = λ·μ` (so `d ≡ 0 mod λ`, `d ≡ 1 mod n`) is
+//! Shamir-shared. Exponents live in the group order, so shares are taken
+//! over ℤ_{n·λ} rather than a prime field; interpolation then uses integer
+//! Lagrange coefficients scaled by `Δ = parties!`, as in Shoup's scheme.
+//! Any `t` partial decryptions combine to the plaintext.
+
+use num_bigint::{BigInt, BigUint, RandBigInt, Sign};
+use num_traits::{One, Signed};
+use rand::thread_rng;
+use std::fmt;
+
+use super::{l_function, PaillierCiphertext, PaillierKey};
+
+/// One party's share of the decryption exponent
+#[derive(Debug, Clone)]
+pub struct KeyShare {
+    /// evaluation point, 1-based
+    pub index:     usize,
+    /// how many shares it takes to decrypt
+    pub threshold: usize,
+    share:         BigUint,
+    pub n:         BigUint,
+    pub n_squared: BigUint,
+    /// Δ = parties!
+    pub delta:     BigUint,
+}
+
+/// One party's contribution towards decrypting a ciphertext
+#[derive(Debug, Clone)]
+pub struct PartialDecryption {
+    pub index:     usize,
+    pub threshold: usize,
+    /// c^(Δ·sᵢ) mod n²
+    pub c:         BigUint,
+    pub n:         BigUint,
+    pub n_squared: BigUint,
+    pub delta:     BigUint,
+}
+
+/// Split `key`'s decryption exponent into `n` shares, any `t` of which
+/// can decrypt. Panics unless `1 <= t <= n`, and for a Damgård–Jurik
+/// key, since shares combine in `n²`.
+pub fn split_key(key: &PaillierKey, t: usize, n: usize) -> Vec<KeyShare> {
+    assert!(t >= 1 && t <= n, "threshold must satisfy 1 <= t <= n");
+    assert_eq!(key.s, 1, "threshold decryption needs a key with s = 1");
+
+    let modulus = &key.n * &key.lambda;
+    let d       = &key.lambda * &key.mu % &modulus;
+    let delta: BigUint = (1..=n).map(BigUint::from).product();
+
+    // f(x) = d + a₁x + … + a_{t-1}x^{t-1} mod n·λ
+    let mut rng = thread_rng();
+    let coeffs: Vec<BigUint> = std::iter::once(d)
+        .chain((1..t).map(|_| rng.gen_biguint_below(&modulus)))
+        .collect();
+
+    (1..=n)
+        .map(|i| {
+            let x = BigUint::from(i);
+            let share = coeffs.iter().rev()
+                .fold(BigUint::ZERO, |acc, a| (acc * &x + a) % &modulus);
+            KeyShare {
+                index:     i,
+                threshold: t,
+                share,
+                n:         key.n.clone(),
+                n_squared: key.n_squared.clone(),
+                delta:     delta.clone(),
+            }
+        })
+        .collect()
+}
+
+impl KeyShare {
+    /// This share's partial decryption of `ct`
+    pub fn partial_decrypt(&self, ct: &PaillierCiphertext) -> PartialDecryption {
+        let exp = &self.delta * &self.share;
+        PartialDecryption {
+            index:     self.index,
+            threshold: self.threshold,
+            c:         ct.c.modpow(&exp, &self.n_squared),
+            n:         self.n.clone(),
+            n_squared: self.n_squared.clone(),
+            delta:     self.delta.clone(),
+        }
+    }
+}
+
+/// Why `combine_partial_decryptions` couldn't recover the plaintext
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CombineError {
+    /// fewer distinct parties than the threshold
+    TooFewShares { threshold: usize, got: usize },
+    /// two partial decryptions came from the same party
+    DuplicateIndex(usize),
+    /// the partial decryptions aren't all from the same split key
+    MixedKeys,
+    /// the shares don't combine to a valid decryption, e.g. they are for
+    /// different ciphertexts
+    Malformed,
+}
+
+impl fmt::Display for CombineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CombineError::TooFewShares { threshold, got } => {
+                write!(f, "need {threshold} partial decryptions, got {got}")
+            }
+            CombineError::DuplicateIndex(i) => write!(f, "party {i} contributed more than once"),
+            CombineError::MixedKeys         => write!(f, "partial decryptions are from different keys"),
+            CombineError::Malformed         => write!(f, "partial decryptions do not combine to a plaintext"),
+        }
+    }
+}
+
+impl std::error::Error for CombineError {}
+
+/// Combine partial decryptions from at least `t` distinct parties into
+/// the plaintext
+pub fn combine_partial_decryptions(shares: &[PartialDecryption]) -> Result<BigUint, CombineError> {
+    let Some(first) = shares.first() else {
+        return Err(CombineError::TooFewShares { threshold: 1, got: 0 });
+    };
+    for (i, share) in shares.iter().enumerate() {
+        if share.n != first.n || share.delta != first.delta || share.threshold != first.threshold {
+            return Err(CombineError::MixedKeys);
+        }
+        if shares[..i].iter().any(|s| s.index == share.index) {
+            return Err(CombineError::DuplicateIndex(share.index));
+        }
+    }
+    if shares.len() < first.threshold {
+        return Err(CombineError::TooFewShares { threshold: first.threshold, got: shares.len() });
+    }
+    let n_squared = &first.n_squared;
+
+    // c' = ∏ cᵢ^(Δ·λᵢ) = c^(Δ²·d) = 1 + Δ²·m·n mod n²
+    let mut combined = BigUint::one();
+    for share in shares {
+        let coeff = lagrange_at_zero(share.index, shares, &first.delta);
+        let mut factor = share.c.modpow(&coeff.magnitude().clone(), n_squared);
+        if coeff.is_negative() {
+            factor = factor.modinv(n_squared).ok_or(CombineError::Malformed)?;
+        }
+        combined = combined * factor % n_squared;
+    }
+
+    let delta_sq_inv = (&first.delta * &first.delta).modinv(&first.n).ok_or(CombineError::Malformed)?;
+    let l = l_function(&combined, &first.n).ok_or(CombineError::Malformed)?;
+    Ok(l * delta_sq_inv % &first.n)
+}
+
+/// Δ·λᵢ(0) for the points in `shares`; always an integer because
+/// Δ = parties! clears every denominator.
+fn lagrange_at_zero(i: usize, shares: &[PartialDecryption], delta: &BigUint) -> BigInt {
+    let xi = BigInt::from(i);
+    let mut num = BigInt::from_biguint(Sign::Plus, delta.clone());
+    let mut den = BigInt::one();
+    for share in shares.iter().filter(|s| s.index != i) {
+        let xj = BigInt::from(share.index);
+        num *= &xj;
+        den *= &xj - &xi;
+    }
+    num / den
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paillier::encrypt;
+
+    #[test]
+    fn any_two_of_three_decrypt() {
+        let key = PaillierKey::new(512).unwrap();
+        let shares = split_key(&key, 2, 3);
+        let m = BigUint::from(1234u32);
+        let ct = encrypt(&key, &m);
+        let partials: Vec<_> = shares.iter().map(|s| s.partial_decrypt(&ct)).collect();
+
+        for pair in [[0, 1], [0, 2], [1, 2], [2, 0]] {
+            let chosen: Vec<_> = pair.iter().map(|&i| partials[i].clone()).collect();
+            assert_eq!(combine_partial_decryptions(&chosen), Ok(m.clone()));
+        }
+        assert_eq!(combine_partial_decryptions(&partials), Ok(m));
+    }
+
+    #[test]
+    fn rejects_too_few_or_repeated_shares() {
+        let key = PaillierKey::new(512).unwrap();
+        let shares = split_key(&key, 2, 3);
+        let partial = shares[0].partial_decrypt(&encrypt(&key, &BigUint::from(5u32)));
+
+        assert_eq!(combine_partial_decryptions(&[]),
+                   Err(CombineError::TooFewShares { threshold: 1, got: 0 }));
+        assert_eq!(combine_partial_decryptions(std::slice::from_ref(&partial)),
+                   Err(CombineError::TooFewShares { threshold: 2, got: 1 }));
+        assert_eq!(combine_partial_decryptions(&[partial.clone(), partial]),
+                   Err(CombineError::DuplicateIndex(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "threshold decryption needs a key with s = 1")]
+    fn damgard_jurik_keys_cannot_be_split() {
+        split_key(&PaillierKey::new_with_s(512, 2).unwrap(), 2, 3);
+    }
+}
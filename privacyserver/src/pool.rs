@@ -0,0 +1,148 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use num_bigint::BigUint;
+use rand::thread_rng;
+use tracing::warn;
+
+use privacyserver::paillier::{randomness_factor, PaillierKey};
+
+struct Factors {
+    /// the ciphertext modulus of the key the factors are for
+    modulus: BigUint,
+    ready:   Vec<BigUint>,
+}
+
+/// Encryption randomness `r^(n^s) mod n^(s+1)` computed ahead of time on
+/// a background thread, so `encrypt` can skip its modpow.
+///
+/// `pop` removes each factor as it hands it out, so none is ever used
+/// twice. The pool is for one key at a time; `reset` it whenever the key
+/// changes, and factors a refill computed for the old key are dropped.
+pub struct RandomnessPool {
+    capacity:  usize,
+    factors:   Mutex<Factors>,
+    /// set while a refill thread is running
+    refilling: AtomicBool,
+}
+
+impl RandomnessPool {
+    /// An empty pool of at most `capacity` factors for `key`; 0 disables it
+    pub fn new(capacity: usize, key: &PaillierKey) -> Self {
+        let factors = Factors { modulus: key.modulus.clone(), ready: Vec::with_capacity(capacity) };
+        RandomnessPool { capacity, factors: Mutex::new(factors), refilling: AtomicBool::new(false) }
+    }
+
+    /// A factor for `key`, or `None` if the pool has none ready. Once the
+    /// pool is half empty this starts refilling it.
+    pub fn pop(&'static self, key: &PaillierKey) -> Option<BigUint> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let (factor, low) = {
+            let mut factors = self.factors.lock().unwrap();
+            let factor = if factors.modulus == key.modulus { factors.ready.pop() } else { None };
+            (factor, factors.ready.len() <= self.capacity / 2)
+        };
+        if low {
+            self.refill(key);
+        }
+        factor
+    }
+
+    /// Drop every factor and start over for `key`, e.g. after rotating
+    pub fn reset(&'static self, key: &PaillierKey) {
+        {
+            let mut factors = self.factors.lock().unwrap();
+            factors.modulus = key.modulus.clone();
+            factors.ready.clear();
+        }
+        self.refill(key);
+    }
+
+    /// Top the pool up to capacity on a background thread, unless one is
+    /// already at it. The thread only sees `key`'s public half.
+    pub fn refill(&'static self, key: &PaillierKey) {
+        if self.capacity == 0 || self.refilling.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let public = key.public_key();
+        let spawned = thread::Builder::new().name("randomness-pool".into()).spawn(move || {
+            let mut rng = thread_rng();
+            loop {
+                // the modpow runs unlocked, so `pop` never waits on it
+                let factor = randomness_factor(&public, &mut rng);
+                let mut factors = self.factors.lock().unwrap();
+                if factors.modulus != public.modulus {
+                    break;
+                }
+                factors.ready.push(factor);
+                if factors.ready.len() >= self.capacity {
+                    break;
+                }
+            }
+            self.refilling.store(false, Ordering::Release);
+        });
+        if let Err(e) = spawned {
+            warn!(error = %e, "failed to start refilling the randomness pool");
+            self.refilling.store(false, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::{Duration, Instant};
+
+    use privacyserver::paillier::{decrypt, encrypt_with_factor};
+
+    use super::*;
+
+    /// Helper: pop from `pool` until it has a factor, for at most 10s
+    fn next(pool: &'static RandomnessPool, key: &PaillierKey) -> BigUint {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(factor) = pool.pop(key) {
+                return factor;
+            }
+            assert!(Instant::now() < deadline, "the pool never filled");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn pooled_factors_encrypt_decryptably_and_never_repeat() {
+        let key = PaillierKey::new(512).unwrap();
+        let pool: &'static RandomnessPool = Box::leak(Box::new(RandomnessPool::new(8, &key)));
+        pool.refill(&key);
+
+        let mut seen = HashSet::new();
+        for m in 0..24u32 {
+            let factor = next(pool, &key);
+            assert!(seen.insert(factor.clone()), "factor handed out twice");
+            let ct = encrypt_with_factor(&key, &BigUint::from(m), &factor);
+            assert_eq!(decrypt(&key, &ct).unwrap(), BigUint::from(m));
+        }
+    }
+
+    #[test]
+    fn a_reset_pool_serves_only_the_new_key() {
+        let old = PaillierKey::new(512).unwrap();
+        let new = PaillierKey::new(512).unwrap();
+        let pool: &'static RandomnessPool = Box::leak(Box::new(RandomnessPool::new(4, &old)));
+        next(pool, &old);
+        assert_eq!(pool.pop(&new), None);
+
+        pool.reset(&new);
+        let factor = next(pool, &new);
+        let ct = encrypt_with_factor(&new, &BigUint::from(9u32), &factor);
+        assert_eq!(decrypt(&new, &ct).unwrap(), BigUint::from(9u32));
+        assert_eq!(pool.pop(&old), None);
+
+        let disabled: &'static RandomnessPool = Box::leak(Box::new(RandomnessPool::new(0, &old)));
+        disabled.refill(&old);
+        assert_eq!(disabled.pop(&old), None);
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Per-client token buckets: each client may burst up to `capacity`
+/// requests, then gets `refill_per_sec` more per second.
+pub struct RateLimiter {
+    capacity:       f64,
+    refill_per_sec: f64,
+    buckets:        Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens:  f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        RateLimiter {
+            capacity:       f64::from(capacity),
+            refill_per_sec,
+            buckets:        Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token for `client`; false if its bucket is empty
+    pub fn allow(&self, client: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // forget clients whose buckets have refilled, so the map can't
+        // grow without bound
+        let full_after = self.capacity / self.refill_per_sec;
+        buckets.retain(|_, b| now.duration_since(b.updated).as_secs_f64() < full_after);
+
+        let bucket = buckets.entry(client).or_insert(Bucket { tokens: self.capacity, updated: now });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens  = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
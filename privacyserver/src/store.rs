@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::RwLock;
+
+use crate::ledger::{Ledger, WalletLimitError};
+use crate::paillier::PaillierCiphertext;
+
+/// Why a `LedgerStore` write failed
+#[derive(Debug)]
+pub enum StoreError {
+    /// the entry couldn't be persisted
+    Io(io::Error),
+    /// the wallet would be new, and the store holds its maximum
+    TooManyWallets(WalletLimitError),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e)             => write!(f, "{e}"),
+            StoreError::TooManyWallets(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<WalletLimitError> for StoreError {
+    fn from(e: WalletLimitError) -> Self {
+        StoreError::TooManyWallets(e)
+    }
+}
+
+/// `update_pair`'s `next`: both wallets' latest ciphertexts in, both new
+/// entries out
+pub type PairNext<'a> = dyn FnMut(Option<&PaillierCiphertext>, Option<&PaillierCiphertext>)
+                                  -> Option<(PaillierCiphertext, PaillierCiphertext)> + 'a;
+
+/// `update_pair`'s `appended`: each new entry with its index
+pub type PairAppended<'a> = dyn FnMut((&PaillierCiphertext, usize), (&PaillierCiphertext, usize)) + 'a;
+
+/// `update_held`'s `next`: the latest balance and held ciphertexts in;
+/// the new balance entry, if any, and the new held entry out
+pub type HeldNext<'a> = dyn FnMut(Option<&PaillierCiphertext>, Option<&PaillierCiphertext>)
+                                  -> Option<(Option<PaillierCiphertext>, PaillierCiphertext)> + 'a;
+
+/// `update_held`'s `appended`: the latest balance and held ciphertexts
+/// once both are stored
+pub type HeldAppended<'a> = dyn FnMut(Option<&PaillierCiphertext>, &PaillierCiphertext) + 'a;
+
+/// One `(wallet, currency)` with entries, as listed by `accounts`
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub wallet:   String,
+    pub currency: String,
+    /// latest net-balance ciphertext, if any
+    pub latest:   Option<PaillierCiphertext>,
+    /// latest held sub-balance ciphertext, if anything was ever held
+    pub held:     Option<PaillierCiphertext>,
+    /// number of net-balance entries
+    pub entries:  usize,
+}
+
+/// Storage backend for wallet histories, keyed by `(wallet, currency)`.
+///
+/// Each call is atomic on its own, but a `latest` followed by an
+/// `append` is not: read-modify-append flows (credit, debit, transfer)
+/// go through `update` or `update_pair` instead.
+pub trait LedgerStore: Send + Sync {
+    /// Latest net-balance ciphertext, if the wallet has any entries
+    fn latest(&self, wallet: &str, currency: &str) -> Option<PaillierCiphertext>;
+
+    /// Record `ct` as the wallet's new net balance
+    fn append(&self, wallet: &str, currency: &str, ct: PaillierCiphertext) -> Result<(), StoreError>;
+
+    /// Up to `limit` entries starting at `offset`, oldest first
+    fn history(&self, wallet: &str, currency: &str, offset: usize, limit: usize) -> Vec<PaillierCiphertext>;
+
+    /// Read-modify-append one wallet: `next` gets its latest ciphertext
+    /// and returns the entry to append, or `None` for none. No other
+    /// write to the wallet lands between the two. Once the entry is
+    /// stored, `appended` gets it and its index in the history, while the
+    /// wallet is still held, so callers can notify others in order.
+    fn update(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut dyn FnMut(Option<&PaillierCiphertext>) -> Option<PaillierCiphertext>,
+        appended: &mut dyn FnMut(&PaillierCiphertext, usize),
+    ) -> Result<(), StoreError>;
+
+    /// `update` for two different wallets in `currency` at once: both new
+    /// entries are stored, or neither
+    fn update_pair(
+        &self,
+        a:        &str,
+        b:        &str,
+        currency: &str,
+        next:     &mut PairNext<'_>,
+        appended: &mut PairAppended<'_>,
+    ) -> Result<(), StoreError>;
+
+    /// `update` for a wallet's balance and held sub-balance together:
+    /// both new entries are stored in one write, or neither
+    fn update_held(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut HeldNext<'_>,
+        appended: &mut HeldAppended<'_>,
+    ) -> Result<(), StoreError>;
+
+    /// Collapse the wallet's history to the single entry `next` makes of
+    /// its latest one, keeping only the latest held entry too. Returns
+    /// how many entries were dropped, or `None` if the wallet has none.
+    fn compact(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut dyn FnMut(&PaillierCiphertext) -> PaillierCiphertext,
+    ) -> io::Result<Option<usize>>;
+
+    /// Every account with entries, sorted by wallet then currency, as of
+    /// one instant: `seen` gets them before any other write can land, so
+    /// totals kept alongside the store (like the audit's) read in step
+    fn with_accounts(&self, seen: &mut dyn FnMut(&[Account]));
+
+    /// `with_accounts`, copied out
+    fn accounts(&self) -> Vec<Account> {
+        let mut out = Vec::new();
+        self.with_accounts(&mut |accounts| out = accounts.to_vec());
+        out
+    }
+
+    /// Replace everything stored with `accounts`, each history (and held
+    /// sub-balance) collapsing to its `latest` (and `held`) entry. Used
+    /// when every ciphertext changes at once, e.g. on key rotation.
+    fn replace_all(&self, accounts: &[Account]) -> io::Result<()>;
+}
+
+/// `LedgerStore` that keeps everything in memory; nothing survives a
+/// restart
+#[derive(Default)]
+pub struct InMemoryLedger {
+    wallets: RwLock<HashMap<(String, String), Entries>>,
+}
+
+/// One `InMemoryLedger` account's balance and held entries, oldest first
+#[derive(Default)]
+struct Entries {
+    history: Vec<PaillierCiphertext>,
+    held:    Vec<PaillierCiphertext>,
+}
+
+impl InMemoryLedger {
+    pub fn new() -> Self {
+        InMemoryLedger::default()
+    }
+}
+
+impl LedgerStore for InMemoryLedger {
+    fn latest(&self, wallet: &str, currency: &str) -> Option<PaillierCiphertext> {
+        self.wallets.read().unwrap()
+            .get(&(wallet.to_string(), currency.to_string()))
+            .and_then(|entries| entries.history.last().cloned())
+    }
+
+    fn append(&self, wallet: &str, currency: &str, ct: PaillierCiphertext) -> Result<(), StoreError> {
+        self.wallets.write().unwrap()
+            .entry((wallet.to_string(), currency.to_string()))
+            .or_default()
+            .history
+            .push(ct);
+        Ok(())
+    }
+
+    fn history(&self, wallet: &str, currency: &str, offset: usize, limit: usize) -> Vec<PaillierCiphertext> {
+        match self.wallets.read().unwrap().get(&(wallet.to_string(), currency.to_string())) {
+            Some(entries) => entries.history.iter().skip(offset).take(limit).cloned().collect(),
+            None          => Vec::new(),
+        }
+    }
+
+    fn update(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut dyn FnMut(Option<&PaillierCiphertext>) -> Option<PaillierCiphertext>,
+        appended: &mut dyn FnMut(&PaillierCiphertext, usize),
+    ) -> Result<(), StoreError> {
+        // the one write lock serializes every update
+        let mut wallets = self.wallets.write().unwrap();
+        let key = (wallet.to_string(), currency.to_string());
+        let Some(ct) = next(wallets.get(&key).and_then(|entries| entries.history.last())) else {
+            return Ok(());
+        };
+        let history = &mut wallets.entry(key).or_default().history;
+        history.push(ct);
+        appended(&history[history.len() - 1], history.len() - 1);
+        Ok(())
+    }
+
+    fn update_pair(
+        &self,
+        a:        &str,
+        b:        &str,
+        currency: &str,
+        next:     &mut PairNext<'_>,
+        appended: &mut PairAppended<'_>,
+    ) -> Result<(), StoreError> {
+        assert_ne!(a, b, "update_pair needs two different wallets");
+        let mut wallets = self.wallets.write().unwrap();
+        let (key_a, key_b) = ((a.to_string(), currency.to_string()), (b.to_string(), currency.to_string()));
+        let latest = |key| wallets.get(key).and_then(|entries: &Entries| entries.history.last());
+        let Some((ct_a, ct_b)) = next(latest(&key_a), latest(&key_b)) else {
+            return Ok(());
+        };
+        let seq_a = push(&mut wallets, key_a, ct_a.clone());
+        let seq_b = push(&mut wallets, key_b, ct_b.clone());
+        appended((&ct_a, seq_a), (&ct_b, seq_b));
+        Ok(())
+    }
+
+    fn update_held(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut HeldNext<'_>,
+        appended: &mut HeldAppended<'_>,
+    ) -> Result<(), StoreError> {
+        let mut wallets = self.wallets.write().unwrap();
+        let key = (wallet.to_string(), currency.to_string());
+        let current = wallets.get(&key);
+        let next = next(
+            current.and_then(|entries| entries.history.last()),
+            current.and_then(|entries| entries.held.last()),
+        );
+        let Some((balance, held)) = next else {
+            return Ok(());
+        };
+        let entries = wallets.entry(key).or_default();
+        entries.history.extend(balance);
+        entries.held.push(held);
+        appended(entries.history.last(), &entries.held[entries.held.len() - 1]);
+        Ok(())
+    }
+
+    fn compact(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut dyn FnMut(&PaillierCiphertext) -> PaillierCiphertext,
+    ) -> io::Result<Option<usize>> {
+        let mut wallets = self.wallets.write().unwrap();
+        let Some(entries) = wallets.get_mut(&(wallet.to_string(), currency.to_string())) else {
+            return Ok(None);
+        };
+        let Some(latest) = entries.history.last() else {
+            return Ok(None);
+        };
+        let ct = next(latest);
+        let removed = entries.history.len() - 1;
+        entries.history = vec![ct];
+        entries.held.drain(..entries.held.len().saturating_sub(1));
+        Ok(Some(removed))
+    }
+
+    fn with_accounts(&self, seen: &mut dyn FnMut(&[Account])) {
+        let wallets = self.wallets.read().unwrap();
+        let mut accounts: Vec<_> = wallets.iter()
+            .filter(|(_, entries)| !entries.history.is_empty() || !entries.held.is_empty())
+            .map(|((wallet, currency), entries)| Account {
+                wallet:   wallet.clone(),
+                currency: currency.clone(),
+                latest:   entries.history.last().cloned(),
+                held:     entries.held.last().cloned(),
+                entries:  entries.history.len(),
+            })
+            .collect();
+        accounts.sort_by(|a, b| (&a.wallet, &a.currency).cmp(&(&b.wallet, &b.currency)));
+        seen(&accounts);
+    }
+
+    fn replace_all(&self, accounts: &[Account]) -> io::Result<()> {
+        *self.wallets.write().unwrap() = accounts.iter()
+            .map(|account| {
+                let entries = Entries {
+                    history: account.latest.iter().cloned().collect(),
+                    held:    account.held.iter().cloned().collect(),
+                };
+                ((account.wallet.clone(), account.currency.clone()), entries)
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+/// Helper: append `ct` to the history at `key`, returning its index
+fn push(
+    wallets: &mut HashMap<(String, String), Entries>,
+    key:     (String, String),
+    ct:      PaillierCiphertext,
+) -> usize {
+    let history = &mut wallets.entry(key).or_default().history;
+    history.push(ct);
+    history.len() - 1
+}
+
+/// The JSONL-backed ledger (or, via `Ledger::default`, an in-memory one)
+impl LedgerStore for Ledger {
+    fn latest(&self, wallet: &str, currency: &str) -> Option<PaillierCiphertext> {
+        self.get(wallet, currency)
+            .and_then(|h| h.lock().unwrap().latest().cloned())
+    }
+
+    /// Creates the wallet as `with_wallet` does
+    fn append(&self, wallet: &str, currency: &str, ct: PaillierCiphertext) -> Result<(), StoreError> {
+        self.with_wallet(wallet, currency, |wallet| Ok(Ledger::append(self, wallet, ct)?))
+    }
+
+    fn history(&self, wallet: &str, currency: &str, offset: usize, limit: usize) -> Vec<PaillierCiphertext> {
+        match self.get(wallet, currency) {
+            Some(h) => h.lock().unwrap().history().iter().skip(offset).take(limit).cloned().collect(),
+            None    => Vec::new(),
+        }
+    }
+
+    /// Holds the wallet's lock throughout; creates it as `with_wallet`
+    /// does, so a `next` that appends nothing creates nothing
+    fn update(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut dyn FnMut(Option<&PaillierCiphertext>) -> Option<PaillierCiphertext>,
+        appended: &mut dyn FnMut(&PaillierCiphertext, usize),
+    ) -> Result<(), StoreError> {
+        self.with_wallet(wallet, currency, |wallet| {
+            if let Some(ct) = next(wallet.latest()) {
+                Ledger::append(self, wallet, ct)?;
+                let seq = wallet.history().len() - 1;
+                appended(&wallet.history()[seq], seq);
+            }
+            Ok(())
+        })
+    }
+
+    /// Locks both wallets as `with_wallet_pair` does and writes both
+    /// entries with one `append_all`
+    fn update_pair(
+        &self,
+        a:        &str,
+        b:        &str,
+        currency: &str,
+        next:     &mut PairNext<'_>,
+        appended: &mut PairAppended<'_>,
+    ) -> Result<(), StoreError> {
+        assert_ne!(a, b, "update_pair needs two different wallets");
+        self.with_wallet_pair(a, b, currency, |a, b| {
+            let Some((ct_a, ct_b)) = next(a.latest(), b.latest()) else {
+                return Ok(());
+            };
+            self.append_all(vec![(&mut *a, ct_a), (&mut *b, ct_b)])?;
+            let (seq_a, seq_b) = (a.history().len() - 1, b.history().len() - 1);
+            appended((&a.history()[seq_a], seq_a), (&b.history()[seq_b], seq_b));
+            Ok(())
+        })
+    }
+
+    /// Holds the wallet's lock throughout, as `update` does
+    fn update_held(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut HeldNext<'_>,
+        appended: &mut HeldAppended<'_>,
+    ) -> Result<(), StoreError> {
+        self.with_wallet(wallet, currency, |wallet| {
+            if let Some((balance, held)) = next(wallet.latest(), wallet.latest_held()) {
+                self.append_held(wallet, balance, held)?;
+                appended(wallet.latest(), wallet.latest_held().expect("a held entry was just appended"));
+            }
+            Ok(())
+        })
+    }
+
+    /// Rewrites the file as `Ledger::compact` does, holding the wallet's
+    /// lock from reading the latest entry on
+    fn compact(
+        &self,
+        wallet:   &str,
+        currency: &str,
+        next:     &mut dyn FnMut(&PaillierCiphertext) -> PaillierCiphertext,
+    ) -> io::Result<Option<usize>> {
+        let Some(handle) = self.get(wallet, currency) else {
+            return Ok(None);
+        };
+        let mut wallet = handle.lock().unwrap();
+        let Some(latest) = wallet.latest() else {
+            return Ok(None);
+        };
+        let ct = next(latest);
+        Ledger::compact(self, &mut wallet, ct).map(Some)
+    }
+
+    /// Holds every wallet's lock, in lock order, while `seen` runs
+    fn with_accounts(&self, seen: &mut dyn FnMut(&[Account])) {
+        let handles = self.wallets();
+        let wallets: Vec<_> = handles.iter().map(|h| h.lock().unwrap()).collect();
+        let accounts: Vec<_> = wallets.iter()
+            .filter(|wallet| wallet.latest().is_some() || wallet.latest_held().is_some())
+            .map(|wallet| Account {
+                wallet:   wallet.name().to_string(),
+                currency: wallet.currency().to_string(),
+                latest:   wallet.latest().cloned(),
+                held:     wallet.latest_held().cloned(),
+                entries:  wallet.history().len(),
+            })
+            .collect();
+        seen(&accounts);
+    }
+
+    /// Rewrites the file as `Ledger::replace_all` does; accounts without
+    /// a balance entry, or not in the ledger, are left out
+    fn replace_all(&self, accounts: &[Account]) -> io::Result<()> {
+        let mut accounts: Vec<_> = accounts.iter()
+            .filter_map(|account| Some((self.get(&account.wallet, &account.currency)?, account)))
+            .collect();
+        accounts.sort_by(|(_, a), (_, b)| (&a.wallet, &a.currency).cmp(&(&b.wallet, &b.currency)));
+        let mut wallets: Vec<_> = accounts.iter().map(|(handle, _)| handle.lock().unwrap()).collect();
+        let updates = wallets.iter_mut()
+            .zip(&accounts)
+            .filter_map(|(wallet, (_, account))| Some((&mut **wallet, account.latest.clone()?, account.held.clone())))
+            .collect();
+        Ledger::replace_all(self, updates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+
+    /// A stand-in ciphertext; the store never looks inside one
+    fn ct(c: u32) -> PaillierCiphertext {
+        PaillierCiphertext::new(BigUint::from(c), BigUint::from(1_000_003u32))
+    }
+
+    /// Helper: the `c` values of `cts`, to compare
+    fn values<'a>(cts: impl IntoIterator<Item = &'a PaillierCiphertext>) -> Vec<BigUint> {
+        cts.into_iter().map(|ct| ct.c.clone()).collect()
+    }
+
+    /// The contract every backend must meet
+    fn exercise(store: &dyn LedgerStore) {
+        assert!(store.latest("alice", "USD").is_none());
+        for c in 1..=5 {
+            store.append("alice", "USD", ct(c)).unwrap();
+        }
+        store.append("alice", "EUR", ct(9)).unwrap();
+        assert_eq!(values(&store.latest("alice", "USD")), values(&[ct(5)]));
+        assert_eq!(values(&store.history("alice", "USD", 1, 2)), values(&[ct(2), ct(3)]));
+        assert_eq!(values(&store.history("alice", "USD", 4, 10)), values(&[ct(5)]));
+        assert!(store.history("bob", "USD", 0, 10).is_empty());
+
+        // `next` sees the latest entry; `appended` the new one and its index
+        let mut seen = None;
+        store.update("alice", "USD", &mut |latest| latest.map(|_| ct(6)), &mut |c, seq| seen = Some((c.c.clone(), seq)))
+             .unwrap();
+        assert_eq!(seen, Some((BigUint::from(6u32), 5)));
+        // `None` appends nothing, and `appended` isn't called
+        store.update("alice", "USD", &mut |_| None, &mut |_, _| panic!("nothing was appended")).unwrap();
+        assert_eq!(values(&store.latest("alice", "USD")), values(&[ct(6)]));
+
+        let mut seen = None;
+        store.update_pair(
+            "alice",
+            "bob",
+            "USD",
+            &mut |a, b| {
+                assert_eq!((values(a), values(b)), (values(&[ct(6)]), vec![]));
+                Some((ct(7), ct(8)))
+            },
+            &mut |(_, seq_a), (_, seq_b)| seen = Some((seq_a, seq_b)),
+        )
+        .unwrap();
+        assert_eq!(seen, Some((6, 0)));
+        assert_eq!(values(&store.latest("bob", "USD")), values(&[ct(8)]));
+
+        // a held entry, with or without a balance entry beside it
+        let mut seen = None;
+        store.update_held(
+            "bob",
+            "USD",
+            &mut |balance, held| {
+                assert_eq!((values(balance), values(held)), (values(&[ct(8)]), vec![]));
+                Some((Some(ct(10)), ct(11)))
+            },
+            &mut |balance, held| seen = Some((values(balance), held.c.clone())),
+        )
+        .unwrap();
+        assert_eq!(seen, Some((values(&[ct(10)]), BigUint::from(11u32))));
+        store.update_held("bob", "USD", &mut |_, _| Some((None, ct(12))), &mut |_, _| ()).unwrap();
+        assert_eq!(values(&store.latest("bob", "USD")), values(&[ct(10)]));
+
+        // every account, sorted, with its latest entries
+        let accounts = store.accounts();
+        let listed: Vec<_> = accounts.iter()
+            .map(|a| (a.wallet.as_str(), a.currency.as_str(), a.entries, values(&a.held)))
+            .collect();
+        assert_eq!(listed, vec![
+            ("alice", "EUR", 1, vec![]),
+            ("alice", "USD", 7, vec![]),
+            ("bob", "USD", 2, values(&[ct(12)])),
+        ]);
+
+        // compacting keeps what `next` makes of the latest entry
+        assert_eq!(store.compact("alice", "USD", &mut |latest| {
+            assert_eq!(values([latest]), values(&[ct(7)]));
+            ct(13)
+        }).unwrap(), Some(6));
+        assert_eq!(values(&store.history("alice", "USD", 0, 10)), values(&[ct(13)]));
+        assert_eq!(store.compact("carol", "USD", &mut |_| panic!("carol has no entries")).unwrap(), None);
+
+        // replacing every account leaves just the given entries
+        let mut accounts = store.accounts();
+        for account in &mut accounts {
+            account.latest = Some(ct(20));
+        }
+        store.replace_all(&accounts).unwrap();
+        assert_eq!(values(&store.history("bob", "USD", 0, 10)), values(&[ct(20)]));
+        assert_eq!(values(&store.history("alice", "EUR", 0, 10)), values(&[ct(20)]));
+        assert_eq!(values(&store.accounts()[2].held), values(&[ct(12)]));
+    }
+
+    #[test]
+    fn in_memory_ledger_meets_the_contract() {
+        exercise(&InMemoryLedger::new());
+    }
+
+    #[test]
+    fn ledger_meets_the_contract() {
+        exercise(&Ledger::default());
+    }
+}
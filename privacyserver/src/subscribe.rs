@@ -0,0 +1,88 @@
+use actix_web::{rt, web, HttpRequest, HttpResponse};
+use actix_ws::Message;
+use once_cell::sync::Lazy;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::instrument;
+
+use privacyserver::paillier::PaillierCiphertext;
+
+use crate::{tenant, AccountPath};
+
+/// How many updates a slow subscriber may fall behind before it starts
+/// missing them
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A wallet's new net-balance ciphertext
+#[derive(Clone)]
+struct Update {
+    /// `Tenant::name` of the wallet's tenant
+    tenant:   Option<String>,
+    wallet:   String,
+    currency: String,
+    c:        String,
+}
+
+/// Every update goes to every subscriber; each filters for its wallet
+static UPDATES: Lazy<broadcast::Sender<Update>> =
+    Lazy::new(|| broadcast::channel(CHANNEL_CAPACITY).0);
+
+/// Push `ct` to everyone subscribed to `wallet`'s `currency` balance.
+/// Called by the handlers after the new entry is appended, while the
+/// wallet is still locked, so subscribers see updates in order.
+pub fn publish(wallet: &str, currency: &str, ct: &PaillierCiphertext) {
+    // an error only means nobody is subscribed right now
+    let _ = UPDATES.send(Update {
+        tenant:   tenant::current().name.clone(),
+        wallet:   wallet.to_string(),
+        currency: currency.to_string(),
+        c:        ct.c.to_str_radix(10),
+    });
+}
+
+/// GET /subscribe/{wallet}[/{currency}]
+/// Upgrades to a WebSocket that receives the new ciphertext, as a decimal
+/// string text frame, each time the wallet's balance changes.
+#[instrument(skip_all, fields(operation = "subscribe", wallet = %path.wallet))]
+pub async fn subscribe(
+    req:    HttpRequest,
+    path:   web::Path<AccountPath>,
+    stream: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let AccountPath { wallet, currency } = path.into_inner().normalized()?;
+    let (response, mut session, mut incoming) = actix_ws::handle(&req, stream)?;
+
+    // subscribe before returning the upgrade, so no update after the
+    // handshake is missed
+    let mut updates = UPDATES.subscribe();
+    // the spawned task doesn't run as part of the request
+    let tenant = &tenant::current().name;
+
+    rt::spawn(async move {
+        loop {
+            tokio::select! {
+                update = updates.recv() => match update {
+                    Ok(u) if &u.tenant == tenant && u.wallet == wallet && u.currency == currency => {
+                        if session.text(u.c).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                msg = incoming.recv() => match msg {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        // dropping `updates` here removes the subscription
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
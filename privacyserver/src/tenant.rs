@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpRequest, HttpResponse};
+use num_bigint::BigInt;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use privacyserver::ledger::Ledger;
+use privacyserver::paillier::PaillierKey;
+
+use crate::auth::{self, ApiKeys};
+use crate::cache::DecryptCache;
+use crate::error::ApiError;
+use crate::idempotency::IdempotencyStore;
+use crate::pool::RandomnessPool;
+use crate::{audit, config, generate_key, key_self_test, require_admin};
+
+/// One independent set of wallets: a ledger and keypair of its own, with
+/// everything else that's scoped to them. Handlers reach the current
+/// tenant's through `ledger()`, `key()` and friends, never directly.
+pub struct Tenant {
+    /// `None` for the default tenant, served at the root paths
+    pub name:          Option<String>,
+    pub ledger:        Arc<Ledger>,
+    /// replaced by `/admin/rotate-key`
+    pub key:           RwLock<Arc<PaillierKey>>,
+    pub key_path:      PathBuf,
+    /// held shared by every handler that uses `key`, and exclusively by a
+    /// key rotation (see `rotation_gate`)
+    pub rotation:      RwLock<()>,
+    /// hex SHA-256 of the token for this tenant's admin endpoints: the
+    /// default tenant's is `ADMIN_TOKEN`, a named tenant's is minted by
+    /// `POST /admin/tenants`. `None` rejects every admin request.
+    pub admin_digest:  Option<String>,
+    pub api_keys:      ApiKeys,
+    /// responses to `Idempotency-Key` requests
+    pub idempotency:   IdempotencyStore,
+    /// plaintexts of recent decryptions under `key`
+    pub decrypt_cache: DecryptCache,
+    /// precomputed encryption randomness for `key`
+    pub randomness:    RandomnessPool,
+    /// when `/net` last refreshed each (wallet, currency), under
+    /// `--refresh-on-read`
+    pub last_refresh:  Mutex<HashMap<(String, String), Instant>>,
+    /// net amount credited minus debited per currency: what the wallets'
+    /// balances should add up to, for `/admin/audit`. Every change is
+    /// made while the affected wallet is locked, so a reader holding
+    /// every wallet lock sees a total that matches the ledger.
+    pub expected:      Mutex<BTreeMap<String, BigInt>>,
+}
+
+impl Tenant {
+    /// Open the ledger at `ledger_path` under `key` and load the API keys
+    /// at `api_keys_path`, creating either file as needed
+    pub fn new(
+        name:          Option<String>,
+        key:           PaillierKey,
+        key_path:      &Path,
+        ledger_path:   &Path,
+        api_keys_path: &Path,
+        admin_digest:  Option<String>,
+    ) -> io::Result<Self> {
+        let config = config();
+        let mut ledger = Ledger::open_with_format(ledger_path, &key.n_squared, config.ledger_format)?;
+        if let Some(max) = config.max_wallets {
+            ledger = ledger.with_max_wallets(max);
+        }
+        let randomness = RandomnessPool::new(config.randomness_pool, &key);
+        Ok(Tenant {
+            name,
+            ledger:        Arc::new(ledger),
+            key:           RwLock::new(Arc::new(key)),
+            key_path:      key_path.to_path_buf(),
+            rotation:      RwLock::new(()),
+            admin_digest,
+            api_keys:      ApiKeys::load(api_keys_path)?,
+            idempotency:   IdempotencyStore::new(config.idempotency_ttl),
+            decrypt_cache: DecryptCache::new(config.decrypt_cache),
+            randomness,
+            last_refresh:  Mutex::new(HashMap::new()),
+            expected:      Mutex::new(BTreeMap::new()),
+        })
+    }
+}
+
+/// The default tenant; set up in `main`
+static ROOT: OnceCell<&'static Tenant> = OnceCell::new();
+
+/// Named tenants opened so far. Tenants are never closed, so they're
+/// leaked to hand out `&'static` references.
+static TENANTS: Lazy<RwLock<HashMap<String, &'static Tenant>>> = Lazy::new(Default::default);
+
+tokio::task_local! {
+    /// The tenant a `/{tenant}/...` request is for; unset for root paths
+    static CURRENT: &'static Tenant;
+}
+
+/// Make `tenant` the default tenant, total its ledger for the audit and
+/// start filling its randomness pool
+pub fn init_root(tenant: Tenant) -> Result<(), ApiError> {
+    let tenant: &'static Tenant = Box::leak(Box::new(tenant));
+    if ROOT.set(tenant).is_err() {
+        panic!("root tenant initialized twice");
+    }
+    tenant.randomness.refill(&tenant.key.read().unwrap());
+    audit::init()
+}
+
+/// The tenant the running request is for
+pub fn current() -> &'static Tenant {
+    CURRENT.try_with(|tenant| *tenant).unwrap_or_else(|_| {
+        ROOT.get().expect("root tenant is set up before the server starts")
+    })
+}
+
+/// The default tenant, then every named tenant opened so far
+pub fn all() -> Vec<&'static Tenant> {
+    let root = ROOT.get().expect("root tenant is set up before the server starts");
+    std::iter::once(*root).chain(TENANTS.read().unwrap().values().copied()).collect()
+}
+
+/// Run `f` as part of a request for `tenant`, e.g. in a `web::block`
+/// closure, which doesn't inherit the request's tenant
+pub fn within<T>(tenant: &'static Tenant, f: impl FnOnce() -> T) -> T {
+    CURRENT.sync_scope(tenant, f)
+}
+
+/// First path segment of every root route. A tenant by one of these
+/// names would be shadowed by the root route, so none may have it.
+const RESERVED: &[&str] = &[
+    "admin", "balance", "capture", "check-threshold", "compact", "credit", "debit", "decrypt",
+    "decrypt-ciphertext", "encrypt", "healthz", "history", "hold", "metrics", "net", "openapi.json",
+    "oracle", "pubkey", "register", "release", "simulate", "spend", "submit", "subscribe", "transfer",
+];
+
+/// Name of the file in a tenant's directory holding the hex SHA-256 of
+/// its admin token
+const ADMIN_DIGEST_FILE: &str = "admin_token.sha256";
+
+/// Helper: `Err(InvalidTenant)` unless `name` is usable as a tenant name
+fn check_name(name: &str) -> Result<(), ApiError> {
+    let valid = (1..=64).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid || RESERVED.contains(&name) {
+        return Err(ApiError::InvalidTenant(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Helper: where tenant `name` keeps its files, under `config().tenants_dir`
+fn dir(name: &str) -> PathBuf {
+    config().tenants_dir.as_ref().expect("tenants are only served with --tenants-dir").join(name)
+}
+
+/// Helper: the path in `dir` of the file a root path names
+fn file(dir: &Path, root: &Path) -> PathBuf {
+    dir.join(root.file_name().expect("root paths name a file"))
+}
+
+/// The tenant called `name`, loaded from its directory under
+/// `config().tenants_dir` on first use; 404 unless `POST /admin/tenants`
+/// created it. Loading happens outside every lock, so a slow load holds
+/// up no other tenant.
+pub fn open(name: &str) -> Result<&'static Tenant, ApiError> {
+    check_name(name)?;
+    if let Some(tenant) = TENANTS.read().unwrap().get(name) {
+        return Ok(tenant);
+    }
+    let tenant = load(name)?;
+
+    // another request may have loaded it meanwhile; keep whichever was
+    // registered first, so every request sees the same tenant
+    let mut tenants = TENANTS.write().unwrap();
+    if let Some(tenant) = tenants.get(name) {
+        return Ok(tenant);
+    }
+    let tenant: &'static Tenant = Box::leak(Box::new(tenant));
+    tenants.insert(name.to_string(), tenant);
+    drop(tenants);
+    within(tenant, audit::init)?;
+    tenant.randomness.refill(&tenant.key.read().unwrap());
+    Ok(tenant)
+}
+
+/// Helper: read a created tenant's files from its directory, named like
+/// the root tenant's. The key file is written last by `create`, so
+/// until it exists the tenant doesn't either.
+fn load(name: &str) -> Result<Tenant, ApiError> {
+    let config = config();
+    let dir = dir(name);
+    let key_path = file(&dir, &config.key_path);
+    if !key_path.exists() {
+        return Err(ApiError::TenantNotFound(name.to_string()));
+    }
+    let key = PaillierKey::from_json(&fs::read_to_string(&key_path)?)
+        .map_err(|e| ApiError::Internal(format!("failed to load the tenant key: {e}")))?;
+    if !key_self_test(&key) {
+        return Err(ApiError::Internal("tenant key failed its self-test".into()));
+    }
+    // tenants created before admin tokens were per tenant have none
+    let admin_digest = match fs::read_to_string(dir.join(ADMIN_DIGEST_FILE)) {
+        Ok(digest)                                   => Some(digest.trim().to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e)                                       => return Err(e.into()),
+    };
+    let tenant = Tenant::new(
+        Some(name.to_string()),
+        key,
+        &key_path,
+        &file(&dir, &config.ledger_path),
+        &file(&dir, &config.api_keys_path),
+        admin_digest,
+    )?;
+    info!(tenant = name, "tenant opened");
+    Ok(tenant)
+}
+
+/// Helper: start tenant `name`'s directory with a fresh keypair and the
+/// digest of a newly minted admin token, which is returned. Claiming the
+/// directory is what makes two creations of one name exclusive.
+fn create(name: &str) -> Result<String, ApiError> {
+    check_name(name)?;
+    let dir = dir(name);
+    fs::create_dir_all(dir.parent().expect("a tenant directory has a parent"))?;
+    match fs::create_dir(&dir) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(ApiError::TenantExists(name.to_string()));
+        }
+        result => result?,
+    }
+
+    let written = (|| {
+        let key = generate_key()?;
+        let token = auth::mint_token();
+        fs::write(dir.join(ADMIN_DIGEST_FILE), auth::digest(&token))?;
+        // the key goes in last, atomically: its presence marks the
+        // tenant as complete for `load`
+        let key_path = file(&dir, &config().key_path);
+        let staged = key_path.with_extension("json.tmp");
+        fs::write(&staged, key.to_json())?;
+        fs::rename(&staged, &key_path)?;
+        Ok::<_, ApiError>(token)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_dir_all(&dir);
+    }
+    written
+}
+
+#[derive(Deserialize)]
+pub struct CreateTenantRequest {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct CreateTenantResponse {
+    tenant:      String,
+    /// shown only this once; the server keeps just its hash
+    admin_token: String,
+}
+
+/// POST /admin/tenants
+/// { "name": "acme" }
+/// Requires the default tenant's `Authorization: Bearer <ADMIN_TOKEN>`.
+/// Creates a tenant with its own ledger and keypair, served under
+/// `/acme/...`, and mints the token for its admin endpoints. 409 if the
+/// tenant already exists. Only mounted with `--tenants-dir`.
+#[instrument(skip_all, fields(operation = "create_tenant", tenant = %body.name))]
+pub async fn create_tenant(
+    req:  HttpRequest,
+    body: web::Json<CreateTenantRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&req)?;
+    let name = body.into_inner().name;
+    // key generation can take seconds; keep it off the async workers
+    let (name, admin_token) = web::block(move || {
+        let token = create(&name)?;
+        open(&name)?;
+        Ok::<_, ApiError>((name, token))
+    })
+    .await??;
+    info!("tenant created");
+    Ok(HttpResponse::Ok().json(CreateTenantResponse { tenant: name, admin_token }))
+}
+
+/// Middleware for the `/{tenant}` scope: serve the request as part of
+/// its tenant, or answer 404 if no such tenant was created. Paths no
+/// route matches fall through to a 404 without loading a tenant.
+pub async fn resolve(
+    req:  ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if !req.resource_map().has_resource(req.path()) {
+        return next.call(req).await;
+    }
+    let name = req.match_info().get("tenant").unwrap_or_default().to_string();
+    let tenant = web::block(move || open(&name)).await??;
+    CURRENT.scope(tenant, next.call(req)).await
+}
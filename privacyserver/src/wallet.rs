@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// Which wallet identifiers the server accepts (`--wallet-format`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalletFormat {
+    /// any string, used exactly as given
+    Any,
+    /// Ethereum-style `0x` + 40 hex digits, compared case-insensitively
+    Eth,
+}
+
+/// Why a wallet identifier was rejected
+#[derive(Debug)]
+pub enum WalletError {
+    /// not `0x` followed by 40 hex digits
+    NotEthAddress(String),
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::NotEthAddress(w) => {
+                write!(f, "`{w}` is not an Ethereum address (0x + 40 hex digits)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WalletError {}
+
+/// The canonical form of `wallet` under the configured format, so that
+/// e.g. `0xABC…` and `0xabc…` name the same account. Every handler
+/// passes client-supplied wallets through this before touching the ledger.
+pub fn normalize_wallet(wallet: &str) -> Result<String, WalletError> {
+    match crate::config().wallet_format {
+        WalletFormat::Any => Ok(wallet.to_string()),
+        WalletFormat::Eth => {
+            let hex = wallet.strip_prefix("0x").or_else(|| wallet.strip_prefix("0X"));
+            match hex {
+                Some(h) if h.len() == 40 && h.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                    Ok(format!("0x{}", h.to_ascii_lowercase()))
+                }
+                _ => Err(WalletError::NotEthAddress(wallet.to_string())),
+            }
+        }
+    }
+}
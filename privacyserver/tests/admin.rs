@@ -0,0 +1,210 @@
+mod common;
+
+use common::Server;
+
+#[test]
+fn decrypt_needs_the_admin_token() {
+    let server = Server::start(&[]);
+    server.credit("alice", 75);
+
+    let anonymous = server.post("/decrypt/alice").send();
+    assert_eq!((anonymous.status, anonymous.code()), (401, "UNAUTHORIZED".to_string()));
+    // same length as the real token, differing only in the last byte
+    let wrong = server.post("/decrypt/alice").bearer("test-admiN").send();
+    assert_eq!(wrong.status, 401);
+
+    let admin = server.post("/decrypt/alice").admin().send();
+    assert_eq!(admin.status, 200);
+    assert_eq!(admin.json(), serde_json::json!({ "wallet": "alice", "currency": "USD", "balance": 75 }));
+}
+
+#[test]
+fn compact_collapses_history_to_the_balance() {
+    let mut server = Server::start(&[]);
+    for amount in [10, 20, 30] {
+        server.credit("alice", amount);
+    }
+    server.credit("bob", 5);
+    let latest = server.get("/net/alice").send().json()["c"].clone();
+
+    assert_eq!(server.post("/compact/alice").send().status, 401);
+    let compact = server.post("/compact/alice").admin().send();
+    assert_eq!(compact.json(), serde_json::json!({ "wallet": "alice", "currency": "USD", "removed": 2 }));
+    let history = server.get("/history/alice").send().json();
+    assert_eq!(history, serde_json::json!([{ "index": 0, "c": latest }]));
+
+    let rerandomized = server.post("/compact/alice?rerandomize=true").admin().send();
+    assert_eq!(rerandomized.json()["removed"], 0);
+    assert_ne!(server.get("/net/alice").send().json()["c"], latest);
+
+    // the rewritten file reloads with the same balances
+    server.restart();
+    assert_eq!(server.get("/history/alice").send().json().as_array().unwrap().len(), 1);
+    assert_eq!((server.balance("alice"), server.balance("bob")), (60.into(), 5.into()));
+    assert_eq!(server.post("/compact/nobody").admin().send().status, 404);
+}
+
+#[test]
+fn rotation_re_encrypts_every_balance() {
+    let mut server = Server::start(&[]);
+    server.credit("alice", 40);
+    server.credit("alice", 2);
+    server.post("/credit").json(serde_json::json!({ "wallet": "bob", "amount": 9, "currency": "EUR" })).send();
+    let old_c = server.get("/net/alice").send().json()["c"].clone();
+    let old_fingerprint = server.get("/pubkey/fingerprint").send().json()["fingerprint"].clone();
+
+    assert_eq!(server.post("/admin/rotate-key").send().status, 401);
+    let rotated = server.post("/admin/rotate-key").admin().send();
+    assert_eq!(rotated.status, 200, "{}", rotated.text());
+    assert_eq!(rotated.json()["wallets"], 2);
+    assert_eq!(rotated.json()["n"], server.get("/pubkey").send().json()["n"]);
+    assert_ne!(server.get("/pubkey/fingerprint").send().json()["fingerprint"], old_fingerprint);
+
+    let history = server.get("/history/alice").send().json();
+    assert_eq!(history.as_array().unwrap().len(), 1);
+    assert_ne!(history[0]["c"], old_c);
+    assert_eq!(server.balance("alice"), 42);
+
+    // the new key and ciphertexts are what a restart loads
+    server.restart();
+    assert_eq!(server.balance("alice"), 42);
+    assert_eq!(server.post("/decrypt/bob/EUR").admin().send().json()["balance"], 9);
+    server.credit("alice", 1);
+    assert_eq!(server.balance("alice"), 43);
+}
+
+#[test]
+fn reset_zeroes_the_balance_and_keeps_history() {
+    let server = Server::start(&[]);
+    server.credit("alice", 80);
+    server.post("/transfer").json(serde_json::json!({ "from": "bob", "to": "carol", "amount": 5 })).send();
+
+    assert_eq!(server.post("/admin/reset/alice").send().status, 401);
+    assert_eq!(server.post("/admin/reset/nobody").admin().send().status, 404);
+    assert_eq!(server.post("/admin/reset/alice").admin().send().status, 200);
+    assert_eq!(server.balance("alice"), 0);
+    assert_eq!(server.get("/history/alice").send().json().as_array().unwrap().len(), 2);
+
+    // overdrawn wallets reset to zero too
+    assert_eq!(server.balance("bob"), -5);
+    server.post("/admin/reset/bob").admin().send();
+    assert_eq!(server.balance("bob"), 0);
+    server.credit("alice", 3);
+    assert_eq!(server.balance("alice"), 3);
+}
+
+#[test]
+fn transfers_leave_the_audit_totals_unchanged() {
+    let mut server = Server::start(&[]);
+    server.credit("alice", 100);
+    server.credit("bob", 20);
+    let audit = || server.get("/admin/audit").admin().send().json();
+    let expected = serde_json::json!([{ "currency": "USD", "total": 120, "expected": 120, "balanced": true }]);
+    assert_eq!(audit(), expected);
+
+    server.post("/transfer").json(serde_json::json!({ "from": "alice", "to": "bob", "amount": 70 })).send();
+    server.post("/transfer").json(serde_json::json!({ "from": "bob", "to": "carol", "amount": 5 })).send();
+    assert_eq!(audit(), expected);
+
+    server.post("/debit").json(serde_json::json!({ "wallet": "carol", "amount": 5 })).send();
+    let after_debit = serde_json::json!([{ "currency": "USD", "total": 115, "expected": 115, "balanced": true }]);
+    assert_eq!(audit(), after_debit);
+    // a restart starts the expected totals from the ledger
+    server.restart();
+    assert_eq!(server.get("/admin/audit").admin().send().json(), after_debit);
+    assert_eq!(server.get("/admin/audit").send().status, 401);
+}
+
+#[test]
+fn admin_wallets_lists_every_funded_wallet() {
+    let server = Server::start(&[]);
+    assert_eq!(server.get("/admin/wallets").send().status, 401);
+    assert_eq!(server.get("/admin/wallets").admin().send().json(), serde_json::json!([]));
+
+    server.credit("bob", 1);
+    server.credit("alice", 1);
+    server.credit("alice", 2);
+    server.post("/credit").json(serde_json::json!({ "wallet": "alice", "amount": 3, "currency": "EUR" })).send();
+    // a rejected debit doesn't create a wallet
+    server.post("/debit").json(serde_json::json!({ "wallet": "carol", "amount": 3 })).send();
+
+    assert_eq!(server.get("/admin/wallets").admin().send().json(), serde_json::json!(["alice", "bob"]));
+    assert_eq!(
+        server.get("/admin/wallets?counts=true").admin().send().json(),
+        serde_json::json!([{ "wallet": "alice", "entries": 3 }, { "wallet": "bob", "entries": 1 }]),
+    );
+}
+
+#[test]
+fn decrypt_reports_whole_units_under_scale() {
+    let server = Server::start(&["--scale", "100"]);
+    server.credit("alice", 1234);
+    assert_eq!(server.balance("alice"), "12.34");
+    server.post("/transfer").json(serde_json::json!({ "from": "alice", "to": "bob", "amount": 1239 })).send();
+    assert_eq!(server.balance("alice"), "-0.05");
+    assert_eq!(server.balance("bob"), "12.39");
+    server.credit("carol", 700);
+    assert_eq!(server.balance("carol"), "7.00");
+
+    // without `--scale` the balance is the plain integer
+    let unscaled = Server::start(&[]);
+    unscaled.credit("alice", 1234);
+    assert_eq!(unscaled.balance("alice"), 1234);
+}
+
+#[test]
+fn idempotency_keys_can_be_listed_and_revoked() {
+    let server = Server::start(&[]);
+    let credit = || {
+        server.post("/credit").header("Idempotency-Key", "k-1")
+              .json(serde_json::json!({ "wallet": "alice", "amount": 10 })).send()
+    };
+    credit();
+    assert_eq!(server.get("/admin/idempotency").send().status, 401);
+    let listed = server.get("/admin/idempotency").admin().send().json();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    for (field, value) in [("key", "k-1"), ("operation", "credit"), ("wallet", "alice"), ("currency", "USD")] {
+        assert_eq!(listed[0][field], value);
+    }
+    assert!(listed[0]["ttl_remaining"].as_u64().unwrap() > 0);
+
+    // a replay changes nothing; after a revoke the same key runs again
+    credit();
+    assert_eq!(server.balance("alice"), 10);
+    let revoked = server.delete("/admin/idempotency/k-1").admin().send();
+    assert_eq!(revoked.json(), serde_json::json!({ "key": "k-1", "revoked": 1 }));
+    assert_eq!(server.get("/admin/idempotency").admin().send().json(), serde_json::json!([]));
+    credit();
+    assert_eq!(server.balance("alice"), 20);
+
+    let missing = server.delete("/admin/idempotency/nope").admin().send();
+    assert_eq!((missing.status, missing.code()), (404, "IDEMPOTENCY_KEY_NOT_FOUND".to_string()));
+}
+
+#[test]
+fn compare_reports_only_which_wallet_holds_more() {
+    let server = Server::start(&[]);
+    server.credit("alice", 70);
+    server.credit("bob", 30);
+    server.credit("carol", 70);
+    let compare = |a: &str, b: &str| {
+        let response = server.get(&format!("/admin/compare?a={a}&b={b}")).admin().send();
+        assert_eq!(response.status, 200, "{}", response.text());
+        response.json()
+    };
+
+    assert_eq!(compare("alice", "bob"), serde_json::json!({ "result": "a_greater" }));
+    assert_eq!(compare("bob", "alice"), serde_json::json!({ "result": "b_greater" }));
+    assert_eq!(compare("alice", "carol"), serde_json::json!({ "result": "equal" }));
+
+    // balances in another currency, an unknown wallet, and no token
+    for (wallet, amount) in [("alice", 10), ("bob", 100)] {
+        server.post("/credit").json(serde_json::json!({ "wallet": wallet, "amount": amount, "currency": "EUR" })).send();
+    }
+    let eur = server.get("/admin/compare?a=alice&b=bob&currency=EUR").admin().send();
+    assert_eq!(eur.json(), serde_json::json!({ "result": "b_greater" }));
+    let missing = server.get("/admin/compare?a=alice&b=nobody").admin().send();
+    assert_eq!(missing.status, 404);
+    assert_eq!(missing.code(), "WALLET_NOT_FOUND");
+    assert_eq!(server.get("/admin/compare?a=alice&b=bob").send().status, 401);
+}
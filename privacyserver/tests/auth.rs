@@ -0,0 +1,53 @@
+mod common;
+
+use serde_json::json;
+
+use common::Server;
+
+#[test]
+fn registered_wallets_need_their_api_key() {
+    let mut server = Server::start(&[]);
+    let registered = server.post("/register").json(json!({ "wallet": "alice" })).send().json();
+    let api_key = registered["api_key"].as_str().unwrap().to_string();
+    assert_eq!(api_key.len(), 64);
+
+    let credit = json!({ "wallet": "alice", "amount": 50 });
+    let anonymous = server.post("/credit").json(credit.clone()).send();
+    assert_eq!((anonymous.status, anonymous.code()), (401, "INVALID_API_KEY".to_string()));
+    assert_eq!(server.post("/credit").bearer(&api_key).json(credit.clone()).send().status, 200);
+    assert_eq!(server.post("/credit").admin().json(credit).send().status, 200);
+
+    // only the `from` side of a transfer needs a key
+    let transfer = json!({ "from": "alice", "to": "bob", "amount": 10 });
+    assert_eq!(server.post("/transfer").bearer("not-the-key").json(transfer.clone()).send().status, 401);
+    assert_eq!(server.post("/transfer").bearer(&api_key).json(transfer).send().status, 200);
+    let to_alice = json!({ "from": "bob", "to": "alice", "amount": 1 });
+    assert_eq!(server.post("/transfer").json(to_alice).send().status, 200);
+    assert_eq!(server.balance("alice"), 91);
+
+    // keys are stored hashed and survive a restart
+    let stored = std::fs::read_to_string(server.dir().join("api_keys.json")).unwrap();
+    assert!(!stored.contains(&api_key));
+    server.restart();
+    let debit = json!({ "wallet": "alice", "amount": 1 });
+    assert_eq!(server.post("/debit").json(debit.clone()).send().status, 401);
+    assert_eq!(server.post("/debit").bearer(&api_key).json(debit).send().status, 200);
+}
+
+#[test]
+fn funded_or_registered_wallets_cannot_be_taken_over() {
+    let server = Server::start(&[]);
+    server.credit("alice", 100);
+    let claim = server.post("/register").json(json!({ "wallet": "alice" })).send();
+    assert_eq!(claim.status, 401);
+    let key = server.post("/register").admin().json(json!({ "wallet": "alice" })).send().json()["api_key"].clone();
+
+    // re-registering replaces the key, so it takes the current one
+    assert_eq!(server.post("/register").json(json!({ "wallet": "alice" })).send().status, 401);
+    let rotated = server.post("/register").bearer(key.as_str().unwrap()).json(json!({ "wallet": "alice" })).send();
+    assert_eq!(rotated.status, 200);
+    let debit = json!({ "wallet": "alice", "amount": 1 });
+    assert_eq!(server.post("/debit").bearer(key.as_str().unwrap()).json(debit.clone()).send().status, 401);
+    let new_key = rotated.json()["api_key"].as_str().unwrap().to_string();
+    assert_eq!(server.post("/debit").bearer(&new_key).json(debit).send().status, 200);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::process::Command;
+
+use common::scratch_dir;
+
+#[test]
+fn key_bits_fall_back_to_the_environment() {
+    let dir = scratch_dir();
+    let status = Command::new(env!("CARGO_BIN_EXE_privacyserver"))
+        .env("PAILLIER_KEY_BITS", "513")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    // rejected before a key is generated or the address bound
+    assert_eq!(status.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&status.stdout).contains("key bits must be even and at least 512"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Helper: run `privacyserver decrypt` on the fixture key
+fn decrypt(ciphertext: &str) -> std::process::Output {
+    let key = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/key.json");
+    Command::new(env!("CARGO_BIN_EXE_privacyserver"))
+        .args(["decrypt", "--key", key, "--ciphertext", ciphertext])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn decrypt_prints_the_signed_plaintext() {
+    // encryptions of 1234 and -42 under the fixture key, made independently of this crate
+    let positive = "60769685603490722462775149894838880752998856807632198376953340672522993221508085732555216112465660904986598555613357135569543318868727789190057794337187665906030358314553377453467911089375334453782344652203502579116606583477684374322486373477269110629681479257699318316479910960733290569541043768372338923999";
+    let negative = "51774229720615562309589443884811931763933548412037907906338562923966338300294538305220381946039036212050259398112527340310683712210396105608932640810817782992902863670292874834882175226552848727017855128595794181659219191079828624449263034652442990359808882104920293685064486353520681453243017933701331169981";
+    for (c, m) in [(positive, "1234\n"), (negative, "-42\n")] {
+        let output = decrypt(c);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), m);
+    }
+
+    assert_eq!(decrypt("12x").status.code(), Some(2));
+    let zero = decrypt("0");
+    assert_eq!(zero.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&zero.stderr).contains("failed to decrypt"));
+}
+
+#[test]
+fn selftest_passes_on_this_build() {
+    let output = Command::new(env!("CARGO_BIN_EXE_privacyserver")).args(["selftest", "--rounds", "20"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{stdout}");
+    assert_eq!(stdout.lines().count(), 4);
+    assert!(stdout.lines().all(|line| line.ends_with(" ok")), "{stdout}");
+    assert!(stdout.contains("dec(enc(a) * k) == a * k: 20/20 ok"));
+
+    let bad = Command::new(env!("CARGO_BIN_EXE_privacyserver")).args(["selftest", "--rounds", "x"]).output().unwrap();
+    assert_eq!(bad.status.code(), Some(2));
+}
@@ -0,0 +1,343 @@
+// Each test binary uses a different part of this module
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+/// `ADMIN_TOKEN` of every server `Server::start` spawns
+pub const ADMIN: &str = "test-admin";
+
+/// How long a server gets to generate its key and start listening
+const STARTUP: Duration = Duration::from_secs(60);
+
+/// The real `privacyserver` binary, listening on a free local port with a
+/// small key and every file in a scratch directory of its own. Killed,
+/// and the directory removed, on drop.
+pub struct Server {
+    child: Child,
+    addr:  SocketAddr,
+    dir:   PathBuf,
+    args:  Vec<String>,
+}
+
+impl Server {
+    /// A server started with the default flags plus `args`
+    pub fn start(args: &[&str]) -> Server {
+        Server::start_in(scratch_dir(), args)
+    }
+
+    /// Like `start`, keeping its files in `dir`, e.g. one a stopped server
+    /// left behind
+    pub fn start_in(dir: PathBuf, args: &[&str]) -> Server {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let (child, addr) = spawn(&dir, &args);
+        Server { child, addr, dir, args }
+    }
+
+    /// The scratch directory holding the key, ledger and log
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Everything the server has logged so far
+    pub fn log(&self) -> String {
+        fs::read_to_string(self.dir.join("server.log")).unwrap_or_default()
+    }
+
+    /// Stop the server with SIGINT, as an operator would, and start it
+    /// again on the same files and flags
+    pub fn restart(&mut self) {
+        self.interrupt();
+        self.relaunch();
+    }
+
+    /// Kill the server without warning, as a crash would, and start it
+    /// again on the same files and flags
+    pub fn crash_and_restart(&mut self) {
+        self.child.kill().unwrap();
+        self.child.wait().unwrap();
+        self.relaunch();
+    }
+
+    /// Start the stopped server again
+    fn relaunch(&mut self) {
+        let (child, addr) = spawn(&self.dir, &self.args);
+        self.child = child;
+        self.addr = addr;
+    }
+
+    /// Send SIGINT and wait for a clean exit
+    pub fn interrupt(&mut self) {
+        let status = Command::new("kill").args(["-INT", &self.child.id().to_string()]).status().unwrap();
+        assert!(status.success(), "failed to signal the server");
+        let status = self.child.wait().unwrap();
+        assert!(status.success(), "server exited with {status}:\n{}", self.log());
+    }
+
+    pub fn get(&self, path: &str) -> Request<'_> {
+        Request::new(self, "GET", path)
+    }
+
+    pub fn post(&self, path: &str) -> Request<'_> {
+        Request::new(self, "POST", path)
+    }
+
+    pub fn delete(&self, path: &str) -> Request<'_> {
+        Request::new(self, "DELETE", path)
+    }
+
+    /// `POST /credit` of `amount` to `wallet`'s USD balance
+    pub fn credit(&self, wallet: &str, amount: i64) -> Response {
+        self.post("/credit").json(serde_json::json!({ "wallet": wallet, "amount": amount })).send()
+    }
+
+    /// `wallet`'s USD balance, via `/decrypt` with the admin token
+    pub fn balance(&self, wallet: &str) -> Value {
+        let response = self.post(&format!("/decrypt/{wallet}")).admin().send();
+        assert_eq!(response.status, 200, "{}", response.text());
+        response.json()["balance"].clone()
+    }
+
+    /// Open a WebSocket on `path`
+    pub fn websocket(&self, path: &str) -> WebSocket {
+        let mut stream = connect(self.addr);
+        write!(
+            stream,
+            "GET {path} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            self.addr,
+        ).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            assert_ne!(reader.read_line(&mut head).unwrap(), 0, "connection closed during the handshake");
+        }
+        assert!(head.starts_with("HTTP/1.1 101"), "not upgraded:\n{head}");
+        WebSocket { reader }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Helper: a fresh, empty directory under the system temp dir
+pub fn scratch_dir() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!("privacyserver-test-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+    let dir = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Helper: start the binary on a free port and wait until it accepts
+/// connections
+fn spawn(dir: &Path, args: &[String]) -> (Child, SocketAddr) {
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let log = fs::OpenOptions::new().create(true).append(true).open(dir.join("server.log")).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_privacyserver"))
+        .args(["--key-bits", "512", "--bind", &addr.to_string()])
+        .arg("--key-path").arg(dir.join("key.json"))
+        .arg("--ledger-path").arg(dir.join("ledger.jsonl"))
+        .arg("--api-keys-path").arg(dir.join("api_keys.json"))
+        .args(args)
+        .env("ADMIN_TOKEN", ADMIN)
+        .env_remove("RUST_LOG")
+        .stdout(log.try_clone().unwrap())
+        .stderr(log)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let started = Instant::now();
+    while TcpStream::connect(addr).is_err() {
+        if let Some(status) = child.try_wait().unwrap() {
+            panic!("server exited with {status}:\n{}", fs::read_to_string(dir.join("server.log")).unwrap());
+        }
+        assert!(started.elapsed() < STARTUP, "server didn't start listening on {addr}");
+        thread::sleep(Duration::from_millis(50));
+    }
+    (child, addr)
+}
+
+/// Helper: a connection to `addr` that gives up on a silent server
+fn connect(addr: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+    stream
+}
+
+/// One HTTP/1.1 request, built up and then `send`t
+pub struct Request<'a> {
+    server:  &'a Server,
+    method:  &'static str,
+    path:    String,
+    headers: Vec<(String, String)>,
+    body:    Vec<u8>,
+}
+
+impl<'a> Request<'a> {
+    fn new(server: &'a Server, method: &'static str, path: &str) -> Self {
+        Request { server, method, path: path.to_string(), headers: Vec::new(), body: Vec::new() }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// `Authorization: Bearer <token>`
+    pub fn bearer(self, token: &str) -> Self {
+        self.header("Authorization", &format!("Bearer {token}"))
+    }
+
+    /// The admin token
+    pub fn admin(self) -> Self {
+        self.bearer(ADMIN)
+    }
+
+    pub fn json(self, body: Value) -> Self {
+        self.raw(body.to_string().into_bytes())
+    }
+
+    /// A JSON content type with `body` as is
+    pub fn raw(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self.header("Content-Type", "application/json")
+    }
+
+    pub fn send(self) -> Response {
+        let mut stream = connect(self.server.addr);
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            self.method, self.path, self.server.addr, self.body.len(),
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(&self.body).unwrap();
+
+        let mut raw = Vec::new();
+        match stream.read_to_end(&mut raw) {
+            Ok(_) => {}
+            // the server may answer an oversized body before reading it all
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset && !raw.is_empty() => {}
+            Err(e) => panic!("{} {}: {e}", self.method, self.path),
+        }
+        Response::parse(&raw)
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    headers:    Vec<(String, String)>,
+    pub body:   Vec<u8>,
+}
+
+impl Response {
+    fn parse(raw: &[u8]) -> Response {
+        let split = raw.windows(4).position(|w| w == b"\r\n\r\n").expect("a complete response head");
+        let head = std::str::from_utf8(&raw[..split]).unwrap();
+        let mut lines = head.split("\r\n");
+        let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+        let headers: Vec<(String, String)> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        let mut response = Response { status, headers, body: raw[split + 4..].to_vec() };
+        if response.header("transfer-encoding") == Some("chunked") {
+            response.body = dechunk(&response.body);
+        }
+        response
+    }
+
+    /// The first value of header `name`, case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| panic!("{e} in {:?}", self.text()))
+    }
+
+    /// The `code` of a JSON error body
+    pub fn code(&self) -> String {
+        self.json()["code"].as_str().unwrap_or_else(|| panic!("no error code in {}", self.text())).to_string()
+    }
+}
+
+/// Helper: the body of a `Transfer-Encoding: chunked` response
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    loop {
+        let line = body.windows(2).position(|w| w == b"\r\n").expect("a chunk size line");
+        let size = usize::from_str_radix(std::str::from_utf8(&body[..line]).unwrap().trim(), 16).unwrap();
+        if size == 0 {
+            return out;
+        }
+        out.extend_from_slice(&body[line + 2..line + 2 + size]);
+        body = &body[line + 2 + size + 2..];
+    }
+}
+
+/// The client end of a WebSocket; only receives
+pub struct WebSocket {
+    reader: BufReader<TcpStream>,
+}
+
+impl WebSocket {
+    /// The next text frame, or `None` if none arrives within `timeout`.
+    /// Other frames are skipped.
+    pub fn recv_text(&mut self, timeout: Duration) -> Option<String> {
+        self.reader.get_ref().set_read_timeout(Some(timeout)).unwrap();
+        loop {
+            let mut head = [0u8; 2];
+            match self.reader.read_exact(&mut head) {
+                Ok(()) => {}
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return None,
+                Err(e) => panic!("reading a frame: {e}"),
+            }
+            // frames from a server are never masked
+            let len = match head[1] & 0x7f {
+                126 => {
+                    let mut len = [0u8; 2];
+                    self.reader.read_exact(&mut len).unwrap();
+                    u16::from_be_bytes(len) as usize
+                }
+                127 => {
+                    let mut len = [0u8; 8];
+                    self.reader.read_exact(&mut len).unwrap();
+                    u64::from_be_bytes(len) as usize
+                }
+                len => len as usize,
+            };
+            let mut payload = vec![0u8; len];
+            self.reader.read_exact(&mut payload).unwrap();
+            if head[0] & 0x0f == 0x1 {
+                return Some(String::from_utf8(payload).unwrap());
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use num_bigint::BigUint;
+use serde_json::json;
+
+use common::Server;
+
+#[test]
+fn errors_are_json_with_a_stable_code() {
+    let server = Server::start(&[]);
+    let missing = server.get("/net/nobody").send();
+    assert_eq!(missing.status, 404);
+    assert_eq!(missing.header("content-type"), Some("application/json"));
+    let body = missing.json();
+    assert_eq!(body["code"], "WALLET_NOT_FOUND");
+    assert!(body["message"].is_string());
+
+    // extractor failures get the same shape as handler errors
+    let malformed = server.post("/credit").raw(b"{\"wallet\": ".to_vec()).send();
+    assert_eq!((malformed.status, malformed.code()), (400, "INVALID_REQUEST".to_string()));
+    let negative = server.post("/credit").json(json!({ "wallet": "alice", "amount": -5 })).send();
+    assert_eq!((negative.status, negative.code()), (400, "INVALID_REQUEST".to_string()));
+    let bad_query = server.get("/history/alice?offset=x").send();
+    assert_eq!((bad_query.status, bad_query.code()), (400, "INVALID_REQUEST".to_string()));
+}
+
+#[test]
+fn a_zero_ciphertext_is_a_clean_400() {
+    let server = Server::start(&[]);
+    let zero = server.post("/decrypt-ciphertext").admin().json(json!({ "c": "0" })).send();
+    assert_eq!((zero.status, zero.code()), (400, "INVALID_CIPHERTEXT".to_string()));
+    assert!(zero.json()["message"].as_str().unwrap().contains("not a unit"));
+    assert_eq!(server.get("/healthz").send().status, 200);
+}
+
+#[test]
+fn ingested_ciphertexts_must_be_coprime_to_n_and_below_n_squared() {
+    let server = Server::start(&[]);
+    let n: BigUint = server.get("/pubkey").send().json()["n"].as_str().unwrap().parse().unwrap();
+    let proof = json!({ "a": "1", "z1": "1", "z2": "1" });
+    for c in [n.clone(), &n * &n, &n * 2u32] {
+        let submit = server.post("/submit").json(json!({ "wallet": "alice", "c": c.to_string(), "proof": proof })).send();
+        assert_eq!((submit.status, submit.code()), (400, "INVALID_CIPHERTEXT".to_string()), "{c}");
+    }
+    assert_eq!(server.get("/net/alice").send().status, 404);
+}
+
+#[test]
+fn error_bodies_carry_only_the_ends_of_a_ciphertext() {
+    let server = Server::start(&[]);
+    let n: BigUint = server.get("/pubkey").send().json()["n"].as_str().unwrap().parse().unwrap();
+    let c = (&n * &n + 12_345u32).to_string();
+    let masked = format!("{}…{}", &c[..8], &c[c.len() - 8..]);
+
+    let response = server.post("/decrypt-ciphertext").admin().json(json!({ "c": c })).send();
+    assert_eq!((response.status, response.code()), (400, "INVALID_CIPHERTEXT".to_string()));
+    let body = response.json();
+    assert_eq!(body["details"]["c"], masked.as_str());
+    assert!(body["message"].as_str().unwrap().contains(&masked), "{body}");
+    assert!(!response.text().contains(&c));
+
+    // the flag shows it whole
+    let full = Server::start(&["--full-ciphertext-logs"]);
+    let n: BigUint = full.get("/pubkey").send().json()["n"].as_str().unwrap().parse().unwrap();
+    let c = (&n * &n + 12_345u32).to_string();
+    let body = full.post("/decrypt-ciphertext").admin().json(json!({ "c": c })).send().json();
+    assert_eq!(body["details"]["c"], c.as_str());
+    assert!(body["message"].as_str().unwrap().contains(&c));
+}
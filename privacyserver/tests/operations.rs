@@ -0,0 +1,232 @@
+mod common;
+
+use std::io::{Read, Write};
+
+use flate2::read::GzDecoder;
+use num_bigint::BigUint;
+use serde_json::json;
+
+use common::Server;
+
+/// Helper: the value of sample `name` in a `/metrics` scrape
+fn metric(server: &Server, name: &str) -> f64 {
+    let scrape = server.get("/metrics").send().text();
+    scrape.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+        .unwrap_or_else(|| panic!("no `{name}` in\n{scrape}"))
+}
+
+#[test]
+fn metrics_count_credits_and_overdrafts() {
+    let server = Server::start(&[]);
+    let response = server.get("/metrics").send();
+    assert!(response.header("content-type").unwrap().starts_with("text/plain"));
+    assert_eq!(metric(&server, "privacyserver_credits_total"), 0.0);
+
+    server.credit("alice", 5);
+    server.credit("alice", 5);
+    server.post("/debit").json(json!({ "wallet": "alice", "amount": 50 })).send();
+    assert_eq!(metric(&server, "privacyserver_credits_total"), 2.0);
+    assert_eq!(metric(&server, "privacyserver_debits_total"), 0.0);
+    assert_eq!(metric(&server, "privacyserver_overdrafts_rejected_total"), 1.0);
+    assert!(metric(&server, "privacyserver_encrypt_duration_seconds_count") >= 2.0);
+    assert!(metric(&server, "privacyserver_keygen_retries_total") >= 0.0);
+}
+
+#[test]
+fn dev_keys_serve_requests() {
+    let server = Server::start(&["--dev"]);
+    assert!(server.log().contains("weakened primality test"));
+    server.credit("alice", 9);
+    assert_eq!(server.balance("alice"), 9);
+}
+
+#[test]
+fn healthz_is_ok_once_the_key_passes_its_self_test() {
+    let mut server = Server::start(&[]);
+    let response = server.get("/healthz").send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.json()["status"], "ok");
+
+    // a reloaded key is checked again on the next startup
+    server.restart();
+    assert_eq!(server.get("/healthz").send().status, 200);
+}
+
+#[test]
+fn deep_healthz_reports_an_unwritable_or_changed_ledger() {
+    let server = Server::start(&[]);
+    server.credit("alice", 5);
+    let healthy = server.get("/healthz/deep").send();
+    assert_eq!((healthy.status, healthy.json()), (200, json!({ "status": "ok" })));
+
+    // a directory in the way of the write probe fails it like a full disk
+    let probe = server.dir().join("ledger.jsonl.probe");
+    std::fs::create_dir(&probe).unwrap();
+    let unwritable = server.get("/healthz/deep").send();
+    assert_eq!((unwritable.status, unwritable.code()), (503, "LEDGER_UNHEALTHY".to_string()));
+    assert!(unwritable.json()["details"]["error"].is_string());
+    // the liveness check doesn't look at the ledger
+    assert_eq!(server.get("/healthz").send().status, 200);
+    std::fs::remove_dir(&probe).unwrap();
+    assert_eq!(server.get("/healthz/deep").send().status, 200);
+
+    // bytes the server didn't write
+    let ledger = server.dir().join("ledger.jsonl");
+    let expected = std::fs::metadata(&ledger).unwrap().len();
+    std::fs::OpenOptions::new().append(true).open(&ledger).unwrap().write_all(b"junk").unwrap();
+    let changed = server.get("/healthz/deep").send();
+    assert_eq!(changed.status, 503);
+    assert_eq!(changed.json()["details"], json!({ "tenant": null, "expected": expected, "actual": expected + 4 }));
+}
+
+#[test]
+fn requests_log_a_span_with_their_operation() {
+    let server = Server::start(&[]);
+    server.credit("alice", 4_242_421);
+    server.post("/debit").json(json!({ "wallet": "alice", "amount": 9_999_991 })).send();
+
+    let log = server.log();
+    let credit = log.lines()
+                    .find(|line| line.contains(r#"credit{operation="credit" wallet=alice}"#))
+                    .unwrap_or_else(|| panic!("no credit span in\n{log}"));
+    assert!(credit.contains(" INFO ") && credit.contains("time.busy="), "{credit}");
+    assert!(log.lines().any(|line| line.contains(" WARN ") && line.contains("overdraft rejected")), "{log}");
+    // amounts stay out of the log
+    assert!(!log.contains("4242421") && !log.contains("9999991"), "{log}");
+}
+
+#[test]
+fn primes_file_fixes_the_key() {
+    let p = "89832623605594700308282651133996556597155015442948055892434723408100995745117";
+    let q = "89570935008921592731648345912217359480680185339441583404202685174411415951337";
+    let n = (p.parse::<BigUint>().unwrap() * q.parse::<BigUint>().unwrap()).to_string();
+    let dir = common::scratch_dir();
+    std::fs::write(dir.join("primes.txt"), format!("{p}\n{q}\n")).unwrap();
+
+    let mut server = Server::start_in(dir, &["--primes-file", "primes.txt"]);
+    assert_eq!(server.get("/pubkey").send().json()["n"], n);
+    server.credit("alice", 8);
+    server.restart();
+    assert_eq!(server.get("/pubkey").send().json()["n"], n);
+    assert_eq!(server.balance("alice"), 8);
+
+    // a saved key from other primes is refused rather than overwritten
+    let other = "100666389020185440741628929214101234722534813247131044713797696990828578811677";
+    std::fs::write(server.dir().join("primes.txt"), format!("{p}\n{other}\n")).unwrap();
+    server.interrupt();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_privacyserver"))
+        .args(["--primes-file", "primes.txt", "--bind", "127.0.0.1:0"])
+        .current_dir(server.dir())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("not built from --primes-file"));
+}
+
+#[test]
+fn compression_gzips_for_clients_that_accept_it() {
+    let server = Server::start(&["--compression"]);
+    for wallet in ["alice", "bob", "carol"] {
+        server.credit(wallet, 1);
+    }
+    let plain = server.get("/net/all").send();
+    assert_eq!(plain.header("content-encoding"), None);
+
+    let gzipped = server.get("/net/all").header("Accept-Encoding", "gzip").send();
+    assert_eq!(gzipped.header("content-encoding"), Some("gzip"));
+    assert!(gzipped.body.len() < plain.body.len());
+    let mut unzipped = Vec::new();
+    GzDecoder::new(&gzipped.body[..]).read_to_end(&mut unzipped).unwrap();
+    assert_eq!(unzipped, plain.body);
+
+    // off by default
+    let uncompressed = Server::start(&[]);
+    let response = uncompressed.get("/pubkey").header("Accept-Encoding", "gzip").send();
+    assert_eq!(response.header("content-encoding"), None);
+}
+
+#[test]
+fn repeated_reads_of_a_balance_decrypt_once() {
+    let server = Server::start(&[]);
+    server.credit("alice", 5);
+    let (hits, misses) = (
+        metric(&server, "privacyserver_decrypt_cache_hits_total"),
+        metric(&server, "privacyserver_decrypt_cache_misses_total"),
+    );
+    for _ in 0..3 {
+        assert_eq!(server.post("/decrypt/alice").admin().send().json()["balance"], 5);
+    }
+    assert_eq!(metric(&server, "privacyserver_decrypt_cache_misses_total"), misses + 1.0);
+    assert_eq!(metric(&server, "privacyserver_decrypt_cache_hits_total"), hits + 2.0);
+
+    // `--decrypt-cache-size 0` decrypts every time
+    let uncached = Server::start(&["--decrypt-cache-size", "0"]);
+    uncached.credit("alice", 5);
+    for _ in 0..3 {
+        uncached.post("/decrypt/alice").admin().send();
+    }
+    assert_eq!(metric(&uncached, "privacyserver_decrypt_cache_hits_total"), 0.0);
+    assert!(metric(&uncached, "privacyserver_decrypt_cache_misses_total") >= 3.0);
+}
+
+#[test]
+fn openapi_describes_the_mounted_routes() {
+    let server = Server::start(&["--verified-net", "--oracle-mode", "--tenants-dir", "tenants"]);
+    server.credit("alice", 5);
+    let response = server.get("/openapi.json").send();
+    assert_eq!(response.status, 200);
+    let spec = response.json();
+    assert_eq!(spec["openapi"], "3.0.3");
+    let credit = &spec["paths"]["/credit"]["post"];
+    assert_eq!(credit["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+               "#/components/schemas/TxRequest");
+    assert!(credit["responses"].get("200").is_some());
+    assert!(spec["components"]["schemas"].get("ErrorResponse").is_some());
+
+    // an unrouted request is a bare 404, so every documented route must
+    // answer something else
+    let unrouted = server.get("/no-such-route").admin().send();
+    assert_eq!((unrouted.status, unrouted.body.len()), (404, 0));
+    for (path, ops) in spec["paths"].as_object().unwrap() {
+        let url = path.replace("{wallet}", "alice").replace("{currency}", "USD").replace("{key}", "k");
+        for method in ops.as_object().unwrap().keys() {
+            let request = match method.as_str() {
+                "get"    => server.get(&url),
+                "post"   => server.post(&url),
+                "delete" => server.delete(&url),
+                other    => panic!("unexpected method {other}"),
+            };
+            let response = request.admin().send();
+            assert!(response.status != 404 || !response.body.is_empty(), "{method} {path} is not mounted");
+        }
+    }
+}
+
+#[test]
+fn pooled_encryptions_decrypt_correctly() {
+    let server = Server::start(&["--randomness-pool-size", "16"]);
+    server.credit("alice", 1000);
+    // the pool fills in the background after startup; debits encrypt
+    // their amount, where credits fold it into the balance directly
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let mut debited = 0;
+    while metric(&server, "privacyserver_randomness_pool_hits_total") < 5.0 {
+        assert!(std::time::Instant::now() < deadline, "no encryption used the pool");
+        let debit = server.post("/debit").json(json!({ "wallet": "alice", "amount": 3 })).send();
+        assert_eq!(debit.status, 200, "{}", debit.text());
+        debited += 3;
+    }
+    assert_eq!(server.balance("alice"), 1000 - debited);
+    let history = server.get("/history/alice").send().json();
+    let cs: std::collections::HashSet<_> = history.as_array().unwrap().iter().map(|e| e["c"].clone()).collect();
+    assert_eq!(cs.len(), history.as_array().unwrap().len());
+
+    // and with no pool every encryption misses it
+    let unpooled = Server::start(&["--randomness-pool-size", "0"]);
+    unpooled.credit("alice", 5);
+    unpooled.post("/debit").json(json!({ "wallet": "alice", "amount": 3 })).send();
+    assert_eq!(metric(&unpooled, "privacyserver_randomness_pool_hits_total"), 0.0);
+    assert!(metric(&unpooled, "privacyserver_randomness_pool_misses_total") >= 1.0);
+    assert_eq!(unpooled.balance("alice"), 2);
+}
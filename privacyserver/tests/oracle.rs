@@ -0,0 +1,142 @@
+mod common;
+
+use base64::Engine;
+use num_bigint::BigUint;
+use serde_json::json;
+
+use privacyserver::paillier::encrypt_with_pubkey;
+
+use common::Server;
+
+/// Helper: a decimal string field of a JSON response
+fn big(value: &serde_json::Value) -> BigUint {
+    value.as_str().unwrap().parse().unwrap()
+}
+
+#[test]
+fn decrypt_ciphertext_checks_its_input_and_is_rate_limited() {
+    let server = Server::start(&[]);
+    server.credit("alice", 30);
+    server.credit("bob", 12);
+    let n = big(&server.get("/pubkey").send().json()["n"]);
+    let n_squared = &n * &n;
+
+    // a sum the client built from two balances
+    let a = big(&server.get("/net/alice").send().json()["c"]);
+    let b = big(&server.get("/net/bob").send().json()["c"]);
+    let sum = (a * b % &n_squared).to_string();
+    // any wallet's ciphertext is public, so only the admin may decrypt
+    let anonymous = server.post("/decrypt-ciphertext").json(json!({ "c": sum })).send();
+    assert_eq!((anonymous.status, anonymous.code()), (401, "UNAUTHORIZED".to_string()));
+    let response = server.post("/decrypt-ciphertext").admin().json(json!({ "c": sum })).send();
+    assert_eq!(response.json(), json!({ "plaintext": 42 }));
+
+    let out_of_range = server.post("/decrypt-ciphertext").admin().json(json!({ "c": n_squared.to_string() })).send();
+    assert_eq!((out_of_range.status, out_of_range.code()), (400, "INVALID_CIPHERTEXT".to_string()));
+
+    // two calls above spent part of the burst of 10
+    for _ in 2..10 {
+        assert_eq!(server.post("/decrypt-ciphertext").admin().json(json!({ "c": sum })).send().status, 200);
+    }
+    let limited = server.post("/decrypt-ciphertext").admin().json(json!({ "c": sum })).send();
+    assert_eq!((limited.status, limited.code()), (429, "RATE_LIMITED".to_string()));
+}
+
+#[test]
+fn client_side_encryptions_decrypt_on_the_server() {
+    let server = Server::start(&[]);
+    let pubkey = server.get("/pubkey").send().json();
+    let (n, g) = (big(&pubkey["n"]), big(&pubkey["g"]));
+
+    let local = encrypt_with_pubkey(&n, &g, &BigUint::from(123u32));
+    let response = server.post("/decrypt-ciphertext").admin().json(json!({ "c": local.c.to_string() })).send();
+    assert_eq!(response.json(), json!({ "plaintext": 123 }));
+
+    let encrypted = server.post("/encrypt").json(json!({ "amount": 77 })).send().json();
+    assert!(big(&encrypted["c"]) < &n * &n);
+    let response = server.post("/decrypt-ciphertext").admin().json(json!({ "c": encrypted["c"] })).send();
+    assert_eq!(response.json(), json!({ "plaintext": 77 }));
+}
+
+#[test]
+fn check_threshold_answers_without_the_balance() {
+    let server = Server::start(&[]);
+    server.credit("alice", 100);
+    let check = |threshold: i64| {
+        server.post("/check-threshold").admin().json(json!({ "wallet": "alice", "threshold": threshold })).send().json()
+    };
+    assert_eq!(check(99), json!({ "ok": true }));
+    assert_eq!(check(100), json!({ "ok": true }));
+    assert_eq!(check(101), json!({ "ok": false }));
+
+    let missing = server.post("/check-threshold").admin().json(json!({ "wallet": "nobody", "threshold": 1 })).send();
+    assert_eq!(missing.status, 404);
+}
+
+#[test]
+fn check_threshold_needs_the_wallets_key_or_the_admin_token() {
+    let server = Server::start(&[]);
+    server.credit("bob", 100);
+    let question = |wallet: &str| json!({ "wallet": wallet, "threshold": 1 });
+
+    // enough answers give the balance away, so even a wallet with no
+    // key is closed to anyone but the admin
+    let anonymous = server.post("/check-threshold").json(question("bob")).send();
+    assert_eq!((anonymous.status, anonymous.code()), (401, "INVALID_API_KEY".to_string()));
+
+    let registered = server.post("/register").json(json!({ "wallet": "alice" })).send().json();
+    let api_key = registered["api_key"].as_str().unwrap();
+    server.post("/credit").bearer(api_key).json(json!({ "wallet": "alice", "amount": 100 })).send();
+    let owner = server.post("/check-threshold").bearer(api_key).json(question("alice")).send();
+    assert_eq!(owner.json(), json!({ "ok": true }));
+    let other = server.post("/check-threshold").bearer("not-the-key").json(question("alice")).send();
+    assert_eq!(other.status, 401);
+    // alice's key is hers alone
+    assert_eq!(server.post("/check-threshold").bearer(api_key).json(question("bob")).send().status, 401);
+}
+
+#[test]
+fn oracle_endpoints_exist_only_in_oracle_mode() {
+    let plain = Server::start(&[]);
+    assert_eq!(plain.post("/oracle/encrypt").json(json!({ "m": "1" })).send().status, 404);
+
+    let server = Server::start(&["--oracle-mode"]);
+    let pubkey = server.get("/pubkey").send().json();
+    let (n, g) = (big(&pubkey["n"]), big(&pubkey["g"]));
+    let n_squared = &n * &n;
+
+    // fixed randomness reproduces c = gᵐ · rⁿ mod n² exactly
+    let fixed = server.post("/oracle/encrypt").json(json!({ "m": "42", "r": "12345" })).send().json();
+    let expected = g.modpow(&BigUint::from(42u32), &n_squared)
+                 * BigUint::from(12345u32).modpow(&n, &n_squared) % &n_squared;
+    assert_eq!(big(&fixed["c"]), expected);
+
+    let other = server.post("/oracle/encrypt").json(json!({ "m": "8" })).send().json();
+    let sum = server.post("/oracle/add").json(json!({ "a": fixed["c"], "b": other["c"] })).send().json();
+    assert_eq!(big(&sum["c"]), big(&fixed["c"]) * big(&other["c"]) % &n_squared);
+    let m = server.post("/oracle/decrypt").json(json!({ "c": sum["c"] })).send().json();
+    assert_eq!(m, json!({ "m": "50" }));
+
+    // raw plaintexts anywhere below n, with no signed decoding
+    let top = (&n - 1u32).to_string();
+    let c = server.post("/oracle/encrypt").json(json!({ "m": top })).send().json()["c"].clone();
+    assert_eq!(server.post("/oracle/decrypt").json(json!({ "c": c })).send().json()["m"], json!(top));
+    assert_eq!(server.post("/oracle/encrypt").json(json!({ "m": n.to_string() })).send().status, 400);
+    assert_eq!(server.post("/oracle/encrypt").json(json!({ "m": "1", "r": "0" })).send().status, 400);
+    assert_eq!(server.post("/oracle/decrypt").json(json!({ "c": "0" })).send().code(), "INVALID_CIPHERTEXT");
+}
+
+#[test]
+fn base64_ciphertexts_round_trip_through_the_api() {
+    let server = Server::start(&[]);
+    server.credit("alice", 64);
+    let decimal = big(&server.get("/net/alice").send().json()["c"]);
+    let encoded = server.get("/net/alice?encoding=base64").send().json()["c"].clone();
+    let bytes = base64::engine::general_purpose::STANDARD.decode(encoded.as_str().unwrap()).unwrap();
+    assert_eq!(BigUint::from_bytes_le(&bytes), decimal);
+
+    let decrypted = server.post("/decrypt-ciphertext?encoding=base64").admin().json(json!({ "c": encoded })).send();
+    assert_eq!(decrypted.json(), json!({ "plaintext": 64 }));
+    let garbage = server.post("/decrypt-ciphertext?encoding=base64").admin().json(json!({ "c": "@@@" })).send();
+    assert_eq!((garbage.status, garbage.code()), (400, "INVALID_REQUEST".to_string()));
+}
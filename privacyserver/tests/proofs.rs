@@ -0,0 +1,87 @@
+mod common;
+
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+
+use privacyserver::paillier::{
+    encrypt_with_randomness,
+    prove_ciphertext,
+    verify_decryption,
+    DecryptionProof,
+    PaillierCiphertext,
+    PaillierKey,
+};
+
+use common::Server;
+
+/// Helper: `v` as a decimal string's value
+fn big(v: &Value) -> BigUint {
+    v.as_str().unwrap_or_else(|| panic!("{v} is not a decimal string")).parse().unwrap()
+}
+
+/// Helper: does a `/balance/proof`-shaped response verify under `pubkey`?
+fn verifies(pubkey: &Value, body: &Value, claimed: u64) -> bool {
+    let (n, g) = (big(&pubkey["n"]), big(&pubkey["g"]));
+    let ct = PaillierCiphertext::new(big(&body["c"]), &n * &n);
+    let proof = DecryptionProof { m: claimed.into(), a: big(&body["proof"]["a"]), z: big(&body["proof"]["z"]) };
+    verify_decryption(&n, &g, &ct, &proof)
+}
+
+#[test]
+fn balance_proof_verifies_against_the_public_key() {
+    let server = Server::start(&[]);
+    server.credit("alice", 64);
+    let pubkey = server.get("/pubkey").send().json();
+
+    assert_eq!(server.get("/balance/proof/alice").send().status, 401);
+    let response = server.get("/balance/proof/alice").admin().send();
+    assert_eq!(response.status, 200, "{}", response.text());
+    let body = response.json();
+    assert_eq!(body["balance"], 64);
+    assert_eq!(body["c"], server.get("/net/alice").send().json()["c"]);
+    assert!(verifies(&pubkey, &body, 64));
+    assert!(!verifies(&pubkey, &body, 65));
+}
+
+#[test]
+fn submit_takes_only_ciphertexts_with_a_valid_proof() {
+    let server = Server::start(&[]);
+    // `prove_ciphertext` takes a whole key but uses only `n` and `g`, so
+    // reading the server's key file stands in for a client's public key
+    let key = PaillierKey::from_json(&std::fs::read_to_string(server.dir().join("key.json")).unwrap()).unwrap();
+    let (m, r) = (BigUint::from(31u32), BigUint::from(1_234_567u32));
+    let ct = encrypt_with_randomness(&key, &m, &r);
+    let proof = prove_ciphertext(&m, &r, &key);
+    let body = |z1: &BigUint| json!({
+        "wallet": "alice",
+        "c":      ct.c.to_string(),
+        "proof":  { "a": proof.a.to_string(), "z1": z1.to_string(), "z2": proof.z2.to_string() },
+    });
+
+    let forged = server.post("/submit").json(body(&(&proof.z1 + 1u32))).send();
+    assert_eq!((forged.status, forged.code()), (400, "INVALID_PROOF".to_string()));
+    assert_eq!(server.get("/net/alice").send().status, 404);
+
+    let accepted = server.post("/submit").json(body(&proof.z1)).send();
+    assert_eq!(accepted.status, 200, "{}", accepted.text());
+    assert_eq!(server.balance("alice"), 31);
+}
+
+#[test]
+fn verified_net_proves_the_balance_it_returns() {
+    let plain = Server::start(&[]);
+    plain.credit("alice", 1);
+    assert_eq!(plain.get("/net/alice/verified").send().status, 404);
+
+    let server = Server::start(&["--verified-net"]);
+    assert!(server.log().contains("/net/{wallet}/verified is mounted"));
+    server.credit("alice", 12);
+    let pubkey = server.get("/pubkey").send().json();
+
+    let body = server.get("/net/alice/verified").send().json();
+    assert_eq!(body["balance"], 12);
+    assert_eq!(body["c"], server.get("/net/alice").send().json()["c"]);
+    assert!(verifies(&pubkey, &body, 12));
+    assert!(!verifies(&pubkey, &body, 13));
+    assert_eq!(server.get("/net/nobody/verified").send().status, 404);
+}
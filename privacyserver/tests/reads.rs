@@ -0,0 +1,92 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use common::Server;
+
+/// Helper: the `index` of each entry in a `/history` page
+fn indexes(page: &Value) -> Vec<u64> {
+    page.as_array().unwrap().iter().map(|e| e["index"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn history_pages_oldest_first() {
+    let server = Server::start(&[]);
+    let mut cs = Vec::new();
+    for amount in 1..=5 {
+        cs.push(server.credit("alice", amount).json()["c"].clone());
+    }
+
+    let all = server.get("/history/alice").send().json();
+    assert_eq!(indexes(&all), [0, 1, 2, 3, 4]);
+    assert_eq!(all.as_array().unwrap().iter().map(|e| e["c"].clone()).collect::<Vec<_>>(), cs);
+
+    assert_eq!(indexes(&server.get("/history/alice?offset=1&limit=2").send().json()), [1, 2]);
+    assert_eq!(indexes(&server.get("/history/alice?offset=4&limit=50").send().json()), [4]);
+    assert_eq!(server.get("/history/alice?offset=9").send().json(), json!([]));
+    assert_eq!(server.get("/history/nobody").send().json(), json!([]));
+    assert_eq!(server.get("/history/alice?limit=-1").send().status, 400);
+}
+
+#[test]
+fn net_all_lists_every_wallet_sorted() {
+    let server = Server::start(&[]);
+    server.credit("carol", 3);
+    server.credit("alice", 1);
+    server.credit("bob", 2);
+    server.post("/debit").json(json!({ "wallet": "carol", "amount": 1 })).send();
+
+    let all = server.get("/net/all").send().json();
+    let wallets: Vec<_> = all.as_array().unwrap().iter().map(|e| e["wallet"].clone()).collect();
+    assert_eq!(wallets, [json!("alice"), json!("bob"), json!("carol")]);
+    assert_eq!(all[2]["c"], server.get("/net/carol").send().json()["c"]);
+    assert!(all[0].get("balance").is_none());
+
+    assert_eq!(server.get("/net/all?decrypt=true").send().status, 401);
+    let decrypted = server.get("/net/all?decrypt=true").admin().send().json();
+    let balances: Vec<_> = decrypted.as_array().unwrap().iter().map(|e| e["balance"].clone()).collect();
+    assert_eq!(balances, [json!(1), json!(2), json!(2)]);
+}
+
+#[test]
+fn refresh_on_read_rerandomizes_the_balance() {
+    let server = Server::start(&["--refresh-on-read", "--refresh-interval", "0"]);
+    server.credit("alice", 33);
+    let first = server.get("/net/alice").send().json()["c"].clone();
+    let second = server.get("/net/alice").send().json()["c"].clone();
+    assert_ne!(first, second);
+    for c in [&first, &second] {
+        let plaintext = server.post("/decrypt-ciphertext").admin().json(json!({ "c": c })).send().json();
+        assert_eq!(plaintext["plaintext"], 33);
+    }
+    // each refresh is persisted as the wallet's new latest entry
+    assert_eq!(server.get("/history/alice").send().json().as_array().unwrap().last().unwrap()["c"], second);
+
+    // within the interval, reads share one ciphertext
+    let throttled = Server::start(&["--refresh-on-read", "--refresh-interval", "3600"]);
+    throttled.credit("alice", 33);
+    let refreshed = throttled.get("/net/alice").send().json()["c"].clone();
+    assert_eq!(throttled.get("/net/alice").send().json()["c"], refreshed);
+    assert_eq!(throttled.get("/history/alice").send().json().as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn net_etags_answer_unchanged_reads_with_304() {
+    let server = Server::start(&[]);
+    server.credit("alice", 5);
+    let first = server.get("/net/alice").send();
+    let etag = first.header("etag").unwrap().to_string();
+    assert!(etag.starts_with("W/\""), "{etag}");
+
+    let cached = server.get("/net/alice").header("If-None-Match", &etag).send();
+    assert_eq!(cached.status, 304);
+    assert!(cached.body.is_empty());
+    assert_eq!(cached.header("etag"), Some(etag.as_str()));
+    assert_eq!(server.get("/net/alice").header("If-None-Match", "*").send().status, 304);
+
+    // a new entry changes the tag
+    server.credit("alice", 1);
+    let changed = server.get("/net/alice").header("If-None-Match", &etag).send();
+    assert_eq!(changed.status, 200);
+    assert_ne!(changed.header("etag"), Some(etag.as_str()));
+}
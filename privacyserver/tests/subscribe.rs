@@ -0,0 +1,25 @@
+mod common;
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use common::Server;
+
+#[test]
+fn subscribers_receive_their_wallets_new_ciphertexts() {
+    let server = Server::start(&[]);
+    let mut alice = server.websocket("/subscribe/alice");
+    let mut alice_eur = server.websocket("/subscribe/alice/EUR");
+
+    server.credit("bob", 1);
+    let credit = server.credit("alice", 10).json();
+    assert_eq!(alice.recv_text(Duration::from_secs(5)), Some(credit["c"].as_str().unwrap().to_string()));
+
+    let transfer = server.post("/transfer").json(json!({ "from": "alice", "to": "bob", "amount": 4 })).send().json();
+    assert_eq!(alice.recv_text(Duration::from_secs(5)).as_deref(), transfer["from"]["c"].as_str());
+
+    // bob's and USD's updates never reached the EUR subscriber
+    assert_eq!(alice_eur.recv_text(Duration::from_millis(300)), None);
+    assert_eq!(alice.recv_text(Duration::from_millis(300)), None);
+}
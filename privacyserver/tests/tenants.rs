@@ -0,0 +1,86 @@
+mod common;
+
+use serde_json::json;
+
+use common::{Response, Server};
+
+/// Create tenant `name`, returning its admin token
+fn create(server: &Server, name: &str) -> String {
+    let response = server.post("/admin/tenants").admin().json(json!({ "name": name })).send();
+    assert_eq!(response.status, 200, "{}", response.text());
+    let body = response.json();
+    assert_eq!(body["tenant"], name);
+    body["admin_token"].as_str().unwrap().to_string()
+}
+
+fn credit(server: &Server, tenant: &str, wallet: &str, amount: i64) -> Response {
+    server.post(&format!("/{tenant}/credit")).json(json!({ "wallet": wallet, "amount": amount })).send()
+}
+
+#[test]
+fn tenants_see_only_their_own_wallets() {
+    let mut server = Server::start(&["--tenants-dir", "tenants"]);
+    let acme = create(&server, "acme");
+    create(&server, "globex");
+
+    assert_eq!(credit(&server, "acme", "alice", 50).status, 200);
+    server.credit("alice", 7);
+    assert_eq!(server.balance("alice"), 7);
+    let acme_balance = server.post("/acme/decrypt/alice").bearer(&acme).send();
+    assert_eq!(acme_balance.json()["balance"], 50);
+
+    // globex has no alice, and acme holds only its own credit to her
+    let globex = server.get("/globex/net/alice").send();
+    assert_eq!((globex.status, globex.code()), (404, "WALLET_NOT_FOUND".to_string()));
+    assert_eq!(server.get("/acme/history/alice").send().json().as_array().unwrap().len(), 1);
+
+    // every tenant has a key of its own
+    let n = |path: &str| server.get(path).send().json()["n"].clone();
+    assert_ne!(n("/acme/pubkey"), n("/pubkey"));
+    assert_ne!(n("/acme/pubkey"), n("/globex/pubkey"));
+
+    // and its wallets survive a restart
+    server.restart();
+    let reopened = server.post("/acme/decrypt/alice").bearer(&acme).send();
+    assert_eq!(reopened.json()["balance"], 50);
+    assert_eq!(server.balance("alice"), 7);
+}
+
+#[test]
+fn admin_tokens_are_per_tenant() {
+    let server = Server::start(&["--tenants-dir", "tenants"]);
+    let acme = create(&server, "acme");
+    let globex = create(&server, "globex");
+    credit(&server, "acme", "alice", 5);
+    server.credit("alice", 5);
+
+    assert_eq!(server.post("/acme/decrypt/alice").bearer(&acme).send().status, 200);
+    assert_eq!(server.post("/acme/decrypt/alice").admin().send().status, 401);
+    assert_eq!(server.post("/acme/decrypt/alice").bearer(&globex).send().status, 401);
+    assert_eq!(server.post("/decrypt/alice").bearer(&acme).send().status, 401);
+
+    // only the default tenant's token creates tenants
+    let by_tenant = server.post("/admin/tenants").bearer(&acme).json(json!({ "name": "initech" })).send();
+    assert_eq!(by_tenant.status, 401);
+}
+
+#[test]
+fn tenants_must_be_created_first() {
+    let server = Server::start(&["--tenants-dir", "tenants"]);
+    let missing = credit(&server, "acme", "alice", 5);
+    assert_eq!((missing.status, missing.code()), (404, "TENANT_NOT_FOUND".to_string()));
+    assert!(!server.dir().join("tenants/acme").exists());
+
+    create(&server, "acme");
+    let again = server.post("/admin/tenants").admin().json(json!({ "name": "acme" })).send();
+    assert_eq!((again.status, again.code()), (409, "TENANT_EXISTS".to_string()));
+    for name in ["credit", "has space", ""] {
+        let invalid = server.post("/admin/tenants").admin().json(json!({ "name": name })).send();
+        assert_eq!((invalid.status, invalid.code()), (400, "INVALID_TENANT".to_string()), "{name:?}");
+    }
+    assert_eq!(server.post("/admin/tenants").json(json!({ "name": "globex" })).send().status, 401);
+
+    // without --tenants-dir there are no tenants at all
+    let plain = Server::start(&[]);
+    assert_eq!(plain.post("/admin/tenants").admin().json(json!({ "name": "acme" })).send().status, 404);
+}
@@ -0,0 +1,398 @@
+mod common;
+
+use num_bigint::BigUint;
+use serde_json::json;
+
+use common::Server;
+
+#[test]
+fn debit_subtracts_from_the_credited_balance() {
+    let server = Server::start(&[]);
+    assert_eq!(server.credit("alice", 100).status, 200);
+    let debit = server.post("/debit").json(json!({ "wallet": "alice", "amount": 40 })).send();
+    assert_eq!(debit.status, 200, "{}", debit.text());
+    assert_eq!(server.balance("alice"), 60);
+}
+
+#[test]
+fn balances_survive_a_crash() {
+    let mut server = Server::start(&[]);
+    server.credit("alice", 100);
+    server.post("/debit").json(json!({ "wallet": "alice", "amount": 30 })).send();
+    let before = server.get("/net/alice").send().json();
+
+    // every write is on disk before it's acknowledged, so even a kill
+    // loses nothing, and the persisted key still decrypts it
+    server.crash_and_restart();
+    assert_eq!(server.get("/net/alice").send().json(), before);
+    assert_eq!(server.balance("alice"), 70);
+    let ledger = std::fs::read_to_string(server.dir().join("ledger.jsonl")).unwrap();
+    assert_eq!(ledger.lines().count(), 2);
+}
+
+#[test]
+fn balances_survive_a_graceful_shutdown() {
+    let mut server = Server::start(&[]);
+    server.credit("alice", 25);
+    let before = server.get("/net/alice").send().json();
+
+    server.restart();
+    assert!(server.log().contains("ledger flushed; shut down cleanly"));
+    assert_eq!(server.get("/net/alice").send().json(), before);
+    assert_eq!(server.balance("alice"), 25);
+}
+
+#[test]
+fn overdrawn_balance_reads_negative() {
+    let server = Server::start(&[]);
+    server.credit("alice", 50);
+    // `/transfer` doesn't check the sender's balance, so it can overdraw
+    let transfer = server.post("/transfer").json(json!({ "from": "alice", "to": "bob", "amount": 80 })).send();
+    assert_eq!(transfer.status, 200, "{}", transfer.text());
+    assert_eq!(server.balance("alice"), -30);
+    assert_eq!(server.balance("bob"), 80);
+
+    let simulated = server.post("/simulate/carol").admin()
+        .json(json!([{ "op": "credit", "amount": 50 }, { "op": "debit", "amount": 80 }]))
+        .send();
+    assert_eq!(simulated.json()["balance"], -30);
+}
+
+#[test]
+fn transfer_debits_one_wallet_and_credits_the_other() {
+    let server = Server::start(&[]);
+    server.credit("alice", 100);
+    let transfer = server.post("/transfer").json(json!({ "from": "alice", "to": "bob", "amount": 25 })).send();
+    assert_eq!(transfer.status, 200, "{}", transfer.text());
+    let body = transfer.json();
+    assert_eq!((&body["from"]["wallet"], &body["to"]["wallet"]), (&json!("alice"), &json!("bob")));
+    assert_eq!(server.get("/net/alice").send().json()["c"], body["from"]["c"]);
+    assert_eq!((server.balance("alice"), server.balance("bob")), (json!(75), json!(25)));
+
+    let to_self = server.post("/transfer").json(json!({ "from": "alice", "to": "alice", "amount": 1 })).send();
+    assert_eq!(to_self.status, 400);
+}
+
+#[test]
+fn transfer_rejects_overflowing_the_recipient() {
+    let server = Server::start(&[]);
+    let n: BigUint = server.get("/pubkey").send().json()["n"].as_str().unwrap().parse().unwrap();
+    let max = (n >> 1u32) - 1u32;
+    let filled = server.post("/credit").json(json!({ "wallet": "bob", "amount": max.to_string() })).send();
+    assert_eq!(filled.status, 200, "{}", filled.text());
+    server.credit("alice", 10);
+
+    let transfer = server.post("/transfer").json(json!({ "from": "alice", "to": "bob", "amount": 1 })).send();
+    assert_eq!((transfer.status, transfer.code()), (400, "PLAINTEXT_OVERFLOW".to_string()));
+    // neither side moved
+    assert_eq!(server.balance("alice"), 10);
+    assert_eq!(server.balance("bob"), json!(max.to_string()));
+}
+
+#[test]
+fn batch_credit_appends_one_record_for_the_sum() {
+    let server = Server::start(&[]);
+    server.credit("alice", 1);
+    let batch = server.post("/credit/batch").json(json!({ "wallet": "alice", "amounts": [100, 250, 5] })).send();
+    assert_eq!(batch.status, 200, "{}", batch.text());
+    assert_eq!(batch.json()["seq"], 1);
+    assert_eq!(server.balance("alice"), 356);
+    let history = server.get("/history/alice").send().json();
+    assert_eq!(history.as_array().unwrap().len(), 2);
+
+    let empty = server.post("/credit/batch").json(json!({ "wallet": "alice", "amounts": [] })).send();
+    assert_eq!((empty.status, empty.code()), (400, "EMPTY_BATCH".to_string()));
+}
+
+#[test]
+fn oversized_batches_are_rejected_before_any_change() {
+    let server = Server::start(&["--max-batch-len", "3", "--max-body-bytes", "256"]);
+    server.credit("alice", 1);
+
+    let long = server.post("/credit/batch").json(json!({ "wallet": "alice", "amounts": [1, 2, 3, 4] })).send();
+    assert_eq!((long.status, long.code()), (400, "BATCH_TOO_LARGE".to_string()));
+    let padding = "x".repeat(300);
+    let big = server.post("/credit").json(json!({ "wallet": "alice", "amount": 1, "memo": padding })).send();
+    assert_eq!((big.status, big.code()), (413, "PAYLOAD_TOO_LARGE".to_string()));
+
+    assert_eq!(server.get("/history/alice").send().json().as_array().unwrap().len(), 1);
+    let short = server.post("/credit/batch").json(json!({ "wallet": "alice", "amounts": [1, 2, 3] })).send();
+    assert_eq!(short.status, 200);
+    assert_eq!(server.balance("alice"), 7);
+}
+
+#[test]
+fn overdraft_is_a_409_that_changes_nothing() {
+    let server = Server::start(&[]);
+    server.credit("alice", 10);
+    let before = server.get("/history/alice").send().json();
+
+    let debit = server.post("/debit").json(json!({ "wallet": "alice", "amount": 40 })).send();
+    assert_eq!(debit.status, 409);
+    assert_eq!(debit.code(), "INSUFFICIENT_FUNDS");
+    assert_eq!(debit.json()["details"], json!({ "attempted": 40, "available": 10 }));
+    assert_eq!(server.get("/history/alice").send().json(), before);
+    assert_eq!(server.balance("alice"), 10);
+}
+
+#[test]
+fn concurrent_writes_to_two_wallets_all_land() {
+    let server = Server::start(&[]);
+    server.credit("alice", 1000);
+    server.credit("bob", 1000);
+    std::thread::scope(|scope| {
+        for t in 0..8 {
+            let server = &server;
+            scope.spawn(move || {
+                let (from, to) = if t % 2 == 0 { ("alice", "bob") } else { ("bob", "alice") };
+                for _ in 0..10 {
+                    assert_eq!(server.credit(from, 3).status, 200);
+                    // opposite-direction transfers lock the same pair; they mustn't deadlock
+                    let transfer = server.post("/transfer").json(json!({ "from": from, "to": to, "amount": 1 })).send();
+                    assert_eq!(transfer.status, 200, "{}", transfer.text());
+                }
+            });
+        }
+    });
+    // each wallet: 1000, +3 × 40 credits, and as many transfers out as in
+    assert_eq!((server.balance("alice"), server.balance("bob")), (json!(1120), json!(1120)));
+    assert_eq!(server.get("/history/alice?limit=500").send().json().as_array().unwrap().len(), 1 + 40 + 80);
+}
+
+#[test]
+fn credits_stop_at_the_largest_balance() {
+    let server = Server::start(&[]);
+    let n: BigUint = server.get("/pubkey").send().json()["n"].as_str().unwrap().parse().unwrap();
+    let max = (n >> 1u32) - 1u32;
+    let credit = |amount: String| server.post("/credit").json(json!({ "wallet": "alice", "amount": amount })).send();
+
+    assert_eq!(credit((&max - 1u32).to_string()).status, 200);
+    assert_eq!(credit("1".into()).status, 200);
+    assert_eq!(server.balance("alice"), json!(max.to_string()));
+
+    let over = credit("1".into());
+    assert_eq!((over.status, over.code()), (400, "PLAINTEXT_OVERFLOW".to_string()));
+    assert_eq!(over.json()["details"]["headroom"], 0);
+    let batch = server.post("/credit/batch").json(json!({ "wallet": "alice", "amounts": [1] })).send();
+    assert_eq!((batch.status, batch.code()), (400, "PLAINTEXT_OVERFLOW".to_string()));
+    assert_eq!(server.balance("alice"), json!(max.to_string()));
+}
+
+#[test]
+fn currencies_keep_separate_balances() {
+    let server = Server::start(&[]);
+    server.credit("alice", 100);
+    server.post("/credit").json(json!({ "wallet": "alice", "amount": 7, "currency": "EUR" })).send();
+    server.post("/debit").json(json!({ "wallet": "alice", "amount": 2, "currency": "EUR" })).send();
+
+    assert_eq!(server.balance("alice"), 100);
+    let eur = server.post("/decrypt/alice/EUR").admin().send().json();
+    assert_eq!(eur, json!({ "wallet": "alice", "currency": "EUR", "balance": 5 }));
+    // a USD debit can't draw on the EUR balance
+    let debit = server.post("/debit").json(json!({ "wallet": "alice", "amount": 103 })).send();
+    assert_eq!(debit.status, 409);
+    assert_eq!(server.get("/net/alice/EUR").send().json()["currency"], "EUR");
+    assert_eq!(server.get("/net/alice/GBP").send().status, 404);
+}
+
+#[test]
+fn idempotent_retries_apply_once() {
+    let server = Server::start(&[]);
+    let credit = || {
+        server.post("/credit").header("Idempotency-Key", "retry-1")
+              .json(json!({ "wallet": "alice", "amount": 40 })).send()
+    };
+    let first = credit();
+    let retry = credit();
+    assert_eq!(first.status, 200);
+    assert_eq!(retry.json(), first.json());
+    assert_eq!(server.balance("alice"), 40);
+    assert_eq!(server.get("/history/alice").send().json().as_array().unwrap().len(), 1);
+
+    // the same key on another operation is a client bug, not a replay
+    let reused = server.post("/debit").header("Idempotency-Key", "retry-1")
+                       .json(json!({ "wallet": "alice", "amount": 40 })).send();
+    assert_eq!((reused.status, reused.code()), (422, "IDEMPOTENCY_KEY_REUSED".to_string()));
+    assert_eq!(server.balance("alice"), 40);
+}
+
+#[test]
+fn eth_addresses_are_case_insensitive_and_checked() {
+    let server = Server::start(&["--wallet-format", "eth"]);
+    let lower = "0xabcdef0123456789abcdef0123456789abcdef01";
+    let mixed = "0xAbCdEf0123456789ABCDEF0123456789abcdef01";
+    server.credit(mixed, 10);
+    server.credit(lower, 5);
+    assert_eq!(server.balance(lower), 15);
+    assert_eq!(server.get(&format!("/net/{mixed}")).send().json()["wallet"], lower);
+
+    for bad in ["alice", "0x1234", "abcdef0123456789abcdef0123456789abcdef01", "0xzzcdef0123456789abcdef0123456789abcdef01"] {
+        let response = server.post("/credit").json(json!({ "wallet": bad, "amount": 1 })).send();
+        assert_eq!((response.status, response.code()), (400, "INVALID_WALLET".to_string()), "{bad}");
+    }
+    assert_eq!(server.get("/net/0x1234").send().code(), "INVALID_WALLET");
+}
+
+#[test]
+fn amounts_beyond_u64_round_trip_as_strings() {
+    let server = Server::start(&[]);
+    let wei = "1000000000000000000000";
+    let credit = server.post("/credit").json(json!({ "wallet": "alice", "amount": wei })).send();
+    assert_eq!(credit.status, 200, "{}", credit.text());
+    server.post("/credit").json(json!({ "wallet": "alice", "amount": 1 })).send();
+    assert_eq!(server.balance("alice"), json!("1000000000000000000001"));
+
+    server.post("/debit").json(json!({ "wallet": "alice", "amount": "999999999999999999999" })).send();
+    assert_eq!(server.balance("alice"), 2);
+}
+
+#[test]
+fn holds_capture_or_release_reserved_funds() {
+    let server = Server::start(&[]);
+    server.credit("alice", 100);
+    let step = |path: &str, amount: i64| server.post(path).json(json!({ "wallet": "alice", "amount": amount })).send();
+
+    assert_eq!(step("/hold", 40).status, 200);
+    assert_eq!(server.balance("alice"), 60);
+    // held funds can't be debited
+    assert_eq!(step("/debit", 70).status, 409);
+    assert_eq!(step("/hold", 61).status, 409);
+
+    // hold → capture: the captured part is gone
+    assert_eq!(step("/capture", 30).status, 200);
+    assert_eq!(server.balance("alice"), 60);
+    // hold → release: the rest comes back
+    assert_eq!(step("/release", 10).status, 200);
+    assert_eq!(server.balance("alice"), 70);
+    let overdrawn = step("/release", 1);
+    assert_eq!((overdrawn.status, overdrawn.code()), (409, "INSUFFICIENT_HELD".to_string()));
+    assert_eq!(step("/capture", 1).status, 409);
+    assert_eq!(server.balance("alice"), 70);
+}
+
+#[test]
+fn dry_runs_record_nothing() {
+    let server = Server::start(&[]);
+    server.credit("alice", 50);
+    let ledger_len = || std::fs::read_to_string(server.dir().join("ledger.jsonl")).unwrap().lines().count();
+    let before = server.get("/net/alice").send().json();
+
+    let credit = server.post("/credit?dry_run=true").admin().json(json!({ "wallet": "alice", "amount": 25 })).send();
+    assert_eq!(credit.json()["balance"], 75);
+    assert_ne!(credit.json()["c"], before["c"]);
+    let debit = server.post("/debit?dry_run=true").json(json!({ "wallet": "alice", "amount": 20 })).send();
+    assert_eq!(debit.status, 200);
+    assert!(debit.json().get("balance").is_none());
+    let overdraft = server.post("/debit?dry_run=true").json(json!({ "wallet": "alice", "amount": 51 })).send();
+    assert_eq!(overdraft.status, 409);
+    // previews don't create wallets either
+    server.post("/credit?dry_run=true").json(json!({ "wallet": "bob", "amount": 1 })).send();
+
+    assert_eq!(ledger_len(), 1);
+    assert_eq!(server.get("/net/alice").send().json(), before);
+    assert_eq!(server.get("/net/bob").send().status, 404);
+}
+
+#[test]
+fn responses_carry_the_wallets_sequence_number() {
+    let server = Server::start(&[]);
+    assert_eq!(server.credit("alice", 1).json()["seq"], 0);
+    assert_eq!(server.credit("alice", 1).json()["seq"], 1);
+    // per wallet, and shared by every kind of entry
+    assert_eq!(server.credit("bob", 1).json()["seq"], 0);
+    assert_eq!(server.post("/debit").json(json!({ "wallet": "alice", "amount": 1 })).send().json()["seq"], 2);
+    let transfer = server.post("/transfer").json(json!({ "from": "alice", "to": "bob", "amount": 1 })).send().json();
+    assert_eq!((transfer["from"]["seq"].clone(), transfer["to"]["seq"].clone()), (json!(3), json!(1)));
+    assert_eq!(server.get("/history/alice").send().json()[3]["c"], transfer["from"]["c"]);
+}
+
+#[test]
+fn spend_moves_funds_only_when_covered() {
+    let server = Server::start(&[]);
+    server.credit("alice", 100);
+    let spend = |amount: i64, exact: bool| {
+        server.post("/spend").json(json!({ "wallet": "alice", "to": "bob", "amount": amount, "exact": exact })).send()
+    };
+
+    let response = spend(30, false);
+    assert_eq!(response.status, 200, "{}", response.text());
+    let body = response.json();
+    assert_eq!(body["from"]["c"], server.get("/net/alice").send().json()["c"]);
+    assert_eq!(body["to"]["c"], server.get("/net/bob").send().json()["c"]);
+    assert_eq!((server.balance("alice"), server.balance("bob")), (json!(70), json!(30)));
+
+    let overdraft = spend(71, false);
+    assert_eq!((overdraft.status, overdraft.code()), (409, "INSUFFICIENT_FUNDS".to_string()));
+    assert_eq!(overdraft.json()["details"]["available"], 70);
+    let inexact = spend(69, true);
+    assert_eq!((inexact.status, inexact.code()), (409, "INEXACT_SPEND".to_string()));
+    assert_eq!((server.balance("alice"), server.balance("bob")), (json!(70), json!(30)));
+
+    assert_eq!(spend(70, true).status, 200);
+    assert_eq!((server.balance("alice"), server.balance("bob")), (json!(0), json!(100)));
+}
+
+#[test]
+fn max_wallets_caps_new_wallets_only() {
+    let mut server = Server::start(&["--max-wallets", "2"]);
+    server.credit("alice", 1);
+    server.credit("bob", 1);
+    let third = server.post("/credit").json(json!({ "wallet": "carol", "amount": 1 })).send();
+    assert_eq!((third.status, third.code()), (429, "TOO_MANY_WALLETS".to_string()));
+    let transfer = server.post("/transfer").json(json!({ "from": "alice", "to": "carol", "amount": 1 })).send();
+    assert_eq!(transfer.status, 429);
+    server.credit("alice", 1);
+    assert_eq!(server.balance("alice"), 2);
+
+    // the cap holds across restarts, counted from the loaded ledger
+    server.restart();
+    assert_eq!(server.post("/credit").json(json!({ "wallet": "carol", "amount": 1 })).send().status, 429);
+}
+
+#[test]
+fn refused_requests_leave_the_wallet_cap_alone() {
+    let server = Server::start(&["--max-wallets", "2"]);
+    server.credit("alice", 1);
+    // nothing is written for an overdraft or a hold of funds that aren't
+    // there, so the new wallets aren't added
+    let refusals = [
+        ("/debit", "mallory", "INSUFFICIENT_FUNDS"),
+        ("/hold", "trudy", "INSUFFICIENT_FUNDS"),
+        ("/release", "eve", "INSUFFICIENT_HELD"),
+    ];
+    for (path, wallet, code) in refusals {
+        let refused = server.post(path).json(json!({ "wallet": wallet, "amount": 5 })).send();
+        assert_eq!((refused.status, refused.code()), (409, code.to_string()), "{path}");
+    }
+    assert_eq!(server.credit("bob", 1).status, 200);
+    assert_eq!(server.credit("carol", 1).status, 429);
+}
+
+#[test]
+fn simulate_folds_the_steps_without_recording_them() {
+    let server = Server::start(&[]);
+    server.credit("alice", 100);
+    let net = server.get("/net/alice").send().json();
+    let history = server.get("/history/alice").send().json();
+    let steps = json!([{ "op": "credit", "amount": 50 }, { "op": "debit", "amount": 20 }]);
+
+    let simulated = server.post("/simulate/alice").admin().json(steps.clone()).send();
+    assert_eq!(simulated.status, 200, "{}", simulated.text());
+    let body = simulated.json();
+    assert_eq!(body["balance"], 130);
+    assert_ne!(body["c"], net["c"]);
+    let decrypted = server.post("/decrypt-ciphertext").admin().json(json!({ "c": body["c"] })).send();
+    assert_eq!(decrypted.json(), json!({ "plaintext": 130 }));
+
+    // without the admin token only the ciphertext comes back
+    let anonymous = server.post("/simulate/alice").json(steps.clone()).send().json();
+    assert!(anonymous.get("balance").is_none());
+    assert!(anonymous["c"].is_string());
+
+    // neither call touched the ledger, nor did one for a new wallet
+    server.post("/simulate/dave").json(steps).send();
+    assert_eq!(server.get("/net/alice").send().json(), net);
+    assert_eq!(server.get("/history/alice").send().json(), history);
+    assert_eq!(server.balance("alice"), 100);
+    assert_eq!(server.get("/net/dave").send().status, 404);
+}
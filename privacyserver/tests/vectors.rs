@@ -0,0 +1,84 @@
+use num_bigint::BigUint;
+use serde::Deserialize;
+
+use privacyserver::paillier::{
+    decrypt,
+    decrypt_crt,
+    encrypt_with_randomness,
+    homomorphic_addition,
+    PaillierKey,
+};
+
+/// The same known-answer vectors `privacyserver selftest` checks
+const VECTORS: &str = include_str!("../vectors/paillier.json");
+
+/// One key, in the `PaillierKey::to_json` form, and its vectors
+#[derive(Deserialize)]
+struct VectorSet {
+    key:     serde_json::Value,
+    vectors: Vec<Vector>,
+}
+
+/// `m` encrypted with randomness `r` is `c`; all decimal strings
+#[derive(Deserialize)]
+struct Vector {
+    m: String,
+    r: String,
+    c: String,
+}
+
+fn parse(v: &str) -> BigUint {
+    v.parse().expect("vector values are decimal integers")
+}
+
+fn sets() -> Vec<(PaillierKey, Vec<Vector>)> {
+    let sets: Vec<VectorSet> = serde_json::from_str(VECTORS).expect("the vectors file is valid");
+    sets.into_iter()
+        .map(|set| (PaillierKey::from_json(&set.key.to_string()).expect("vector keys load"), set.vectors))
+        .collect()
+}
+
+#[test]
+fn vectors_cover_small_and_near_n_plaintexts() {
+    for (key, vectors) in sets() {
+        let plaintexts: Vec<BigUint> = vectors.iter().map(|v| parse(&v.m)).collect();
+        assert!(vectors.len() >= 3);
+        assert!(plaintexts.contains(&BigUint::from(0u8)));
+        assert!(plaintexts.contains(&(&key.n - 1u8)), "no vector for n - 1 under n = {}", key.n);
+    }
+}
+
+#[test]
+fn encryption_reproduces_every_vector() {
+    for (key, vectors) in sets() {
+        for v in &vectors {
+            let ct = encrypt_with_randomness(&key, &parse(&v.m), &parse(&v.r));
+            assert_eq!(ct.c, parse(&v.c), "m = {} under n = {}", v.m, key.n);
+        }
+    }
+}
+
+#[test]
+fn decryption_recovers_every_vector() {
+    for (key, vectors) in sets() {
+        for v in &vectors {
+            let ct = encrypt_with_randomness(&key, &parse(&v.m), &parse(&v.r));
+            assert_eq!(ct.c, parse(&v.c));
+            assert_eq!(decrypt(&key, &ct).unwrap(), parse(&v.m), "m = {} under n = {}", v.m, key.n);
+            assert_eq!(decrypt_crt(&key, &ct).unwrap(), parse(&v.m), "m = {} under n = {}", v.m, key.n);
+        }
+    }
+}
+
+#[test]
+fn vector_ciphertexts_add_mod_n() {
+    for (key, vectors) in sets() {
+        for pair in vectors.windows(2) {
+            let [a, b] = pair else { unreachable!() };
+            let a_ct = encrypt_with_randomness(&key, &parse(&a.m), &parse(&a.r));
+            let b_ct = encrypt_with_randomness(&key, &parse(&b.m), &parse(&b.r));
+            let sum = homomorphic_addition(&a_ct, &b_ct).unwrap();
+            assert_eq!(decrypt(&key, &sum).unwrap(), (parse(&a.m) + parse(&b.m)) % &key.n);
+        }
+    }
+}